@@ -0,0 +1,128 @@
+#![feature(test)]
+extern crate test;
+
+use shreddr::index::document_repository::DocumentData;
+use shreddr::metadata::tag::{from_matcher_config, MatcherConfig, TagConfig, TagId, Tagger};
+use test::Bencher;
+
+const WORDS: [&str; 16] = [
+    "Rechnung",
+    "invoice",
+    "Vertrag",
+    "contract",
+    "Telekom",
+    "Stadtwerke",
+    "insurance",
+    "Versicherung",
+    "Kontoauszug",
+    "statement",
+    "Miete",
+    "rent",
+    "Steuer",
+    "tax",
+    "Arzt",
+    "doctor",
+];
+
+/// Synthetic corpus of documents and regex based tags
+fn corpus(tags: usize, docs: usize) -> (tempfile::TempDir, Tagger, Vec<DocumentData>) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut tagger = Tagger::new(dir.path(), false).unwrap();
+    for i in 0..tags {
+        let word = WORDS[i % WORDS.len()];
+        let matcher = match i % 3 {
+            0 => MatcherConfig::FullMatcher {
+                match_str: format!("{} {}", word, i),
+                case_insensitive: true,
+            },
+            1 => MatcherConfig::AnyMatcher {
+                match_str: format!("{}{},{}", word, i, WORDS[(i + 1) % WORDS.len()]),
+                case_insensitive: false,
+            },
+            _ => MatcherConfig::RegexMatcher {
+                match_str: format!(r"{}\s+\d{{{}}}", word, i % 4 + 1),
+            },
+        };
+        tagger
+            .add_tag(TagConfig {
+                id: 0,
+                name: format!("Tag {}", i),
+                color: None,
+                matcher,
+                parent: None,
+            })
+            .unwrap();
+    }
+    // Linear congruential generator for reproducible texts
+    let mut seed: usize = 42;
+    let documents = (0..docs)
+        .map(|id| {
+            let text: Vec<String> = (0..500)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    match (seed >> 33) % 4 {
+                        0 => ((seed >> 20) % 100).to_string(),
+                        _ => WORDS[(seed >> 40) % WORDS.len()].to_string(),
+                    }
+                })
+                .collect();
+            DocumentData {
+                id: id as u64,
+                original_filename: format!("{}.pdf", id),
+                title: format!("Document {}", id),
+                body: Some(text.join(" ")),
+                tags: vec![],
+                imported_date: chrono::Utc::now(),
+                hash: format!("hash{}", id),
+                file_size: 0,
+                language: None,
+                extracted: Default::default(),
+                provenance: Default::default(),
+                evidence: vec![],
+                correspondent: None,
+                document_type: None,
+                custom_fields: Default::default(),
+                file_metadata: Default::default(),
+            }
+        })
+        .collect();
+    (dir, tagger, documents)
+}
+
+/// Matches all tags like before the matchers were compiled once
+fn match_tags_uncompiled(tagger: &Tagger, doc: &DocumentData) -> Vec<TagId> {
+    let classifier = Default::default();
+    let mut ids: Vec<TagId> = tagger
+        .get_tags()
+        .iter()
+        .filter(|cfg| {
+            from_matcher_config(&cfg.matcher, cfg.id, &classifier)
+                .unwrap()
+                .match_document(doc)
+                .unwrap()
+        })
+        .map(|cfg| cfg.id)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[bench]
+fn bench_match_tags_compiled(b: &mut Bencher) {
+    let (_dir, tagger, docs) = corpus(50, 50);
+    b.iter(|| {
+        for doc in &docs {
+            test::black_box(tagger.match_tags(doc).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_match_tags_uncompiled(b: &mut Bencher) {
+    let (_dir, tagger, docs) = corpus(50, 50);
+    b.iter(|| {
+        for doc in &docs {
+            test::black_box(match_tags_uncompiled(&tagger, doc));
+        }
+    });
+}
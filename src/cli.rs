@@ -4,6 +4,12 @@ extern crate confy;
 extern crate serde_derive;
 use std::path::PathBuf;
 
+use crate::index::document_repository::postgres_repository::PostgresConfig;
+use crate::index::document_repository::{FilterOptions, SortOrder};
+use crate::index::JobType;
+use crate::metadata::tag::{DocumentField, FieldCondition, MatcherConfig};
+use crossbeam_channel::Sender;
+
 use shrust::{Shell, ShellIO};
 use std::io::prelude::*;
 
@@ -24,6 +30,30 @@ pub struct ShreddrConfig {
     pub max_upload_size: u64,
     #[serde(default)]
     pub extract_extended_metadata: bool,
+    /// Backend in which the document metadata is stored
+    #[serde(default)]
+    pub document_repository: DocumentRepositoryType,
+    /// Connection settings, if the document metadata is stored in PostgreSQL
+    #[serde(default)]
+    pub postgres: PostgresConfig,
+}
+
+/// Available backends for storing document metadata
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentRepositoryType {
+    /// YAML file together with a tantivy full text index
+    Local,
+    /// Embedded SQLite database
+    Sqlite,
+    /// External PostgreSQL database
+    Postgres,
+}
+
+impl Default for DocumentRepositoryType {
+    fn default() -> Self {
+        DocumentRepositoryType::Local
+    }
 }
 
 impl Default for ShreddrConfig {
@@ -35,6 +65,8 @@ impl Default for ShreddrConfig {
             tesseract_languages: vec![],
             max_upload_size: 20 * 1024 * 1024,
             extract_extended_metadata: true,
+            document_repository: DocumentRepositoryType::Local,
+            postgres: PostgresConfig::default(),
         }
     }
 }
@@ -92,8 +124,9 @@ fn load_config(cli_arguments: &clap::ArgMatches) -> Result<ShreddrConfig, CLIErr
     Ok(cfg)
 }
 
-/// Executes the Shell I/O
-pub fn run_shell(index: Arc<super::index::Index>) {
+/// Executes the Shell I/O.
+/// Long running jobs are sent to the job thread of the index.
+pub fn run_shell(index: Arc<super::index::Index>, sender: Sender<JobType>) {
     print!("{}", header());
     let mut shell = Shell::new(index);
 
@@ -157,64 +190,429 @@ pub fn run_shell(index: Arc<super::index::Index>) {
         Ok(())
     });
 
+    shell.new_command(
+        "fsck",
+        "Checks the repositories for inconsistencies",
+        0,
+        |io, index, _| {
+            let report = match index.fsck() {
+                Ok(r) => r,
+                Err(e) => {
+                    writeln!(io, "Error during check: {}", e)?;
+                    return Ok(());
+                }
+            };
+            writeln!(io, "Checked {} documents", report.checked_documents)?;
+            for issue in &report.issues {
+                writeln!(io, " - {}", issue)?;
+            }
+            if report.issues.is_empty() {
+                writeln!(io, "No issues found")?;
+                return Ok(());
+            }
+            let repair =
+                dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt(format!("Repair {} issues?", report.issues.len()))
+                    .interact()?;
+            if repair {
+                for issue in &report.issues {
+                    if let Err(e) = index.repair_issue(issue) {
+                        writeln!(io, "Could not repair `{}`: {}", issue, e)?;
+                    }
+                }
+                writeln!(io, "Repair finished")?;
+            }
+            Ok(())
+        },
+    );
+
+    let tag_sender = sender.clone();
     shell.new_command(
         "addtag",
         "Adds a tag with the given name",
         1,
+        move |io, index, s| {
+            let matcher = match prompt_matcher()? {
+                Some(m) => m,
+                None => {
+                    writeln!(io, "Invalid selection")?;
+                    return Ok(());
+                }
+            };
+            let parent = prompt_optional("Parent tag ID (empty for none)")?;
+            let tag = super::metadata::tag::TagConfig {
+                id: 0,
+                name: s[0].into(),
+                color: None,
+                matcher,
+                parent,
+            };
+            let auto = tag.matcher.uses_classifier();
+            let id = index.add_tag(tag)?;
+            // Apply the new matcher to all existing documents, training applies all automatic tags afterwards
+            let job = if auto {
+                JobType::TrainClassifier
+            } else {
+                JobType::Retag { tag: id }
+            };
+            match tag_sender.send(job) {
+                Ok(()) => writeln!(
+                    io,
+                    "Added tag {}, existing documents are tagged in the background",
+                    id
+                )?,
+                Err(e) => writeln!(io, "Could not queue tagging of documents: {}", e)?,
+            }
+            Ok(())
+        },
+    );
+
+    let remove_sender = sender.clone();
+    shell.new_command(
+        "removetag",
+        "Removes the tag with the given ID from all documents and deletes it",
+        1,
+        move |io, index, s| {
+            let tag = match s[0].parse::<u64>() {
+                Ok(tag) => tag,
+                Err(e) => {
+                    writeln!(io, "Could not parse number {}", e)?;
+                    return Ok(());
+                }
+            };
+            if index.get_tag(tag).is_none() {
+                writeln!(io, "Tag {} does not exist", tag)?;
+                return Ok(());
+            }
+            match remove_sender.send(JobType::RemoveTag { tag }) {
+                Ok(()) => writeln!(io, "Removal of tag {} queued", tag)?,
+                Err(e) => writeln!(io, "Could not queue removal: {}", e)?,
+            }
+            Ok(())
+        },
+    );
+
+    let merge_sender = sender.clone();
+    shell.new_command(
+        "mergetag",
+        "Moves all documents from the first tag to the second tag and deletes the first tag",
+        2,
+        move |io, index, s| {
+            let (from, into) = match (s[0].parse::<u64>(), s[1].parse::<u64>()) {
+                (Ok(from), Ok(into)) => (from, into),
+                (Err(e), _) | (_, Err(e)) => {
+                    writeln!(io, "Could not parse number {}", e)?;
+                    return Ok(());
+                }
+            };
+            if let Err(e) = index.check_merge(from, into) {
+                writeln!(io, "Cannot merge: {}", e)?;
+                return Ok(());
+            }
+            match merge_sender.send(JobType::MergeTags { from, into }) {
+                Ok(()) => writeln!(io, "Merge of tag {} into tag {} queued", from, into)?,
+                Err(e) => writeln!(io, "Could not queue merge: {}", e)?,
+            }
+            Ok(())
+        },
+    );
+
+    shell.new_command(
+        "renametag",
+        "Renames the tag with the given ID",
+        2,
         |io, index, s| {
-            let selections = &["Full Match", "Regex"];
-            let selection =
-                dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                    .with_prompt("What type of matcher should the tag use?")
-                    .default(0)
-                    .items(&selections[..])
-                    .interact()?;
-            let tag = match selection {
-                0 => {
-                    //Fullmatch
-                    let match_str: String =
-                        dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                            .with_prompt("Match String")
-                            .interact()?;
-                    let case_insensitive =
-                        dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                            .with_prompt("Case insensitive?")
-                            .interact()?;
-                    super::metadata::tag::TagConfig {
-                        id: 0,
-                        name: s[0].into(),
-                        color: None,
-                        matcher: super::metadata::tag::MatcherConfig::FullMatcher {
-                            match_str,
-                            case_insensitive,
-                        },
-                    }
+            match s[0].parse::<u64>() {
+                Ok(tag) => match index.rename_tag(tag, &s[1..].join(" ")) {
+                    Ok(()) => writeln!(io, "Renamed tag {}", tag)?,
+                    Err(e) => writeln!(io, "Error during rename: {}", e)?,
+                },
+                Err(e) => writeln!(io, "Could not parse number {}", e)?,
+            }
+            Ok(())
+        },
+    );
+
+    shell.new_command(
+        "train",
+        "Trains the classifier of automatic tags and applies them to all documents",
+        0,
+        move |io, _, _| {
+            match sender.send(JobType::TrainClassifier) {
+                Ok(()) => writeln!(io, "Training the classifier in the background")?,
+                Err(e) => writeln!(io, "Could not queue training: {}", e)?,
+            }
+            Ok(())
+        },
+    );
+
+    shell.new_command(
+        "previewtag",
+        "Shows which documents a matcher would tag, optionally compared to the tag with the given ID",
+        0,
+        |io, index, s| {
+            let tag = match s.first().map(|t| t.parse::<u64>()) {
+                Some(Ok(t)) => Some(t),
+                Some(Err(e)) => {
+                    writeln!(io, "Could not parse number {}", e)?;
+                    return Ok(());
                 }
-                1 => {
-                    //Regex Match
-                    let match_str: String =
-                        dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                            .with_prompt("Match String")
-                            .interact()?;
-                    super::metadata::tag::TagConfig {
-                        id: 0,
-                        name: s[0].into(),
-                        color: None,
-                        matcher: super::metadata::tag::MatcherConfig::RegexMatcher { match_str },
-                    }
+                None => None,
+            };
+            let matcher = match prompt_matcher()? {
+                Some(m) => m,
+                None => {
+                    writeln!(io, "Invalid selection")?;
+                    return Ok(());
                 }
-                x => {
-                    writeln!(io, "Invalid selection: {}", x)?;
+            };
+            let previews = match index.preview_matcher(&matcher, tag) {
+                Ok(p) => p,
+                Err(e) => {
+                    writeln!(io, "Error during preview: {}", e)?;
                     return Ok(());
                 }
             };
-            index.add_tag(tag)?;
+            for preview in &previews {
+                let change = match (&preview.change, preview.manual) {
+                    (_, true) => "manual",
+                    (crate::index::AssignmentChange::Added, _) => "+",
+                    (crate::index::AssignmentChange::Removed, _) => "-",
+                    (crate::index::AssignmentChange::Unchanged, _) => "=",
+                };
+                writeln!(io, "[{}] {} ({})", change, preview.title, preview.id)?;
+                for snippet in &preview.snippets {
+                    writeln!(io, "      {}", snippet)?;
+                }
+            }
+            writeln!(
+                io,
+                "{} documents match",
+                previews.iter().filter(|p| p.matches).count()
+            )?;
+            Ok(())
+        },
+    );
+
+    shell.new_command(
+        "export",
+        "Exports the metadata of all documents as CSV file to the given path",
+        1,
+        |io, index, s| {
+            let filter = FilterOptions {
+                sort: SortOrder::ImportedDate,
+                tags: vec![],
+                query: None,
+                correspondent: None,
+                document_type: None,
+                custom_fields: vec![],
+                amount: None,
+            };
+            match index.export_csv(filter) {
+                Ok(csv) => {
+                    std::fs::write(s[0], csv)?;
+                    writeln!(io, "Exported documents to {}", s[0])?;
+                }
+                Err(e) => writeln!(io, "Error during export: {}", e)?,
+            }
             Ok(())
         },
     );
     shell.run_loop(&mut ShellIO::default());
 }
 
+/// Asks the user for the type and configuration of a matcher
+fn prompt_matcher() -> Result<Option<MatcherConfig>, std::io::Error> {
+    let selections = &[
+        "Full Match",
+        "Any Match",
+        "Regex",
+        "All of (AND)",
+        "One of (OR)",
+        "Not",
+        "Metadata field",
+        "File size",
+        "Document date",
+        "Automatic (learned from documents tagged by hand)",
+        "Fuzzy Match (tolerates OCR errors)",
+    ];
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt("What type of matcher should the tag use?")
+        .default(0)
+        .items(&selections[..])
+        .interact()?;
+    let matcher = match selection {
+        0 | 1 => {
+            //Full and any match
+            let match_str: String =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt(match selection {
+                        0 => "Match String",
+                        _ => "Comma separated terms",
+                    })
+                    .interact()?;
+            let case_insensitive =
+                dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Case insensitive?")
+                    .interact()?;
+            match selection {
+                0 => MatcherConfig::FullMatcher {
+                    match_str,
+                    case_insensitive,
+                },
+                _ => MatcherConfig::AnyMatcher {
+                    match_str,
+                    case_insensitive,
+                },
+            }
+        }
+        2 => {
+            //Regex Match
+            let match_str: String =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Match String")
+                    .interact()?;
+            MatcherConfig::RegexMatcher { match_str }
+        }
+        3 | 4 => {
+            //Composite matchers
+            let count: usize =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Number of sub-matchers")
+                    .interact()?;
+            let mut matchers = vec![];
+            for _ in 0..count {
+                match prompt_matcher()? {
+                    Some(m) => matchers.push(m),
+                    None => return Ok(None),
+                }
+            }
+            match selection {
+                3 => MatcherConfig::And { matchers },
+                _ => MatcherConfig::Or { matchers },
+            }
+        }
+        5 => match prompt_matcher()? {
+            Some(m) => MatcherConfig::Not {
+                matcher: Box::new(m),
+            },
+            None => return Ok(None),
+        },
+        6 => {
+            //Field match
+            let fields = [
+                DocumentField::OriginalFilename,
+                DocumentField::Title,
+                DocumentField::Language,
+                DocumentField::Iban,
+                DocumentField::Email,
+                DocumentField::Phone,
+                DocumentField::Link,
+                DocumentField::Author,
+                DocumentField::Subject,
+                DocumentField::Keywords,
+            ];
+            let conditions = [
+                FieldCondition::Equals,
+                FieldCondition::StartsWith,
+                FieldCondition::EndsWith,
+                FieldCondition::Contains,
+                FieldCondition::Regex,
+            ];
+            let field = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt("Field")
+                .default(0)
+                .items(
+                    &fields
+                        .iter()
+                        .map(|f| format!("{:?}", f))
+                        .collect::<Vec<_>>(),
+                )
+                .interact()?;
+            let condition =
+                dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Condition")
+                    .default(0)
+                    .items(
+                        &conditions
+                            .iter()
+                            .map(|c| format!("{:?}", c))
+                            .collect::<Vec<_>>(),
+                    )
+                    .interact()?;
+            let value: String =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Value")
+                    .interact()?;
+            let case_insensitive =
+                dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Case insensitive?")
+                    .interact()?;
+            MatcherConfig::FieldMatcher {
+                field: fields[field],
+                condition: conditions[condition],
+                value,
+                case_insensitive,
+            }
+        }
+        7 => MatcherConfig::FileSizeMatcher {
+            min: prompt_optional("Minimum size in bytes (empty for none)")?,
+            max: prompt_optional("Maximum size in bytes (empty for none)")?,
+        },
+        8 => MatcherConfig::DateMatcher {
+            from: prompt_optional("From date YYYY-MM-DD (empty for none)")?,
+            to: prompt_optional("To date YYYY-MM-DD (empty for none)")?,
+        },
+        9 => {
+            let threshold: f64 =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Minimum confidence (0-1)")
+                    .default(0.9)
+                    .interact()?;
+            MatcherConfig::Auto { threshold }
+        }
+        10 => {
+            let match_str: String =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Match String")
+                    .interact()?;
+            let max_distance: usize =
+                dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt("Maximum edit distance")
+                    .default(1)
+                    .interact()?;
+            MatcherConfig::FuzzyMatcher {
+                match_str,
+                max_distance,
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(matcher))
+}
+
+/// Asks for a value which may be left empty
+fn prompt_optional<T>(prompt: &str) -> Result<Option<T>, std::io::Error>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .validate_with(|v: &String| -> Result<(), String> {
+            match v.trim() {
+                "" => Ok(()),
+                v => v.parse::<T>().map(|_| ()).map_err(|e| e.to_string()),
+            }
+        })
+        .interact()?;
+    match value.trim() {
+        "" => Ok(None),
+        v => Ok(v.parse().ok()),
+    }
+}
+
 fn header() -> String {
     r#"
 
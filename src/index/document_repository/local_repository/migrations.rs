@@ -1,13 +1,9 @@
 use crate::index::document_repository::ExtractedData;
 use crate::metadata::tag::TagId;
+use crate::persistence;
 use chrono::serde::{ts_seconds, ts_seconds_option};
 use std::{fs, io, path::Path};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-struct VersionTest {
-    version: usize,
-}
-
 //Error Handling
 use thiserror::Error;
 #[derive(Error, Debug)]
@@ -15,31 +11,41 @@ pub enum MigrationError {
     #[error("unsupported repo version {0}")]
     Version(usize),
     #[error("could not load/write document file")]
-    Config(#[from] confy::ConfyError),
+    Persistence(#[from] persistence::PersistenceError),
     #[error("could not read/delete file")]
     IO(#[from] io::Error),
 }
 
-pub fn migrate(file: &Path, index_dir: &Path) -> Result<bool, MigrationError> {
+/// Current version of the local document repository
+pub const CURRENT_VERSION: usize = 4;
+
+pub fn migrate(file: &Path, index_dir: &Path, snapshot_dir: &Path) -> Result<bool, MigrationError> {
     debug!("Checking migrations");
     if !file.exists() {
         return Ok(false);
     }
-    let version_object: VersionTest = confy::load_path(file).unwrap_or_default();
-    let version = version_object.version;
+    // V0 is stored as a plain list of documents, all later versions contain a version field
+    let version_object: serde_yaml::Value = persistence::load(file, snapshot_dir)?;
+    let mut version = version_object
+        .get("version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as usize;
 
-    if version > 2 {
+    if version > CURRENT_VERSION {
         return Err(MigrationError::Version(version));
     }
 
     if version == 0 {
         info!("Local Document Repository is stored in V0 format. Migrating to V1");
-        let v0: RepoV0 = confy::load_path(file)?;
+        let v0: RepoV0 = persistence::load(file, snapshot_dir)?;
         let v1: RepoV1 = v0.into();
-        confy::store_path(file, v1)?;
+        persistence::store(file, snapshot_dir, &v1)?;
+        version = 1;
     }
 
-    if version == 1 {
+    // V2 changed the tokenizer, V3 stores the body in the index
+    let requires_reindex = version < 3;
+    if requires_reindex {
         warn!("Index needs to be recalculated. This may take a while");
         info!("Deleting old index");
         for entry in fs::read_dir(index_dir)? {
@@ -60,13 +66,22 @@ pub fn migrate(file: &Path, index_dir: &Path) -> Result<bool, MigrationError> {
                 fs::remove_file(&path)?;
             }
         }
-        let mut v2: RepoV1 = confy::load_path(file)?;
-        v2.version = 2;
-        confy::store_path(file, v2)?;
-        return Ok(true);
     }
 
-    Ok(false)
+    if version < CURRENT_VERSION {
+        let mut repo: RepoV1 = persistence::load(file, snapshot_dir)?;
+        if version < 4 {
+            info!("Validating the extracted IBANs");
+            // BICs, VAT IDs and tax numbers are extracted when the documents are reprocessed
+            for doc in repo.docs.iter_mut() {
+                doc.validate_ibans();
+            }
+        }
+        repo.version = CURRENT_VERSION;
+        persistence::store(file, snapshot_dir, &repo)?;
+    }
+
+    Ok(requires_reindex)
 }
 
 //////////////////////////////////////////////
@@ -117,8 +132,20 @@ impl From<RepoV0> for RepoV1 {
                         email: vec![],
                         link: vec![],
                         iban: vec![],
+                        bic: vec![],
+                        vat_id: vec![],
+                        tax_number: vec![],
                         doc_date: d.inferred_date,
+                        date_candidates: vec![],
+                        amounts: vec![],
+                        total: None,
                     },
+                    provenance: Default::default(),
+                    evidence: vec![],
+                    correspondent: None,
+                    document_type: None,
+                    custom_fields: Default::default(),
+                    file_metadata: Default::default(),
                 })
                 .collect(),
         }
@@ -127,7 +154,7 @@ impl From<RepoV0> for RepoV1 {
 impl std::default::Default for RepoV1 {
     fn default() -> Self {
         RepoV1 {
-            version: 1,
+            version: CURRENT_VERSION,
             docs: vec![],
         }
     }
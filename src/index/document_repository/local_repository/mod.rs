@@ -11,6 +11,7 @@ use tantivy::schema::*;
 use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy};
 
 use super::{DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions, SortOrder};
+use crate::persistence;
 
 mod migrations;
 
@@ -18,6 +19,7 @@ pub struct LocalDocumentRepository {
     index_writer: IndexWriter,
     index_reader: IndexReader,
     doc_file: PathBuf,
+    snapshot_dir: PathBuf,
     schema: Schema,
     requires_reindex: bool,
 }
@@ -43,7 +45,7 @@ pub enum IndexerError {
     #[error("function is not implemented")]
     NotImplementedError(),
     #[error("could not load/write document file")]
-    ConfigError(#[from] confy::ConfyError),
+    PersistenceError(#[from] crate::persistence::PersistenceError),
     #[error("could not migrate repo")]
     MigrationError(#[from] migrations::MigrationError),
 }
@@ -58,7 +60,10 @@ impl LocalDocumentRepository {
     pub fn new(index_dir: &Path) -> Result<LocalDocumentRepository, IndexerError> {
         let i_dir: PathBuf = index_dir.into();
         let doc_file = index_dir.join("docs.yaml");
-        let requires_reindex = migrations::migrate(&doc_file, &i_dir)?;
+        let snapshot_dir = index_dir.join("snapshots");
+        let requires_reindex = migrations::migrate(&doc_file, &i_dir, &snapshot_dir)?;
+        // Recover a corrupted document file before using it
+        let _: Documents = persistence::load_or_default(&doc_file, &snapshot_dir)?;
         let index = LocalDocumentRepository::init_index(&i_dir)?;
         let schema = index.schema();
         let reader = index
@@ -76,12 +81,19 @@ impl LocalDocumentRepository {
             index_reader: reader,
             index_writer: writer,
             doc_file,
+            snapshot_dir,
             requires_reindex,
         })
     }
 
-    pub fn requires_reindex(&self) -> bool {
-        self.requires_reindex
+    /// Reads all documents stored in the `docs.yaml` of the given index directory.
+    /// Used to migrate the documents into another document repository.
+    pub fn read_documents(index_dir: &Path) -> Result<Vec<DocumentData>, IndexerError> {
+        let doc_file = index_dir.join("docs.yaml");
+        let snapshot_dir = index_dir.join("snapshots");
+        migrations::migrate(&doc_file, index_dir, &snapshot_dir)?;
+        let cfg: Documents = persistence::load_or_default(&doc_file, &snapshot_dir)?;
+        Ok(cfg.docs)
     }
 
     fn init_index(index_dir: &Path) -> Result<Index, IndexerError> {
@@ -89,11 +101,13 @@ impl LocalDocumentRepository {
         let tokenizer = NgramTokenizer::new(3, 6, false);
 
         let mut schema_builder = Schema::builder();
-        let full_text_options = TextOptions::default().set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer("ngram")
-                .set_index_option(IndexRecordOption::WithFreqs),
-        );
+        let full_text_options = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("ngram")
+                    .set_index_option(IndexRecordOption::WithFreqs),
+            )
+            .set_stored();
         let id_option = IntOptions::default()
             .set_stored()
             .set_fast(Cardinality::SingleValue)
@@ -139,10 +153,10 @@ impl LocalDocumentRepository {
 
         debug!("Adding document {} to index", doc_data.id);
         self.index_writer.add_document(doc);
-        let mut cfg: Documents = confy::load_path(&self.doc_file)?;
+        let mut cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         cfg.docs.retain(|d| d.id != doc_data.id);
         cfg.docs.push(doc_data.clone());
-        confy::store_path(&self.doc_file, cfg)?;
+        persistence::store(&self.doc_file, &self.snapshot_dir, &cfg)?;
         self.index_writer
             .commit()
             .map_err(|e| IndexerError::TantivyException(format!("{:?}", e)))?;
@@ -159,15 +173,15 @@ impl LocalDocumentRepository {
         self.index_writer
             .delete_term(tantivy::Term::from_field_u64(id, doc_id));
 
-        let mut cfg: Documents = confy::load_path(&self.doc_file)?;
+        let mut cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         cfg.docs.retain(|d| d.id != doc_id);
-        confy::store_path(&self.doc_file, cfg)?;
+        persistence::store(&self.doc_file, &self.snapshot_dir, &cfg)?;
         Ok(())
     }
 
     /// Retrieves a document from the repository
     fn _get_document(&self, id: DocId) -> Result<DocumentData, IndexerError> {
-        let cfg: Documents = confy::load_path(&self.doc_file)?;
+        let cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         for doc in &cfg.docs {
             if doc.id == id {
                 return Ok(doc.clone());
@@ -190,7 +204,7 @@ impl LocalDocumentRepository {
         offset: usize,
         count: usize,
     ) -> Result<Vec<DocumentData>, IndexerError> {
-        let cfg: Documents = confy::load_path(&self.doc_file)?;
+        let cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         let slice = &cfg.docs[offset..std::cmp::min(offset + count, cfg.docs.len())];
         Ok(slice.to_vec())
     }
@@ -202,7 +216,7 @@ impl LocalDocumentRepository {
         count: usize,
         filter: FilterOptions,
     ) -> Result<Vec<DocumentData>, IndexerError> {
-        let mut cfg: Documents = confy::load_path(&self.doc_file)?;
+        let mut cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         let mut sorted = false;
         if let Some(query) = &filter.query {
             if !query.is_empty() {
@@ -230,9 +244,25 @@ impl LocalDocumentRepository {
                 sorted = true;
             }
         }
+        if let Some(correspondent) = filter.correspondent {
+            cfg.docs.retain(|d| d.correspondent == Some(correspondent));
+        }
+        if let Some(document_type) = filter.document_type {
+            cfg.docs.retain(|d| d.document_type == Some(document_type));
+        }
+        for custom_field in &filter.custom_fields {
+            cfg.docs.retain(|d| custom_field.matches(d));
+        }
+        if let Some(amount) = &filter.amount {
+            cfg.docs.retain(|d| amount.matches(d));
+        }
         if !filter.tags.is_empty() {
-            cfg.docs
-                .retain(|d| filter.tags.intersect(d.tags.clone()).len() >= filter.tags.len())
+            cfg.docs.retain(|d| {
+                filter
+                    .tags
+                    .iter()
+                    .all(|group| !group.intersect(d.tags.clone()).is_empty())
+            })
         }
         match filter.sort {
             SortOrder::ImportedDate => cfg.docs.sort_unstable_by(|a, b| {
@@ -258,6 +288,20 @@ impl LocalDocumentRepository {
                     });
                 }
             }
+            SortOrder::DocumentType => cfg.docs.sort_unstable_by(|a, b| {
+                (a.document_type.is_none(), a.document_type)
+                    .cmp(&(b.document_type.is_none(), b.document_type))
+                    .then(b.imported_date.cmp(&a.imported_date))
+            }),
+            SortOrder::CustomField(field) => cfg.docs.sort_by(|a, b| {
+                match (a.custom_fields.get(&field), b.custom_fields.get(&field)) {
+                    (Some(x), Some(y)) => x.compare(y).unwrap_or(std::cmp::Ordering::Equal),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+                .then(b.imported_date.cmp(&a.imported_date))
+            }),
         }
         let slice = &cfg.docs
             [std::cmp::min(offset, cfg.docs.len())..std::cmp::min(offset + count, cfg.docs.len())];
@@ -266,10 +310,10 @@ impl LocalDocumentRepository {
 
     fn _update_metadata(&mut self, doc: &DocumentData) -> Result<(), IndexerError> {
         //Update metadata array
-        let mut cfg: Documents = confy::load_path(&self.doc_file)?;
+        let mut cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         cfg.docs.retain(|d| d.id != doc.id);
         cfg.docs.push(doc.clone());
-        confy::store_path(&self.doc_file, cfg)?;
+        persistence::store(&self.doc_file, &self.snapshot_dir, &cfg)?;
 
         Ok(())
     }
@@ -310,15 +354,63 @@ impl LocalDocumentRepository {
     }
 
     fn _contains_hash(&self, hash: &str) -> Result<Option<DocId>, IndexerError> {
-        let cfg: Documents = confy::load_path(&self.doc_file)?;
+        let cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         match cfg.docs.iter().find(|&d| d.hash == hash) {
             Some(d) => Ok(Some(d.id)),
             None => Ok(None),
         }
     }
 
+    fn _get_body(&self, doc_id: DocId) -> Result<Option<String>, IndexerError> {
+        let id = self
+            .schema
+            .get_field("id")
+            .ok_or_else(|| IndexerError::UnknownField("id".into()))?;
+        let body = self
+            .schema
+            .get_field("body")
+            .ok_or_else(|| IndexerError::UnknownField("body".into()))?;
+        let query = tantivy::query::TermQuery::new(
+            tantivy::Term::from_field_u64(id, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let searcher = self.index_reader.searcher();
+        let result = searcher
+            .search(&query, &TopDocs::with_limit(1))
+            .map_err(|e| IndexerError::TantivyException(format!("{:?}", e)))?;
+        match result.first() {
+            Some((_, doc_address)) => {
+                let retrieved_doc = searcher
+                    .doc(*doc_address)
+                    .map_err(|e| IndexerError::TantivyException(format!("{:?}", e)))?;
+                Ok(retrieved_doc
+                    .get_first(body)
+                    .and_then(|v| v.text())
+                    .map(|t| t.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn _is_indexed(&self, doc_id: DocId) -> Result<bool, IndexerError> {
+        let id = self
+            .schema
+            .get_field("id")
+            .ok_or_else(|| IndexerError::UnknownField("id".into()))?;
+        let query = tantivy::query::TermQuery::new(
+            tantivy::Term::from_field_u64(id, doc_id),
+            IndexRecordOption::Basic,
+        );
+        let count = self
+            .index_reader
+            .searcher()
+            .search(&query, &Count)
+            .map_err(|e| IndexerError::TantivyException(format!("{:?}", e)))?;
+        Ok(count > 0)
+    }
+
     fn _get_doc_ids(&self) -> Result<Vec<DocId>, IndexerError> {
-        let cfg: Documents = confy::load_path(&self.doc_file)?;
+        let cfg: Documents = persistence::load_or_default(&self.doc_file, &self.snapshot_dir)?;
         Ok(cfg.docs.into_iter().map(|doc| doc.id).collect())
     }
 }
@@ -375,4 +467,28 @@ impl DocumentRepository for LocalDocumentRepository {
     fn get_doc_ids(&self) -> Result<Vec<DocId>, DocumentRepositoryError> {
         self._get_doc_ids().map_err(|e| e.into())
     }
+
+    fn get_body(&self, id: DocId) -> Result<Option<String>, DocumentRepositoryError> {
+        self._get_body(id).map_err(|e| e.into())
+    }
+
+    fn is_indexed(&self, id: DocId) -> Result<bool, DocumentRepositoryError> {
+        self._is_indexed(id).map_err(|e| e.into())
+    }
+
+    fn requires_reindex(&self) -> bool {
+        self.requires_reindex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalDocumentRepository;
+    use crate::index::document_repository::test_filters;
+
+    #[test]
+    fn filter_test() {
+        let index_dir = tempfile::tempdir().unwrap();
+        test_filters(&mut LocalDocumentRepository::new(index_dir.path()).unwrap());
+    }
 }
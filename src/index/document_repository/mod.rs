@@ -1,8 +1,15 @@
+use crate::metadata::correspondent::CorrespondentId;
+use crate::metadata::custom_field::{CustomFieldId, CustomFieldValue, Money};
+use crate::metadata::document_type::DocumentTypeId;
+use crate::metadata::identifier::normalize_iban;
 use crate::metadata::tag::TagId;
 
 use chrono::serde::{ts_seconds, ts_seconds_option};
+use std::collections::BTreeMap;
 
 pub mod local_repository;
+pub mod postgres_repository;
+pub mod sqlite_repository;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ExtractedData {
@@ -12,10 +19,90 @@ pub struct ExtractedData {
     pub email: Vec<String>,
     #[serde(default)]
     pub link: Vec<String>,
+    /// Valid IBANs without whitespace
     #[serde(default)]
     pub iban: Vec<String>,
+    #[serde(default)]
+    pub bic: Vec<String>,
+    /// VAT identification numbers of EU member states
+    #[serde(default)]
+    pub vat_id: Vec<String>,
+    /// German tax numbers (Steuernummern)
+    #[serde(default)]
+    pub tax_number: Vec<String>,
     #[serde(with = "ts_seconds_option")]
     pub doc_date: Option<chrono::DateTime<chrono::Utc>>,
+    /// Dates found in the document, the most likely date of the document first
+    #[serde(default)]
+    pub date_candidates: Vec<DateCandidate>,
+    /// Amounts of money found in the document, in the order they appear
+    #[serde(default)]
+    pub amounts: Vec<Money>,
+    /// Likely total of an invoice or receipt
+    #[serde(default)]
+    pub total: Option<Money>,
+}
+
+/// Date found in the body of a document, which may be the date of the document
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DateCandidate {
+    #[serde(with = "ts_seconds")]
+    pub date: chrono::DateTime<chrono::Utc>,
+    /// Higher scores are more likely the date of the document
+    pub score: f64,
+    /// Text around the date
+    pub snippet: String,
+}
+
+/// Metadata embedded in the file of a document, e.g. the Info dictionary and XMP metadata of a PDF
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FileMetadata {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default, with = "ts_seconds_option")]
+    pub creation_date: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub page_count: Option<u32>,
+    /// e.g. `1.7`
+    #[serde(default)]
+    pub pdf_version: Option<String>,
+}
+
+/// Generic titles set by scanners and office applications
+const GENERIC_TITLES: &[&str] = &["untitled", "unbenannt", "document", "dokument", "title"];
+/// Extensions of titles which are only the name of the original file
+const FILE_EXTENSIONS: &[&str] = &[
+    ".pdf", ".doc", ".docx", ".odt", ".rtf", ".txt", ".xls", ".xlsx", ".ppt", ".pptx", ".htm",
+    ".html", ".jpg", ".png", ".tif", ".tiff", ".tmp",
+];
+
+impl FileMetadata {
+    /// Returns the embedded title, if it is better than the filename
+    pub fn default_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?.trim();
+        let title = [
+            "Microsoft Word - ",
+            "Microsoft PowerPoint - ",
+            "Microsoft Excel - ",
+        ]
+        .iter()
+        .fold(title, |t, prefix| t.strip_prefix(prefix).unwrap_or(t))
+        .trim();
+        let lowercase = title.to_lowercase();
+        if title.is_empty()
+            || GENERIC_TITLES.contains(&lowercase.as_str())
+            || FILE_EXTENSIONS.iter().any(|e| lowercase.ends_with(e))
+        {
+            return None;
+        }
+        Some(title.to_string())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,6 +120,477 @@ pub struct DocumentData {
     pub language: Option<String>,
     // Extracted metadata
     pub extracted: ExtractedData,
+    /// Values which were set by a user and are kept when reprocessing
+    #[serde(default)]
+    pub provenance: Provenance,
+    /// Explains why the automatically assigned tags were assigned
+    #[serde(default)]
+    pub evidence: Vec<TagEvidence>,
+    /// Sender of the document
+    #[serde(default)]
+    pub correspondent: Option<CorrespondentId>,
+    /// Kind of the document, e.g. invoice or contract
+    #[serde(default)]
+    pub document_type: Option<DocumentTypeId>,
+    /// Values of the user-defined fields
+    #[serde(default)]
+    pub custom_fields: BTreeMap<CustomFieldId, CustomFieldValue>,
+    /// Metadata embedded in the file
+    #[serde(default)]
+    pub file_metadata: FileMetadata,
+}
+
+/// Metadata fields of a document, which can be set either automatically or by a user
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataField {
+    Language,
+    Phone,
+    Email,
+    Link,
+    Iban,
+    Bic,
+    VatId,
+    TaxNumber,
+    DocDate,
+    Correspondent,
+    DocumentType,
+}
+
+/// Tracks which values of a document were set by a user.
+/// All other values were inferred automatically and are replaced when reprocessing.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Provenance {
+    /// Tags which were added by a user
+    #[serde(default)]
+    pub manual_tags: Vec<TagId>,
+    /// Tags which were removed by a user and are not assigned automatically anymore
+    #[serde(default)]
+    pub rejected_tags: Vec<TagId>,
+    /// Fields which were edited by a user
+    #[serde(default)]
+    pub manual_fields: Vec<MetadataField>,
+    /// Custom fields which were edited by a user
+    #[serde(default)]
+    pub manual_custom_fields: Vec<CustomFieldId>,
+    /// Tags which were only assigned as ancestors of other tags, they are removed together with these tags
+    #[serde(default)]
+    pub implied_tags: Vec<TagId>,
+}
+
+/// Records which rule of a tag matched a document and where
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TagEvidence {
+    pub tag: TagId,
+    /// Readable description of the matcher of the tag
+    pub rule: String,
+    /// Byte ranges of the body which caused the match
+    pub matches: Vec<(usize, usize)>,
+    /// Text around the first matches
+    pub snippets: Vec<String>,
+}
+
+impl DocumentData {
+    /// Adds a tag which matched the document. A tag implied by another tag before is kept with its own match.
+    pub fn assign_tag(&mut self, tag: TagId) {
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+        self.provenance.implied_tags.retain(|t| *t != tag);
+    }
+
+    /// Adds a tag set by a user
+    pub fn add_manual_tag(&mut self, tag: TagId) {
+        self.assign_tag(tag);
+        if !self.provenance.manual_tags.contains(&tag) {
+            self.provenance.manual_tags.push(tag);
+        }
+        self.provenance.rejected_tags.retain(|t| *t != tag);
+    }
+
+    /// Removes a tag by request of a user, so that it is not assigned again when reprocessing
+    pub fn remove_manual_tag(&mut self, tag: TagId) {
+        self.tags.retain(|t| *t != tag);
+        self.evidence.retain(|e| e.tag != tag);
+        self.provenance.manual_tags.retain(|t| *t != tag);
+        self.provenance.implied_tags.retain(|t| *t != tag);
+        if !self.provenance.rejected_tags.contains(&tag) {
+            self.provenance.rejected_tags.push(tag);
+        }
+    }
+
+    /// Marks a field as edited by a user
+    pub fn set_manual_field(&mut self, field: MetadataField) {
+        if !self.provenance.manual_fields.contains(&field) {
+            self.provenance.manual_fields.push(field);
+        }
+    }
+
+    /// Sets or removes the correspondent by request of a user, so that it is not assigned again when reprocessing
+    pub fn set_manual_correspondent(&mut self, correspondent: Option<CorrespondentId>) {
+        self.correspondent = correspondent;
+        self.set_manual_field(MetadataField::Correspondent);
+    }
+
+    /// Sets or removes the type by request of a user, so that it is not assigned again when reprocessing
+    pub fn set_manual_document_type(&mut self, document_type: Option<DocumentTypeId>) {
+        self.document_type = document_type;
+        self.set_manual_field(MetadataField::DocumentType);
+    }
+
+    /// Sets or removes the value of a custom field by request of a user, so that it is kept when reprocessing
+    pub fn set_manual_custom_field(
+        &mut self,
+        field: CustomFieldId,
+        value: Option<CustomFieldValue>,
+    ) {
+        match value {
+            Some(value) => self.custom_fields.insert(field, value),
+            None => self.custom_fields.remove(&field),
+        };
+        if !self.provenance.manual_custom_fields.contains(&field) {
+            self.provenance.manual_custom_fields.push(field);
+        }
+    }
+
+    /// Replaces a tag by another tag or removes it, if no replacement is given.
+    /// The provenance of the tag is moved to the replacement. Returns true if the document changed.
+    /// The ancestors of the remaining tags have to be assigned again afterwards.
+    pub fn replace_tag(&mut self, tag: TagId, replacement: Option<TagId>) -> bool {
+        let had_tag = self.tags.contains(&tag);
+        let manual = self.provenance.manual_tags.contains(&tag);
+        let rejected = self.provenance.rejected_tags.contains(&tag);
+        let implied = self.provenance.implied_tags.contains(&tag);
+        if !(had_tag || manual || rejected) {
+            return false;
+        }
+        self.tags.retain(|t| *t != tag);
+        self.evidence.retain(|e| e.tag != tag);
+        self.provenance.manual_tags.retain(|t| *t != tag);
+        self.provenance.rejected_tags.retain(|t| *t != tag);
+        self.provenance.implied_tags.retain(|t| *t != tag);
+        if let Some(replacement) = replacement {
+            if manual {
+                self.add_manual_tag(replacement);
+            } else if had_tag && !implied {
+                self.assign_tag(replacement);
+            } else if rejected
+                && !self.tags.contains(&replacement)
+                && !self.provenance.rejected_tags.contains(&replacement)
+            {
+                self.provenance.rejected_tags.push(replacement);
+            }
+        }
+        true
+    }
+
+    /// Replaces the evidence of a tag, or removes it if `None` is given
+    pub fn set_evidence(&mut self, tag: TagId, evidence: Option<TagEvidence>) {
+        self.evidence.retain(|e| e.tag != tag);
+        self.evidence.extend(evidence);
+    }
+
+    /// Restores all values set by a user from the previous version of the document.
+    /// Should be called after the automatic values were inferred again.
+    pub fn keep_manual_values(&mut self, previous: &DocumentData) {
+        let provenance = &previous.provenance;
+        self.tags.retain(|t| !provenance.rejected_tags.contains(t));
+        self.evidence
+            .retain(|e| !provenance.rejected_tags.contains(&e.tag));
+        for tag in &provenance.manual_tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(*tag);
+            }
+        }
+        for field in &provenance.manual_fields {
+            match field {
+                MetadataField::Language => self.language = previous.language.clone(),
+                MetadataField::Phone => self.extracted.phone = previous.extracted.phone.clone(),
+                MetadataField::Email => self.extracted.email = previous.extracted.email.clone(),
+                MetadataField::Link => self.extracted.link = previous.extracted.link.clone(),
+                MetadataField::Iban => self.extracted.iban = previous.extracted.iban.clone(),
+                MetadataField::Bic => self.extracted.bic = previous.extracted.bic.clone(),
+                MetadataField::VatId => self.extracted.vat_id = previous.extracted.vat_id.clone(),
+                MetadataField::TaxNumber => {
+                    self.extracted.tax_number = previous.extracted.tax_number.clone()
+                }
+                MetadataField::DocDate => self.extracted.doc_date = previous.extracted.doc_date,
+                MetadataField::Correspondent => self.correspondent = previous.correspondent,
+                MetadataField::DocumentType => self.document_type = previous.document_type,
+            }
+        }
+        for field in &provenance.manual_custom_fields {
+            match previous.custom_fields.get(field) {
+                Some(value) => self.custom_fields.insert(*field, value.clone()),
+                None => self.custom_fields.remove(field),
+            };
+        }
+        // The implied tags were inferred again, but tags set by a user are not implied
+        let implied_tags = std::mem::take(&mut self.provenance.implied_tags)
+            .into_iter()
+            .filter(|t| !provenance.manual_tags.contains(t))
+            .collect();
+        self.provenance = Provenance {
+            implied_tags,
+            ..provenance.clone()
+        };
+    }
+
+    /// Removes extracted IBANs with an invalid checksum and the whitespace of the others,
+    /// IBANs entered by a user are kept. Returns true if the IBANs changed.
+    /// Used to migrate documents extracted before IBANs were validated.
+    pub fn validate_ibans(&mut self) -> bool {
+        if self.provenance.manual_fields.contains(&MetadataField::Iban) {
+            return false;
+        }
+        let mut ibans: Vec<String> = vec![];
+        for iban in self.extracted.iban.iter().filter_map(|i| normalize_iban(i)) {
+            if !ibans.contains(&iban) {
+                ibans.push(iban);
+            }
+        }
+        let changed = ibans != self.extracted.iban;
+        self.extracted.iban = ibans;
+        changed
+    }
+}
+
+#[cfg(test)]
+impl DocumentData {
+    /// Creates a document for tests, which is imported `id` seconds after 2020-09-13
+    pub(crate) fn test(id: super::DocId) -> DocumentData {
+        use chrono::TimeZone;
+        DocumentData {
+            id,
+            original_filename: format!("{}.pdf", id),
+            title: format!("Document {}", id),
+            body: None,
+            tags: vec![],
+            imported_date: chrono::Utc.timestamp(1_600_000_000 + id as i64, 0),
+            hash: format!("hash{}", id),
+            file_size: 0,
+            language: None,
+            extracted: ExtractedData::default(),
+            provenance: Default::default(),
+            evidence: vec![],
+            correspondent: None,
+            document_type: None,
+            custom_fields: Default::default(),
+            file_metadata: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_body(mut self, body: &str) -> DocumentData {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub(crate) fn with_tags(mut self, tags: Vec<TagId>) -> DocumentData {
+        self.tags = tags;
+        self
+    }
+
+    pub(crate) fn with_language(mut self, language: &str) -> DocumentData {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub(crate) fn with_extracted(mut self, extracted: ExtractedData) -> DocumentData {
+        self.extracted = extracted;
+        self
+    }
+}
+
+/// Checks the filters and sort orders, which have to behave the same in all document repositories.
+/// The full text search is tested by each repository.
+#[cfg(test)]
+pub(crate) fn test_filters(repo: &mut dyn DocumentRepository) {
+    for (id, body, tags) in [
+        (1, "telekom invoice", vec![1, 2]),
+        (2, "insurance contract", vec![1]),
+        (3, "another invoice", vec![2]),
+    ] {
+        repo.add_document(&DocumentData::test(id).with_body(body).with_tags(tags))
+            .unwrap();
+    }
+
+    let ids = |docs: Vec<DocumentData>| docs.iter().map(|d| d.id).collect::<Vec<u64>>();
+    let filter = |tags: Vec<Vec<u64>>| FilterOptions {
+        sort: SortOrder::ImportedDate,
+        tags,
+        query: None,
+        correspondent: None,
+        document_type: None,
+        custom_fields: vec![],
+        amount: None,
+    };
+
+    assert_eq!(
+        ids(repo.get_filtered_documents(0, 10, filter(vec![])).unwrap()),
+        vec![3, 2, 1]
+    );
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, filter(vec![vec![1]]))
+            .unwrap()),
+        vec![2, 1]
+    );
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, filter(vec![vec![1], vec![2]]))
+            .unwrap()),
+        vec![1]
+    );
+    // Any tag of a group matches
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, filter(vec![vec![2, 5]]))
+            .unwrap()),
+        vec![3, 1]
+    );
+    assert_eq!(
+        ids(repo.get_filtered_documents(1, 1, filter(vec![])).unwrap()),
+        vec![2]
+    );
+
+    let mut doc = repo.get_document(2).unwrap();
+    doc.correspondent = Some(7);
+    repo.update_metadata(&doc).unwrap();
+    let by_correspondent = FilterOptions {
+        correspondent: Some(7),
+        ..filter(vec![vec![1]])
+    };
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, by_correspondent)
+            .unwrap()),
+        vec![2]
+    );
+    for (id, document_type) in [(3, 1), (1, 2)] {
+        let mut doc = repo.get_document(id).unwrap();
+        doc.document_type = Some(document_type);
+        repo.update_metadata(&doc).unwrap();
+    }
+    let by_type = FilterOptions {
+        document_type: Some(1),
+        ..filter(vec![])
+    };
+    assert_eq!(
+        ids(repo.get_filtered_documents(0, 10, by_type).unwrap()),
+        vec![3]
+    );
+    // Documents without type come last
+    let sorted_by_type = FilterOptions {
+        sort: SortOrder::DocumentType,
+        ..filter(vec![])
+    };
+    assert_eq!(
+        ids(repo.get_filtered_documents(0, 10, sorted_by_type).unwrap()),
+        vec![3, 1, 2]
+    );
+
+    // Custom fields, the first document has no value
+    let money = |cents, currency: &str| Money {
+        cents,
+        currency: currency.into(),
+    };
+    for (id, cents) in [(2, 25000), (3, 9950)] {
+        let mut doc = repo.get_document(id).unwrap();
+        doc.custom_fields
+            .insert(1, CustomFieldValue::Money(money(cents, "EUR")));
+        repo.update_metadata(&doc).unwrap();
+    }
+    let by_field = |comparison| FilterOptions {
+        custom_fields: vec![CustomFieldFilter {
+            field: 1,
+            comparison,
+            value: CustomFieldValue::Money(money(10000, "EUR")),
+        }],
+        ..filter(vec![])
+    };
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, by_field(Comparison::GreaterThan))
+            .unwrap()),
+        vec![2]
+    );
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(0, 10, by_field(Comparison::LessThan))
+            .unwrap()),
+        vec![3]
+    );
+    let sorted_by_field = FilterOptions {
+        sort: SortOrder::CustomField(1),
+        ..filter(vec![])
+    };
+    assert_eq!(
+        ids(repo.get_filtered_documents(0, 10, sorted_by_field).unwrap()),
+        vec![3, 2, 1]
+    );
+
+    // Amounts, the first document has no total
+    for (id, amounts, total) in [
+        (1, vec![money(20000, "USD")], None),
+        (
+            2,
+            vec![money(1900, "EUR"), money(11900, "EUR")],
+            Some(11900),
+        ),
+        (3, vec![money(2500, "EUR")], Some(2500)),
+    ] {
+        let mut doc = repo.get_document(id).unwrap();
+        doc.extracted.amounts = amounts;
+        doc.extracted.total = total.map(|cents| money(cents, "EUR"));
+        repo.update_metadata(&doc).unwrap();
+    }
+    let by_amount = |scope, comparison, cents, currency: &str| FilterOptions {
+        amount: Some(AmountFilter {
+            scope,
+            comparison,
+            amount: money(cents, currency),
+        }),
+        ..filter(vec![])
+    };
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(
+                0,
+                10,
+                by_amount(AmountScope::Total, Comparison::GreaterThan, 10000, "EUR")
+            )
+            .unwrap()),
+        vec![2]
+    );
+    assert!(repo
+        .get_filtered_documents(
+            0,
+            10,
+            by_amount(AmountScope::Total, Comparison::GreaterThan, 10000, "USD")
+        )
+        .unwrap()
+        .is_empty());
+    // Any amount, the currency has to match
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(
+                0,
+                10,
+                by_amount(AmountScope::Any, Comparison::LessThan, 2000, "EUR")
+            )
+            .unwrap()),
+        vec![2]
+    );
+    assert_eq!(
+        ids(repo
+            .get_filtered_documents(
+                0,
+                10,
+                by_amount(AmountScope::Any, Comparison::Equals, 20000, "USD")
+            )
+            .unwrap()),
+        vec![1]
+    );
 }
 
 //Error Handling
@@ -41,17 +599,105 @@ use thiserror::Error;
 pub enum DocumentRepositoryError {
     #[error("error in local document repository: `{0}`")]
     LocalRepoError(#[from] local_repository::IndexerError),
+    #[error("error in sqlite document repository: `{0}`")]
+    SqliteRepoError(#[from] sqlite_repository::SqliteRepositoryError),
+    #[error("error in postgres document repository: `{0}`")]
+    PostgresRepoError(#[from] postgres_repository::PostgresRepositoryError),
 }
 pub enum SortOrder {
-    ImportedDate = 0,
-    InferredDate = 1,
-    NoOrder = 2,
+    ImportedDate,
+    InferredDate,
+    NoOrder,
+    /// Groups the documents by type (by ID of the type), newest first within a type
+    DocumentType,
+    /// Ascending by the value of a custom field, documents without a value come last
+    CustomField(CustomFieldId),
+}
+
+/// Comparison of a filter on a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equals,
+    LessThan,
+    GreaterThan,
+}
+
+impl Comparison {
+    pub fn sql_operator(&self) -> &'static str {
+        match self {
+            Comparison::Equals => "=",
+            Comparison::LessThan => "<",
+            Comparison::GreaterThan => ">",
+        }
+    }
+
+    /// Checks the result of comparing a value to the value of the filter
+    pub fn accepts(&self, ordering: std::cmp::Ordering) -> bool {
+        match self {
+            Comparison::Equals => ordering == std::cmp::Ordering::Equal,
+            Comparison::LessThan => ordering == std::cmp::Ordering::Less,
+            Comparison::GreaterThan => ordering == std::cmp::Ordering::Greater,
+        }
+    }
+}
+
+/// Matches documents whose value of a custom field compares to the given value.
+/// Amounts of money are compared regardless of their currency.
+pub struct CustomFieldFilter {
+    pub field: CustomFieldId,
+    pub comparison: Comparison,
+    pub value: CustomFieldValue,
+}
+
+impl CustomFieldFilter {
+    pub fn matches(&self, doc: &DocumentData) -> bool {
+        doc.custom_fields
+            .get(&self.field)
+            .and_then(|v| v.compare(&self.value))
+            .map(|o| self.comparison.accepts(o))
+            .unwrap_or(false)
+    }
+}
+
+/// Amounts of a document an `AmountFilter` is applied to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AmountScope {
+    /// The likely total of an invoice or receipt
+    Total,
+    /// Any amount found in the document
+    Any,
+}
+
+/// Matches documents with an amount in the currency of the filter, which compares to its amount
+pub struct AmountFilter {
+    pub scope: AmountScope,
+    pub comparison: Comparison,
+    pub amount: Money,
+}
+
+impl AmountFilter {
+    pub fn matches(&self, doc: &DocumentData) -> bool {
+        let accepts = |m: &Money| {
+            m.currency == self.amount.currency
+                && self.comparison.accepts(m.cents.cmp(&self.amount.cents))
+        };
+        match self.scope {
+            AmountScope::Total => doc.extracted.total.iter().any(accepts),
+            AmountScope::Any => doc.extracted.amounts.iter().any(accepts),
+        }
+    }
 }
 
 pub struct FilterOptions {
     pub sort: SortOrder,
-    pub tags: Vec<TagId>,
+    /// Groups of tags. A group matches documents with any of its tags, all groups have to match.
+    pub tags: Vec<Vec<TagId>>,
     pub query: Option<String>,
+    pub correspondent: Option<CorrespondentId>,
+    pub document_type: Option<DocumentTypeId>,
+    /// All filters have to match
+    pub custom_fields: Vec<CustomFieldFilter>,
+    pub amount: Option<AmountFilter>,
 }
 
 /// Implements a location where document data will be stored
@@ -59,6 +705,18 @@ pub trait DocumentRepository {
     /// Adds or replaces a document to the repository
     fn add_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError>;
 
+    /// Adds a newly imported document.
+    /// Repositories shared between instances fail if a document with the same ID already exists.
+    fn insert_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self.add_document(doc)
+    }
+
+    /// Allocates the ID of a new document in repositories shared between instances.
+    /// Returns `None` if the IDs are allocated by the index.
+    fn next_id(&mut self) -> Result<Option<super::DocId>, DocumentRepositoryError> {
+        Ok(None)
+    }
+
     ///Updates the metadata of the document.
     ///The body attribute does not have to be set
     fn update_metadata(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError>;
@@ -92,4 +750,152 @@ pub trait DocumentRepository {
 
     /// Returns a list of all known ids
     fn get_doc_ids(&self) -> Result<Vec<super::DocId>, DocumentRepositoryError>;
+
+    /// Retrieves the stored body of a document without extracting it again
+    fn get_body(&self, id: super::DocId) -> Result<Option<String>, DocumentRepositoryError>;
+
+    /// Checks if the body of the document is contained in the full text index
+    fn is_indexed(&self, id: super::DocId) -> Result<bool, DocumentRepositoryError>;
+
+    /// Whether the stored documents have to be reprocessed to rebuild the search index
+    fn requires_reindex(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DocumentData, FileMetadata, MetadataField, Provenance, TagEvidence};
+    use crate::metadata::custom_field::CustomFieldValue;
+    use crate::metadata::tag::TagId;
+
+    fn get_document() -> DocumentData {
+        DocumentData::test(1)
+    }
+
+    fn evidence(tag: TagId) -> TagEvidence {
+        TagEvidence {
+            tag,
+            rule: "contains \"test\"".into(),
+            matches: vec![(0, 4)],
+            snippets: vec!["test".into()],
+        }
+    }
+
+    #[test]
+    fn keep_manual_values_test() {
+        let mut previous = get_document();
+        previous.tags = vec![1, 2];
+        previous.add_manual_tag(3);
+        previous.remove_manual_tag(2);
+        previous.language = Some("German".into());
+        previous.set_manual_field(MetadataField::Language);
+        previous.extracted.email = vec!["old@example.com".into()];
+        previous.set_manual_correspondent(Some(4));
+        previous.document_type = Some(1);
+        previous.set_manual_custom_field(1, Some(CustomFieldValue::String("A-1".into())));
+        previous.set_manual_custom_field(2, None);
+
+        // Reprocessing infers tag 2 again, tags 3 and 5 as ancestors and finds new values
+        let mut doc = previous.clone();
+        doc.tags = vec![1, 2, 3, 5];
+        doc.provenance.implied_tags = vec![3, 5];
+        doc.language = Some("English".into());
+        doc.extracted.email = vec!["new@example.com".into()];
+        doc.correspondent = Some(5);
+        doc.document_type = Some(2);
+        doc.custom_fields = vec![
+            (1, CustomFieldValue::String("B-2".into())),
+            (2, CustomFieldValue::Number(3.0)),
+            (3, CustomFieldValue::Number(4.0)),
+        ]
+        .into_iter()
+        .collect();
+        for tag in &[1, 2] {
+            doc.set_evidence(*tag, Some(evidence(*tag)));
+        }
+        doc.keep_manual_values(&previous);
+
+        assert_eq!(doc.tags, vec![1, 3, 5]);
+        assert_eq!(doc.language, Some("German".into()));
+        assert_eq!(doc.extracted.email, vec!["new@example.com".to_string()]);
+        // Tags set by a user are not implied
+        assert_eq!(
+            doc.provenance,
+            Provenance {
+                implied_tags: vec![5],
+                ..previous.provenance
+            }
+        );
+        assert_eq!(doc.evidence, vec![evidence(1)]);
+        assert_eq!(doc.correspondent, Some(4));
+        // The type was inferred and is replaced
+        assert_eq!(doc.document_type, Some(2));
+        // Custom fields set or removed by a user are kept, others are replaced
+        assert_eq!(
+            doc.custom_fields.into_iter().collect::<Vec<_>>(),
+            vec![
+                (1, CustomFieldValue::String("A-1".into())),
+                (3, CustomFieldValue::Number(4.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_tag_test() {
+        let mut doc = get_document();
+        doc.tags = vec![1, 2];
+        doc.add_manual_tag(3);
+        doc.remove_manual_tag(4);
+
+        assert!(!doc.replace_tag(5, Some(1)));
+        // Merge into a tag the document already has
+        assert!(doc.replace_tag(1, Some(2)));
+        assert_eq!(doc.tags, vec![2, 3]);
+        // Manual tags stay manual
+        assert!(doc.replace_tag(3, Some(6)));
+        assert_eq!(doc.tags, vec![2, 6]);
+        assert_eq!(doc.provenance.manual_tags, vec![6]);
+        // Rejections are kept
+        assert!(doc.replace_tag(4, Some(7)));
+        assert_eq!(doc.provenance.rejected_tags, vec![7]);
+        // Removal
+        assert!(doc.replace_tag(6, None));
+        assert_eq!(doc.tags, vec![2]);
+        assert!(doc.provenance.manual_tags.is_empty());
+
+        // Implied tags are not assigned directly by a merge
+        doc.tags = vec![2, 8, 9];
+        doc.provenance.implied_tags = vec![8, 9];
+        assert!(doc.replace_tag(8, Some(10)));
+        assert_eq!(doc.tags, vec![2, 9]);
+        assert_eq!(doc.provenance.implied_tags, vec![9]);
+        // A tag merged into an implied tag assigns it directly
+        assert!(doc.replace_tag(2, Some(9)));
+        assert_eq!(doc.tags, vec![9]);
+        assert!(doc.provenance.implied_tags.is_empty());
+    }
+
+    #[test]
+    fn default_title_test() {
+        let title = |t: &str| {
+            FileMetadata {
+                title: Some(t.into()),
+                ..Default::default()
+            }
+            .default_title()
+        };
+        assert_eq!(
+            title(" Stromrechnung 2021 "),
+            Some("Stromrechnung 2021".into())
+        );
+        assert_eq!(
+            title("Microsoft Word - Kündigung"),
+            Some("Kündigung".into())
+        );
+        assert_eq!(title("Untitled"), None);
+        assert_eq!(title("scan_0001.PDF"), None);
+        assert_eq!(title(""), None);
+        assert_eq!(FileMetadata::default().default_title(), None);
+    }
 }
@@ -0,0 +1,862 @@
+use std::sync::{Mutex, MutexGuard};
+
+use postgres::error::SqlState;
+use postgres::types::ToSql;
+use postgres::{Client, NoTls, Row, Transaction};
+
+use super::{
+    AmountScope, DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions,
+    SortOrder,
+};
+use crate::index::DocId;
+use crate::metadata::custom_field::CustomFieldValue;
+
+/// Current version of the database schema, stored in the table `schema_version`.
+/// V1 validates the extracted IBANs, V2 allocates the document IDs from the sequence `document_ids`.
+const SCHEMA_VERSION: i32 = 2;
+
+/// Languages (as detected by `whatlang`) which have a text search configuration in a default PostgreSQL installation
+const TS_CONFIGS: &[&str] = &[
+    "danish",
+    "dutch",
+    "english",
+    "finnish",
+    "french",
+    "german",
+    "hungarian",
+    "italian",
+    "portuguese",
+    "romanian",
+    "russian",
+    "spanish",
+    "swedish",
+    "turkish",
+];
+
+/// Connection settings of the PostgreSQL document repository
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PostgresConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    /// Schema in which the tables are created
+    pub schema: String,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            host: "localhost".into(),
+            port: 5432,
+            user: "shreddr".into(),
+            password: String::new(),
+            database: "shreddr".into(),
+            schema: "public".into(),
+        }
+    }
+}
+
+/// Document repository storing the metadata in a PostgreSQL database.
+/// Full text search is implemented with a `tsvector` per document in the language of the document.
+/// The IDs of new documents are allocated by the database, so that multiple instances can share it.
+pub struct PostgresDocumentRepository {
+    client: Mutex<Client>,
+}
+
+//Error Handling
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum PostgresRepositoryError {
+    #[error("error in postgres database `{0}`")]
+    Postgres(#[from] postgres::Error),
+    #[error("could not (de)serialize document data `{0}`")]
+    Serialization(#[from] serde_json::Error),
+    #[error("could not fetch document with id `{0}`")]
+    DocumentFetchError(DocId),
+    #[error("could not get lock on database connection")]
+    Lock(),
+    #[error("unsupported database schema version {0}")]
+    Version(i32),
+    #[error("document with id `{0}` already exists")]
+    DuplicateId(DocId),
+}
+
+impl PostgresDocumentRepository {
+    /// Connects to the configured database and creates the tables if they do not exist yet
+    pub fn new(
+        cfg: &PostgresConfig,
+    ) -> Result<PostgresDocumentRepository, PostgresRepositoryError> {
+        info!(
+            "Connecting to postgres database `{}` at {}:{}",
+            cfg.database, cfg.host, cfg.port
+        );
+        let mut client = postgres::Config::new()
+            .host(&cfg.host)
+            .port(cfg.port)
+            .user(&cfg.user)
+            .password(&cfg.password)
+            .dbname(&cfg.database)
+            .connect(NoTls)?;
+        PostgresDocumentRepository::init_schema(&mut client, &cfg.schema)?;
+        Ok(PostgresDocumentRepository {
+            client: Mutex::new(client),
+        })
+    }
+
+    fn init_schema(client: &mut Client, schema: &str) -> Result<(), PostgresRepositoryError> {
+        let schema = schema.replace('"', "\"\"");
+        client.batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS \"{0}\";
+            SET search_path TO \"{0}\";",
+            schema
+        ))?;
+        client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS documents (
+                id BIGINT PRIMARY KEY,
+                original_filename TEXT NOT NULL,
+                title TEXT NOT NULL,
+                body TEXT,
+                imported_date TIMESTAMPTZ NOT NULL,
+                hash TEXT NOT NULL,
+                file_size BIGINT NOT NULL,
+                language TEXT,
+                ts_config REGCONFIG NOT NULL DEFAULT 'simple',
+                body_tsv TSVECTOR,
+                data JSONB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS documents_hash ON documents(hash);
+            CREATE INDEX IF NOT EXISTS documents_imported_date ON documents(imported_date);
+            CREATE INDEX IF NOT EXISTS documents_body_tsv ON documents USING GIN(body_tsv);
+            CREATE SEQUENCE IF NOT EXISTS document_ids OWNED BY documents.id;
+            CREATE TABLE IF NOT EXISTS extracted_data (
+                doc_id BIGINT PRIMARY KEY REFERENCES documents(id) ON DELETE CASCADE,
+                doc_date TIMESTAMPTZ,
+                phone TEXT[] NOT NULL,
+                email TEXT[] NOT NULL,
+                link TEXT[] NOT NULL,
+                iban TEXT[] NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS extracted_data_doc_date ON extracted_data(doc_date);
+            CREATE TABLE IF NOT EXISTS document_tags (
+                doc_id BIGINT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                tag_id BIGINT NOT NULL,
+                PRIMARY KEY (doc_id, tag_id)
+            );
+            CREATE INDEX IF NOT EXISTS document_tags_tag ON document_tags(tag_id);
+            CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+        )?;
+        let version: i32 = client
+            .query_opt("SELECT version FROM schema_version", &[])?
+            .map(|row| row.get(0))
+            .unwrap_or(0);
+        if version > SCHEMA_VERSION {
+            return Err(PostgresRepositoryError::Version(version));
+        }
+        if version < 1 {
+            PostgresDocumentRepository::validate_ibans(client)?;
+        }
+        if version < 2 {
+            PostgresDocumentRepository::init_document_ids(client)?;
+        }
+        Ok(())
+    }
+
+    fn set_version(tx: &mut Transaction, version: i32) -> Result<(), PostgresRepositoryError> {
+        tx.execute("DELETE FROM schema_version", &[])?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES ($1)",
+            &[&version],
+        )?;
+        Ok(())
+    }
+
+    /// Migrates the documents extracted before IBANs were validated
+    fn validate_ibans(client: &mut Client) -> Result<(), PostgresRepositoryError> {
+        info!("Validating the extracted IBANs");
+        let mut tx = client.transaction()?;
+        let mut changed = 0;
+        for row in tx.query("SELECT data FROM documents", &[])? {
+            let mut doc: DocumentData = serde_json::from_value(row.try_get("data")?)?;
+            if !doc.validate_ibans() {
+                continue;
+            }
+            let id = doc.id as i64;
+            tx.execute(
+                "UPDATE documents SET data = $2 WHERE id = $1",
+                &[&id, &serde_json::to_value(&doc)?],
+            )?;
+            tx.execute(
+                "UPDATE extracted_data SET iban = $2 WHERE doc_id = $1",
+                &[&id, &doc.extracted.iban],
+            )?;
+            changed += 1;
+        }
+        PostgresDocumentRepository::set_version(&mut tx, 1)?;
+        tx.commit()?;
+        info!("Validated the IBANs of {} documents", changed);
+        Ok(())
+    }
+
+    /// Continues the sequence of document IDs after the documents imported before it was used
+    fn init_document_ids(client: &mut Client) -> Result<(), PostgresRepositoryError> {
+        let mut tx = client.transaction()?;
+        let row = tx.query_one(
+            "SELECT setval('document_ids', COALESCE(MAX(id), 0) + 1, false) FROM documents",
+            &[],
+        )?;
+        info!(
+            "Allocating document IDs from {} in the database",
+            row.try_get::<_, i64>(0)?
+        );
+        PostgresDocumentRepository::set_version(&mut tx, 2)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Client>, PostgresRepositoryError> {
+        self.client
+            .lock()
+            .map_err(|_| PostgresRepositoryError::Lock())
+    }
+
+    /// Returns the text search configuration used for a document of the given language
+    fn ts_config(language: &Option<String>) -> &'static str {
+        language
+            .as_ref()
+            .and_then(|l| {
+                TS_CONFIGS
+                    .iter()
+                    .find(|c| c.eq_ignore_ascii_case(l))
+                    .copied()
+            })
+            .unwrap_or("simple")
+    }
+
+    /// Inserts a document, or replaces it if `replace` is set.
+    /// If `with_body` is false, the stored body and search vector are kept.
+    fn store(
+        &self,
+        doc: &DocumentData,
+        with_body: bool,
+        replace: bool,
+    ) -> Result<(), PostgresRepositoryError> {
+        let data = serde_json::to_value(doc)?;
+        let id = doc.id as i64;
+        let mut sql = String::from(
+            "INSERT INTO documents (id, original_filename, title, imported_date, hash, file_size, language, data)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        );
+        if replace {
+            sql.push_str(
+                " ON CONFLICT (id) DO UPDATE SET original_filename = excluded.original_filename,
+                title = excluded.title, imported_date = excluded.imported_date, hash = excluded.hash,
+                file_size = excluded.file_size, language = excluded.language, data = excluded.data",
+            );
+        }
+        let mut client = self.lock()?;
+        let mut tx = client.transaction()?;
+        tx.execute(
+            sql.as_str(),
+            &[
+                &id,
+                &doc.original_filename,
+                &doc.title,
+                &doc.imported_date,
+                &doc.hash,
+                &(doc.file_size as i64),
+                &doc.language,
+                &data,
+            ],
+        )
+        .map_err(|e| match e.code() {
+            Some(&SqlState::UNIQUE_VIOLATION) => PostgresRepositoryError::DuplicateId(doc.id),
+            _ => e.into(),
+        })?;
+        if with_body {
+            debug!("Adding document {} to full text index", doc.id);
+            tx.execute(
+                "UPDATE documents SET body = $2, ts_config = $3::text::regconfig,
+                body_tsv = to_tsvector($3::text::regconfig, coalesce($2, '')) WHERE id = $1",
+                &[
+                    &id,
+                    &doc.body,
+                    &PostgresDocumentRepository::ts_config(&doc.language),
+                ],
+            )?;
+        }
+        tx.execute(
+            "INSERT INTO extracted_data (doc_id, doc_date, phone, email, link, iban)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (doc_id) DO UPDATE SET doc_date = excluded.doc_date, phone = excluded.phone,
+            email = excluded.email, link = excluded.link, iban = excluded.iban",
+            &[
+                &id,
+                &doc.extracted.doc_date,
+                &doc.extracted.phone,
+                &doc.extracted.email,
+                &doc.extracted.link,
+                &doc.extracted.iban,
+            ],
+        )?;
+        tx.execute("DELETE FROM document_tags WHERE doc_id = $1", &[&id])?;
+        for tag in &doc.tags {
+            tx.execute(
+                "INSERT INTO document_tags (doc_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                &[&id, &(*tag as i64)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn _next_id(&mut self) -> Result<DocId, PostgresRepositoryError> {
+        let id: i64 = self
+            .lock()?
+            .query_one("SELECT nextval('document_ids')", &[])?
+            .try_get(0)?;
+        Ok(id as DocId)
+    }
+
+    fn _remove_document(&mut self, id: DocId) -> Result<(), PostgresRepositoryError> {
+        debug!("Deleting {} from postgres repository if it exists", id);
+        self.lock()?
+            .execute("DELETE FROM documents WHERE id = $1", &[&(id as i64)])?;
+        Ok(())
+    }
+
+    fn to_document(row: &Row) -> Result<DocumentData, PostgresRepositoryError> {
+        let mut doc: DocumentData = serde_json::from_value(row.try_get("data")?)?;
+        doc.body = row.try_get("body")?;
+        Ok(doc)
+    }
+
+    fn _get_document(&self, id: DocId) -> Result<DocumentData, PostgresRepositoryError> {
+        match self.lock()?.query_opt(
+            "SELECT data, body FROM documents WHERE id = $1",
+            &[&(id as i64)],
+        )? {
+            Some(row) => PostgresDocumentRepository::to_document(&row),
+            None => Err(PostgresRepositoryError::DocumentFetchError(id)),
+        }
+    }
+
+    fn _len(&self) -> Result<usize, PostgresRepositoryError> {
+        let count: i64 = self
+            .lock()?
+            .query_one("SELECT COUNT(*) FROM documents", &[])?
+            .try_get(0)?;
+        Ok(count as usize)
+    }
+
+    fn _get_documents(
+        &self,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<DocumentData>, PostgresRepositoryError> {
+        self.lock()?
+            .query(
+                "SELECT data, NULL::text AS body FROM documents ORDER BY id LIMIT $1 OFFSET $2",
+                &[&(count as i64), &(offset as i64)],
+            )?
+            .iter()
+            .map(PostgresDocumentRepository::to_document)
+            .collect()
+    }
+
+    fn _get_filtered_documents(
+        &self,
+        offset: usize,
+        count: usize,
+        filter: FilterOptions,
+    ) -> Result<Vec<DocumentData>, PostgresRepositoryError> {
+        let mut sql = String::from(
+            "SELECT d.data, NULL::text AS body FROM documents d JOIN extracted_data e ON e.doc_id = d.id",
+        );
+        let mut conditions: Vec<String> = vec![];
+        let mut values: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+        let ts_query = filter
+            .query
+            .as_deref()
+            .map(PostgresDocumentRepository::ts_query)
+            .unwrap_or_default();
+        let has_query = !ts_query.is_empty();
+        if has_query {
+            values.push(Box::new(ts_query));
+            conditions.push(format!(
+                "d.body_tsv @@ to_tsquery(d.ts_config, ${})",
+                values.len()
+            ));
+        }
+
+        if let Some(correspondent) = filter.correspondent {
+            values.push(Box::new(correspondent as i64));
+            conditions.push(format!(
+                "(d.data->>'correspondent')::BIGINT = ${}",
+                values.len()
+            ));
+        }
+        if let Some(document_type) = filter.document_type {
+            values.push(Box::new(document_type as i64));
+            conditions.push(format!(
+                "(d.data->>'document_type')::BIGINT = ${}",
+                values.len()
+            ));
+        }
+
+        for custom_field in &filter.custom_fields {
+            let path = format!(
+                "{{custom_fields,{},{}}}",
+                custom_field.field,
+                CustomFieldValue::json_path(custom_field.value.field_type()).join(",")
+            );
+            let cast = match &custom_field.value {
+                CustomFieldValue::String(s) => {
+                    values.push(Box::new(s.clone()));
+                    ""
+                }
+                CustomFieldValue::Number(n) => {
+                    values.push(Box::new(*n));
+                    "::DOUBLE PRECISION"
+                }
+                CustomFieldValue::Money(m) => {
+                    values.push(Box::new(m.cents));
+                    "::BIGINT"
+                }
+                CustomFieldValue::Date(d) => {
+                    values.push(Box::new(d.to_string()));
+                    ""
+                }
+            };
+            conditions.push(format!(
+                "(d.data #>> '{}'){} {} ${}",
+                path,
+                cast,
+                custom_field.comparison.sql_operator(),
+                values.len()
+            ));
+        }
+
+        if let Some(amount) = &filter.amount {
+            values.push(Box::new(amount.amount.currency.clone()));
+            values.push(Box::new(amount.amount.cents));
+            let (currency, cents) = (values.len() - 1, values.len());
+            let operator = amount.comparison.sql_operator();
+            conditions.push(match amount.scope {
+                AmountScope::Total => format!(
+                    "(d.data #>> '{{extracted,total,currency}}' = ${} AND (d.data #>> '{{extracted,total,cents}}')::BIGINT {} ${})",
+                    currency, operator, cents
+                ),
+                AmountScope::Any => format!(
+                    "EXISTS (SELECT 1 FROM jsonb_array_elements(d.data #> '{{extracted,amounts}}') a WHERE a ->> 'currency' = ${} AND (a ->> 'cents')::BIGINT {} ${})",
+                    currency, operator, cents
+                ),
+            });
+        }
+
+        for group in &filter.tags {
+            let tags: Vec<i64> = group.iter().map(|t| *t as i64).collect();
+            values.push(Box::new(tags));
+            conditions.push(format!(
+                "d.id IN (SELECT doc_id FROM document_tags WHERE tag_id = ANY(${}))",
+                values.len()
+            ));
+        }
+
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        match filter.sort {
+            SortOrder::ImportedDate => sql.push_str(" ORDER BY d.imported_date DESC"),
+            SortOrder::InferredDate => sql.push_str(" ORDER BY e.doc_date DESC NULLS LAST"),
+            SortOrder::NoOrder if has_query => {
+                sql.push_str(" ORDER BY ts_rank(d.body_tsv, to_tsquery(d.ts_config, $1)) DESC")
+            }
+            SortOrder::NoOrder => sql.push_str(" ORDER BY d.imported_date DESC"),
+            SortOrder::DocumentType => sql.push_str(
+                " ORDER BY (d.data->>'document_type')::BIGINT NULLS LAST, d.imported_date DESC",
+            ),
+            // jsonb orders numbers numerically, amounts of money by their cents
+            SortOrder::CustomField(field) => sql.push_str(&format!(
+                " ORDER BY d.data #> '{{custom_fields,{}}}' NULLS LAST, d.imported_date DESC",
+                field
+            )),
+        };
+        values.push(Box::new(count as i64));
+        values.push(Box::new(offset as i64));
+        sql.push_str(&format!(
+            " LIMIT ${} OFFSET ${}",
+            values.len() - 1,
+            values.len()
+        ));
+
+        debug!("Querying postgres repository: {}", sql);
+        let params: Vec<&(dyn ToSql + Sync)> = values.iter().map(|v| v.as_ref()).collect();
+        self.lock()?
+            .query(sql.as_str(), &params)?
+            .iter()
+            .map(PostgresDocumentRepository::to_document)
+            .collect()
+    }
+
+    /// Converts a user query into a `tsquery` string.
+    /// Each word is used as prefix and the words are combined by `|`.
+    fn ts_query(query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|w| {
+                w.chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .collect::<String>()
+            })
+            .filter(|w| !w.is_empty())
+            .map(|w| format!("{}:*", w))
+            .collect::<Vec<String>>()
+            .join(" | ")
+    }
+
+    fn _contains_hash(&self, hash: &str) -> Result<Option<DocId>, PostgresRepositoryError> {
+        let row = self
+            .lock()?
+            .query_opt("SELECT id FROM documents WHERE hash = $1 LIMIT 1", &[&hash])?;
+        match row {
+            Some(r) => Ok(Some(r.try_get::<_, i64>(0)? as DocId)),
+            None => Ok(None),
+        }
+    }
+
+    fn _get_body(&self, id: DocId) -> Result<Option<String>, PostgresRepositoryError> {
+        let row = self
+            .lock()?
+            .query_opt("SELECT body FROM documents WHERE id = $1", &[&(id as i64)])?;
+        match row {
+            Some(r) => Ok(r.try_get(0)?),
+            None => Ok(None),
+        }
+    }
+
+    fn _is_indexed(&self, id: DocId) -> Result<bool, PostgresRepositoryError> {
+        let row = self.lock()?.query_opt(
+            "SELECT body_tsv IS NOT NULL FROM documents WHERE id = $1",
+            &[&(id as i64)],
+        )?;
+        match row {
+            Some(r) => Ok(r.try_get(0)?),
+            None => Ok(false),
+        }
+    }
+
+    fn _get_doc_ids(&self) -> Result<Vec<DocId>, PostgresRepositoryError> {
+        self.lock()?
+            .query("SELECT id FROM documents ORDER BY id", &[])?
+            .iter()
+            .map(|r| Ok(r.try_get::<_, i64>(0)? as DocId))
+            .collect()
+    }
+}
+
+impl DocumentRepository for PostgresDocumentRepository {
+    fn add_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self.store(doc, true, true).map_err(|e| e.into())
+    }
+
+    fn insert_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self.store(doc, true, false).map_err(|e| e.into())
+    }
+
+    fn next_id(&mut self) -> Result<Option<DocId>, DocumentRepositoryError> {
+        self._next_id().map(Some).map_err(|e| e.into())
+    }
+
+    fn update_metadata(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self.store(doc, false, true).map_err(|e| e.into())
+    }
+
+    fn remove_document(&mut self, id: DocId) -> Result<(), DocumentRepositoryError> {
+        self._remove_document(id).map_err(|e| e.into())
+    }
+
+    fn get_document(&self, id: DocId) -> Result<DocumentData, DocumentRepositoryError> {
+        self._get_document(id).map_err(|e| e.into())
+    }
+
+    fn contains_hash(&self, hash: &str) -> Result<Option<DocId>, DocumentRepositoryError> {
+        self._contains_hash(hash).map_err(|e| e.into())
+    }
+
+    fn len(&self) -> Result<usize, DocumentRepositoryError> {
+        self._len().map_err(|e| e.into())
+    }
+
+    fn get_documents(
+        &self,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+        self._get_documents(offset, count).map_err(|e| e.into())
+    }
+
+    fn get_filtered_documents(
+        &self,
+        offset: usize,
+        count: usize,
+        filter: FilterOptions,
+    ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+        self._get_filtered_documents(offset, count, filter)
+            .map_err(|e| e.into())
+    }
+
+    fn get_doc_ids(&self) -> Result<Vec<DocId>, DocumentRepositoryError> {
+        self._get_doc_ids().map_err(|e| e.into())
+    }
+
+    fn get_body(&self, id: DocId) -> Result<Option<String>, DocumentRepositoryError> {
+        self._get_body(id).map_err(|e| e.into())
+    }
+
+    fn is_indexed(&self, id: DocId) -> Result<bool, DocumentRepositoryError> {
+        self._is_indexed(id).map_err(|e| e.into())
+    }
+}
+
+/// These tests require a running PostgreSQL server.
+/// They are skipped unless `SHREDDR_TEST_POSTGRES_HOST` is set, e.g.:
+/// `docker run -e POSTGRES_USER=shreddr -e POSTGRES_PASSWORD=shreddr -p 5432:5432 postgres`
+/// `SHREDDR_TEST_POSTGRES_HOST=localhost SHREDDR_TEST_POSTGRES_PASSWORD=shreddr cargo test postgres`
+#[cfg(test)]
+mod tests {
+    use super::{PostgresConfig, PostgresDocumentRepository, PostgresRepositoryError};
+    use crate::index::document_repository::{
+        test_filters, DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions,
+        MetadataField, SortOrder,
+    };
+
+    fn get_config(schema: &str) -> Option<PostgresConfig> {
+        let host = std::env::var("SHREDDR_TEST_POSTGRES_HOST").ok()?;
+        Some(PostgresConfig {
+            host,
+            password: std::env::var("SHREDDR_TEST_POSTGRES_PASSWORD").unwrap_or_default(),
+            schema: schema.into(),
+            ..PostgresConfig::default()
+        })
+    }
+
+    fn get_repo(schema: &str) -> Option<(PostgresDocumentRepository, postgres::Client)> {
+        let cfg = get_config(schema)?;
+        let mut admin = postgres::Config::new()
+            .host(&cfg.host)
+            .port(cfg.port)
+            .user(&cfg.user)
+            .password(&cfg.password)
+            .dbname(&cfg.database)
+            .connect(postgres::NoTls)
+            .unwrap();
+        admin
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .unwrap();
+        Some((PostgresDocumentRepository::new(&cfg).unwrap(), admin))
+    }
+
+    #[test]
+    fn config_defaults_test() {
+        let cfg: PostgresConfig = serde_yaml::from_str("host: db.local\nport: 5433").unwrap();
+        assert_eq!(cfg.host, "db.local");
+        assert_eq!(cfg.port, 5433);
+        assert_eq!(cfg.user, "shreddr");
+        assert_eq!(cfg.database, "shreddr");
+        assert_eq!(cfg.schema, "public");
+    }
+
+    fn get_document(id: u64, body: &str, language: &str, tags: Vec<u64>) -> DocumentData {
+        DocumentData::test(id)
+            .with_body(body)
+            .with_language(language)
+            .with_tags(tags)
+    }
+
+    #[test]
+    fn filter_test() {
+        let schema = "shreddr_filter_test";
+        let (mut repo, mut admin) = match get_repo(schema) {
+            Some(r) => r,
+            None => {
+                println!("SHREDDR_TEST_POSTGRES_HOST not set, skipping postgres test");
+                return;
+            }
+        };
+        test_filters(&mut repo);
+
+        admin
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .unwrap();
+    }
+
+    #[test]
+    fn postgres_repository_test() {
+        let schema = "shreddr_test";
+        let (mut repo, mut admin) = match get_repo(schema) {
+            Some(r) => r,
+            None => {
+                println!("SHREDDR_TEST_POSTGRES_HOST not set, skipping postgres test");
+                return;
+            }
+        };
+        repo.add_document(&get_document(
+            1,
+            "Die Rechnungen der Telekom",
+            "German",
+            vec![1, 2],
+        ))
+        .unwrap();
+        repo.add_document(&get_document(
+            2,
+            "The insurance contracts",
+            "English",
+            vec![1],
+        ))
+        .unwrap();
+        repo.add_document(&get_document(3, "Eine weitere Rechnung", "German", vec![2]))
+            .unwrap();
+
+        assert_eq!(repo.len().unwrap(), 3);
+        assert_eq!(repo.contains_hash("hash2").unwrap(), Some(2));
+        assert_eq!(repo.get_document(1).unwrap().tags, vec![1, 2]);
+
+        let ids = |docs: Vec<DocumentData>| docs.iter().map(|d| d.id).collect::<Vec<u64>>();
+        let filter = |query: &str| FilterOptions {
+            sort: SortOrder::ImportedDate,
+            tags: vec![],
+            query: Some(query.into()),
+            correspondent: None,
+            document_type: None,
+            custom_fields: vec![],
+            amount: None,
+        };
+        // Stemming of the document language
+        assert_eq!(
+            ids(repo
+                .get_filtered_documents(0, 10, filter("Rechnung"))
+                .unwrap()),
+            vec![3, 1]
+        );
+        assert_eq!(
+            ids(repo
+                .get_filtered_documents(0, 10, filter("contract"))
+                .unwrap()),
+            vec![2]
+        );
+
+        assert!(repo.is_indexed(1).unwrap());
+        assert!(repo.get_body(2).unwrap().unwrap().contains("contract"));
+        repo.remove_document(1).unwrap();
+        assert!(!repo.is_indexed(1).unwrap());
+        assert_eq!(repo.get_doc_ids().unwrap(), vec![2, 3]);
+
+        admin
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .unwrap();
+    }
+
+    #[test]
+    fn shared_database_test() {
+        let schema = "shreddr_shared_test";
+        let (mut repo, mut admin) = match get_repo(schema) {
+            Some(r) => r,
+            None => {
+                println!("SHREDDR_TEST_POSTGRES_HOST not set, skipping postgres test");
+                return;
+            }
+        };
+        // A document imported before the IDs were allocated by the database
+        repo.add_document(&get_document(5, "", "German", vec![]))
+            .unwrap();
+        admin
+            .batch_execute(&format!("DELETE FROM {}.schema_version", schema))
+            .unwrap();
+        // Two instances sharing the database
+        let mut repos = [
+            PostgresDocumentRepository::new(&get_config(schema).unwrap()).unwrap(),
+            PostgresDocumentRepository::new(&get_config(schema).unwrap()).unwrap(),
+        ];
+
+        let mut ids = vec![];
+        for i in 0..4 {
+            let repo = &mut repos[i % 2];
+            let id = repo.next_id().unwrap().unwrap();
+            let mut doc = get_document(id, "", "German", vec![]);
+            doc.hash = format!("import{}", i);
+            repo.insert_document(&doc).unwrap();
+            ids.push(id);
+        }
+        assert_eq!(ids, vec![6, 7, 8, 9]);
+        assert_eq!(repos[0].get_doc_ids().unwrap(), vec![5, 6, 7, 8, 9]);
+        assert_eq!(repos[1].get_document(8).unwrap().hash, "import2");
+
+        // New documents do not replace existing ones
+        assert!(matches!(
+            repos[1].insert_document(&get_document(6, "", "German", vec![])),
+            Err(DocumentRepositoryError::PostgresRepoError(
+                PostgresRepositoryError::DuplicateId(6)
+            ))
+        ));
+        assert_eq!(repos[0].get_document(6).unwrap().hash, "import0");
+
+        admin
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .unwrap();
+    }
+
+    #[test]
+    fn iban_migration_test() {
+        let schema = "shreddr_iban_test";
+        let (mut repo, mut admin) = match get_repo(schema) {
+            Some(r) => r,
+            None => {
+                println!("SHREDDR_TEST_POSTGRES_HOST not set, skipping postgres test");
+                return;
+            }
+        };
+        let ibans = vec![
+            "DE89 3704 0044 0532 0130 00".to_string(),
+            "DE89 3704 0044 0532 0130 01".to_string(),
+        ];
+        let mut doc = get_document(1, "", "German", vec![]);
+        doc.extracted.iban = ibans.clone();
+        repo.add_document(&doc).unwrap();
+        let mut doc = get_document(2, "", "German", vec![]);
+        doc.extracted.iban = ibans.clone();
+        doc.set_manual_field(MetadataField::Iban);
+        repo.add_document(&doc).unwrap();
+        // Reset the schema to the version before IBANs were validated
+        admin
+            .batch_execute(&format!("DELETE FROM {}.schema_version", schema))
+            .unwrap();
+
+        let repo = PostgresDocumentRepository::new(&get_config(schema).unwrap()).unwrap();
+        assert_eq!(
+            repo.get_document(1).unwrap().extracted.iban,
+            vec!["DE89370400440532013000"]
+        );
+        let row = admin
+            .query_one(
+                &format!(
+                    "SELECT iban FROM {}.extracted_data WHERE doc_id = 1",
+                    schema
+                ),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(row.get::<_, Vec<String>>(0), vec!["DE89370400440532013000"]);
+        // IBANs entered by a user are kept
+        assert_eq!(repo.get_document(2).unwrap().extracted.iban, ibans);
+
+        admin
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .unwrap();
+    }
+}
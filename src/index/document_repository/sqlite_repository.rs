@@ -0,0 +1,693 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use super::local_repository::LocalDocumentRepository;
+use super::{
+    AmountScope, DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions,
+    SortOrder,
+};
+use crate::index::DocId;
+use crate::metadata::custom_field::{CustomFieldId, CustomFieldType, CustomFieldValue};
+
+/// Current version of the database schema, stored in `PRAGMA user_version`.
+/// V2 validates the extracted IBANs.
+const SCHEMA_VERSION: i64 = 2;
+
+/// Document repository storing the metadata in an embedded SQLite database.
+/// The body of each document is stored in a FTS5 table to allow full text search.
+pub struct SqliteDocumentRepository {
+    connection: Mutex<Connection>,
+    requires_reindex: bool,
+}
+
+//Error Handling
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum SqliteRepositoryError {
+    #[error("error in sqlite database `{0}`")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("could not (de)serialize document data `{0}`")]
+    Serialization(#[from] serde_json::Error),
+    #[error("could not fetch document with id `{0}`")]
+    DocumentFetchError(DocId),
+    #[error("could not get lock on database connection")]
+    Lock(),
+    #[error("unsupported database schema version {0}")]
+    Version(i64),
+    #[error("could not migrate documents from local repository: `{0}`")]
+    Migration(#[from] super::local_repository::IndexerError),
+    #[error("could not move migrated document file")]
+    IO(#[from] std::io::Error),
+}
+
+impl SqliteDocumentRepository {
+    /// Opens (or creates) the database `docs.sqlite` in the given index directory.
+    /// If a `docs.yaml` of the local repository exists, its documents are migrated into the database once.
+    pub fn new(index_dir: &Path) -> Result<SqliteDocumentRepository, SqliteRepositoryError> {
+        let connection = Connection::open(index_dir.join("docs.sqlite"))?;
+        let mut repo = SqliteDocumentRepository::from_connection(connection)?;
+
+        let doc_file = index_dir.join("docs.yaml");
+        if doc_file.exists() {
+            repo.migrate_local_repository(index_dir, &doc_file)?;
+        }
+        Ok(repo)
+    }
+
+    fn from_connection(
+        mut connection: Connection,
+    ) -> Result<SqliteDocumentRepository, SqliteRepositoryError> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        SqliteDocumentRepository::init_schema(&mut connection)?;
+        Ok(SqliteDocumentRepository {
+            connection: Mutex::new(connection),
+            requires_reindex: false,
+        })
+    }
+
+    fn init_schema(connection: &mut Connection) -> Result<(), SqliteRepositoryError> {
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(SqliteRepositoryError::Version(version));
+        }
+        if version == SCHEMA_VERSION {
+            return Ok(());
+        }
+        if version < 1 {
+            SqliteDocumentRepository::create_tables(connection)?;
+        }
+        if version < 2 {
+            SqliteDocumentRepository::validate_ibans(connection)?;
+        }
+        Ok(())
+    }
+
+    fn create_tables(connection: &Connection) -> Result<(), SqliteRepositoryError> {
+        info!("Initializing sqlite document repository");
+        connection.execute_batch(
+            "BEGIN;
+            CREATE TABLE IF NOT EXISTS documents (
+                id INTEGER PRIMARY KEY,
+                hash TEXT NOT NULL,
+                imported_date INTEGER NOT NULL,
+                doc_date INTEGER,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS documents_hash ON documents(hash);
+            CREATE INDEX IF NOT EXISTS documents_imported_date ON documents(imported_date);
+            CREATE INDEX IF NOT EXISTS documents_doc_date ON documents(doc_date);
+            CREATE TABLE IF NOT EXISTS document_tags (
+                doc_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                tag_id INTEGER NOT NULL,
+                PRIMARY KEY (doc_id, tag_id)
+            );
+            CREATE INDEX IF NOT EXISTS document_tags_tag ON document_tags(tag_id);
+            CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(body, tokenize = 'trigram');
+            PRAGMA user_version = 1;
+            COMMIT;",
+        )?;
+        Ok(())
+    }
+
+    /// Migrates the documents extracted before IBANs were validated
+    fn validate_ibans(connection: &mut Connection) -> Result<(), SqliteRepositoryError> {
+        info!("Validating the extracted IBANs");
+        let tx = connection.transaction()?;
+        let docs = {
+            let mut stmt = tx.prepare("SELECT data FROM documents")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let mut docs = vec![];
+            for data in rows {
+                docs.push(serde_json::from_str::<DocumentData>(&data?)?);
+            }
+            docs
+        };
+        let mut changed = 0;
+        for mut doc in docs {
+            if doc.validate_ibans() {
+                SqliteDocumentRepository::store_metadata(&tx, &doc)?;
+                changed += 1;
+            }
+        }
+        tx.execute_batch("PRAGMA user_version = 2;")?;
+        tx.commit()?;
+        info!("Validated the IBANs of {} documents", changed);
+        Ok(())
+    }
+
+    /// Imports all documents of the `docs.yaml` of the local repository.
+    /// As the local repository does not store the bodies of the documents, the documents have to be reindexed afterwards.
+    fn migrate_local_repository(
+        &mut self,
+        index_dir: &Path,
+        doc_file: &Path,
+    ) -> Result<(), SqliteRepositoryError> {
+        if self._len()? > 0 {
+            warn!(
+                "Found {:?}, but the sqlite repository already contains documents. Skipping migration",
+                doc_file
+            );
+            return Ok(());
+        }
+        info!(
+            "Migrating documents from {:?} into sqlite repository",
+            doc_file
+        );
+        let docs = LocalDocumentRepository::read_documents(index_dir)?;
+        // All documents are migrated in one transaction, so that an interrupted migration is repeated on the next start
+        {
+            let mut connection = self.lock()?;
+            let tx = connection.transaction()?;
+            for doc in &docs {
+                SqliteDocumentRepository::store_document(&tx, doc)?;
+            }
+            tx.commit()?;
+        }
+        let mut migrated_file: PathBuf = doc_file.into();
+        migrated_file.set_extension("yaml.migrated");
+        std::fs::rename(doc_file, &migrated_file)?;
+        info!(
+            "Migrated {} documents, moved old document file to {:?}",
+            docs.len(),
+            migrated_file
+        );
+        self.requires_reindex = !docs.is_empty();
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, SqliteRepositoryError> {
+        self.connection
+            .lock()
+            .map_err(|_| SqliteRepositoryError::Lock())
+    }
+
+    fn _add_document(&mut self, doc: &DocumentData) -> Result<(), SqliteRepositoryError> {
+        let mut connection = self.lock()?;
+        let tx = connection.transaction()?;
+        SqliteDocumentRepository::store_document(&tx, doc)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Inserts or replaces the metadata and the body of a document
+    fn store_document(
+        connection: &Connection,
+        doc: &DocumentData,
+    ) -> Result<(), SqliteRepositoryError> {
+        SqliteDocumentRepository::store_metadata(connection, doc)?;
+        debug!("Adding document {} to full text index", doc.id);
+        connection.execute(
+            "DELETE FROM documents_fts WHERE rowid = ?1",
+            params![doc.id as i64],
+        )?;
+        connection.execute(
+            "INSERT INTO documents_fts (rowid, body) VALUES (?1, ?2)",
+            params![doc.id as i64, doc.body.as_deref().unwrap_or_default()],
+        )?;
+        Ok(())
+    }
+
+    fn _update_metadata(&mut self, doc: &DocumentData) -> Result<(), SqliteRepositoryError> {
+        let mut connection = self.lock()?;
+        let tx = connection.transaction()?;
+        SqliteDocumentRepository::store_metadata(&tx, doc)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Inserts or replaces the metadata of a document (without the body)
+    fn store_metadata(
+        connection: &Connection,
+        doc: &DocumentData,
+    ) -> Result<(), SqliteRepositoryError> {
+        let data = serde_json::to_string(doc)?;
+        connection.execute(
+            "INSERT INTO documents (id, hash, imported_date, doc_date, data) VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(id) DO UPDATE SET hash = excluded.hash, imported_date = excluded.imported_date,
+            doc_date = excluded.doc_date, data = excluded.data",
+            params![
+                doc.id as i64,
+                doc.hash,
+                doc.imported_date.timestamp(),
+                doc.extracted.doc_date.map(|d| d.timestamp()),
+                data
+            ],
+        )?;
+        connection.execute(
+            "DELETE FROM document_tags WHERE doc_id = ?1",
+            params![doc.id as i64],
+        )?;
+        for tag in &doc.tags {
+            connection.execute(
+                "INSERT OR IGNORE INTO document_tags (doc_id, tag_id) VALUES (?1, ?2)",
+                params![doc.id as i64, *tag as i64],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn _remove_document(&mut self, id: DocId) -> Result<(), SqliteRepositoryError> {
+        let mut connection = self.lock()?;
+        let tx = connection.transaction()?;
+        debug!("Deleting {} from sqlite repository if it exists", id);
+        tx.execute("DELETE FROM documents WHERE id = ?1", params![id as i64])?;
+        tx.execute(
+            "DELETE FROM documents_fts WHERE rowid = ?1",
+            params![id as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn _get_document(&self, id: DocId) -> Result<DocumentData, SqliteRepositoryError> {
+        let data: Option<String> = self
+            .lock()?
+            .query_row(
+                "SELECT data FROM documents WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match data {
+            Some(d) => Ok(serde_json::from_str(&d)?),
+            None => Err(SqliteRepositoryError::DocumentFetchError(id)),
+        }
+    }
+
+    fn _len(&self) -> Result<usize, SqliteRepositoryError> {
+        let count: i64 = self
+            .lock()?
+            .query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn _get_documents(
+        &self,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<DocumentData>, SqliteRepositoryError> {
+        let connection = self.lock()?;
+        let mut stmt =
+            connection.prepare("SELECT data FROM documents ORDER BY id LIMIT ?1 OFFSET ?2")?;
+        let rows = stmt.query_map(params![count as i64, offset as i64], |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut docs = vec![];
+        for data in rows {
+            docs.push(serde_json::from_str(&data?)?);
+        }
+        Ok(docs)
+    }
+
+    fn _get_filtered_documents(
+        &self,
+        offset: usize,
+        count: usize,
+        filter: FilterOptions,
+    ) -> Result<Vec<DocumentData>, SqliteRepositoryError> {
+        let mut sql = String::from("SELECT d.data FROM documents d");
+        let mut conditions: Vec<String> = vec![];
+        let mut values: Vec<rusqlite::types::Value> = vec![];
+
+        let query = filter.query.as_deref().unwrap_or_default();
+        let fts_query = SqliteDocumentRepository::fts_query(query);
+        if fts_query.is_empty() && !query.trim().is_empty() {
+            // None of the words can be searched, so no document matches the query
+            return Ok(vec![]);
+        }
+        if !fts_query.is_empty() {
+            sql.push_str(" JOIN documents_fts f ON f.rowid = d.id");
+            conditions.push("documents_fts MATCH ?".into());
+            values.push(fts_query.clone().into());
+        }
+
+        if let Some(correspondent) = filter.correspondent {
+            conditions.push("json_extract(d.data, '$.correspondent') = ?".into());
+            values.push((correspondent as i64).into());
+        }
+        if let Some(document_type) = filter.document_type {
+            conditions.push("json_extract(d.data, '$.document_type') = ?".into());
+            values.push((document_type as i64).into());
+        }
+
+        for custom_field in &filter.custom_fields {
+            conditions.push(format!(
+                "{} {} ?",
+                SqliteDocumentRepository::custom_field_value(
+                    custom_field.field,
+                    custom_field.value.field_type()
+                ),
+                custom_field.comparison.sql_operator()
+            ));
+            values.push(match &custom_field.value {
+                CustomFieldValue::String(s) => s.clone().into(),
+                CustomFieldValue::Number(n) => (*n).into(),
+                CustomFieldValue::Money(m) => m.cents.into(),
+                CustomFieldValue::Date(d) => d.to_string().into(),
+            });
+        }
+
+        if let Some(amount) = &filter.amount {
+            let operator = amount.comparison.sql_operator();
+            conditions.push(match amount.scope {
+                AmountScope::Total => format!(
+                    "(json_extract(d.data, '$.extracted.total.currency') = ? AND json_extract(d.data, '$.extracted.total.cents') {} ?)",
+                    operator
+                ),
+                AmountScope::Any => format!(
+                    "EXISTS (SELECT 1 FROM json_each(d.data, '$.extracted.amounts') a WHERE json_extract(a.value, '$.currency') = ? AND json_extract(a.value, '$.cents') {} ?)",
+                    operator
+                ),
+            });
+            values.push(amount.amount.currency.clone().into());
+            values.push(amount.amount.cents.into());
+        }
+
+        for group in &filter.tags {
+            let placeholders = vec!["?"; group.len()].join(",");
+            conditions.push(format!(
+                "d.id IN (SELECT doc_id FROM document_tags WHERE tag_id IN ({}))",
+                placeholders
+            ));
+            for tag in group {
+                values.push((*tag as i64).into());
+            }
+        }
+
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        let custom_field_order;
+        sql.push_str(match filter.sort {
+            SortOrder::ImportedDate => " ORDER BY d.imported_date DESC",
+            SortOrder::InferredDate => " ORDER BY d.doc_date IS NULL, d.doc_date DESC",
+            SortOrder::NoOrder if !fts_query.is_empty() => " ORDER BY bm25(documents_fts)",
+            SortOrder::NoOrder => " ORDER BY d.imported_date DESC",
+            SortOrder::DocumentType => {
+                " ORDER BY json_extract(d.data, '$.document_type') IS NULL, json_extract(d.data, '$.document_type'), d.imported_date DESC"
+            }
+            SortOrder::CustomField(field) => {
+                // A field has only one type, so at most one of the values is not null
+                let value = format!(
+                    "COALESCE({})",
+                    [
+                        CustomFieldType::String,
+                        CustomFieldType::Number,
+                        CustomFieldType::Money,
+                        CustomFieldType::Date
+                    ]
+                    .iter()
+                    .map(|t| SqliteDocumentRepository::custom_field_value(field, *t))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+                );
+                custom_field_order = format!(
+                    " ORDER BY {0} IS NULL, {0}, d.imported_date DESC",
+                    value
+                );
+                &custom_field_order
+            }
+        });
+        sql.push_str(" LIMIT ? OFFSET ?");
+        values.push((count as i64).into());
+        values.push((offset as i64).into());
+
+        debug!("Querying sqlite repository: {}", sql);
+        let connection = self.lock()?;
+        let mut stmt = connection.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut docs = vec![];
+        for data in rows {
+            docs.push(serde_json::from_str(&data?)?);
+        }
+        Ok(docs)
+    }
+
+    /// Extracts the comparable part of the value of a custom field with the given type
+    fn custom_field_value(field: CustomFieldId, field_type: CustomFieldType) -> String {
+        format!(
+            "json_extract(d.data, '$.custom_fields.\"{}\".{}')",
+            field,
+            CustomFieldValue::json_path(field_type).join(".")
+        )
+    }
+
+    /// Converts a user query into a FTS5 query.
+    /// Each word is quoted and the words are combined by `OR`, the trigram tokenizer ignores words shorter than three characters.
+    fn fts_query(query: &str) -> String {
+        query
+            .split_whitespace()
+            .filter(|w| w.chars().count() >= 3)
+            .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" OR ")
+    }
+
+    fn _contains_hash(&self, hash: &str) -> Result<Option<DocId>, SqliteRepositoryError> {
+        let id: Option<i64> = self
+            .lock()?
+            .query_row(
+                "SELECT id FROM documents WHERE hash = ?1 LIMIT 1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id.map(|i| i as DocId))
+    }
+
+    fn _get_body(&self, id: DocId) -> Result<Option<String>, SqliteRepositoryError> {
+        Ok(self
+            .lock()?
+            .query_row(
+                "SELECT body FROM documents_fts WHERE rowid = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn _is_indexed(&self, id: DocId) -> Result<bool, SqliteRepositoryError> {
+        let count: i64 = self.lock()?.query_row(
+            "SELECT COUNT(*) FROM documents_fts WHERE rowid = ?1",
+            params![id as i64],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    fn _get_doc_ids(&self) -> Result<Vec<DocId>, SqliteRepositoryError> {
+        let connection = self.lock()?;
+        let mut stmt = connection.prepare("SELECT id FROM documents ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+        let mut ids = vec![];
+        for id in rows {
+            ids.push(id? as DocId);
+        }
+        Ok(ids)
+    }
+}
+
+impl DocumentRepository for SqliteDocumentRepository {
+    fn add_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self._add_document(doc).map_err(|e| e.into())
+    }
+
+    fn update_metadata(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+        self._update_metadata(doc).map_err(|e| e.into())
+    }
+
+    fn remove_document(&mut self, id: DocId) -> Result<(), DocumentRepositoryError> {
+        self._remove_document(id).map_err(|e| e.into())
+    }
+
+    fn get_document(&self, id: DocId) -> Result<DocumentData, DocumentRepositoryError> {
+        self._get_document(id).map_err(|e| e.into())
+    }
+
+    fn contains_hash(&self, hash: &str) -> Result<Option<DocId>, DocumentRepositoryError> {
+        self._contains_hash(hash).map_err(|e| e.into())
+    }
+
+    fn len(&self) -> Result<usize, DocumentRepositoryError> {
+        self._len().map_err(|e| e.into())
+    }
+
+    fn get_documents(
+        &self,
+        offset: usize,
+        count: usize,
+    ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+        self._get_documents(offset, count).map_err(|e| e.into())
+    }
+
+    fn get_filtered_documents(
+        &self,
+        offset: usize,
+        count: usize,
+        filter: FilterOptions,
+    ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+        self._get_filtered_documents(offset, count, filter)
+            .map_err(|e| e.into())
+    }
+
+    fn get_doc_ids(&self) -> Result<Vec<DocId>, DocumentRepositoryError> {
+        self._get_doc_ids().map_err(|e| e.into())
+    }
+
+    fn get_body(&self, id: DocId) -> Result<Option<String>, DocumentRepositoryError> {
+        self._get_body(id).map_err(|e| e.into())
+    }
+
+    fn is_indexed(&self, id: DocId) -> Result<bool, DocumentRepositoryError> {
+        self._is_indexed(id).map_err(|e| e.into())
+    }
+
+    fn requires_reindex(&self) -> bool {
+        self.requires_reindex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteDocumentRepository;
+    use crate::index::document_repository::{
+        test_filters, DocumentData, DocumentRepository, FilterOptions, MetadataField, SortOrder,
+    };
+
+    fn get_repo() -> SqliteDocumentRepository {
+        SqliteDocumentRepository::from_connection(rusqlite::Connection::open_in_memory().unwrap())
+            .unwrap()
+    }
+
+    fn get_document(id: u64, body: &str, tags: Vec<u64>) -> DocumentData {
+        DocumentData::test(id).with_body(body).with_tags(tags)
+    }
+
+    #[test]
+    fn add_get_remove_test() {
+        let mut repo = get_repo();
+        repo.add_document(&get_document(1, "invoice", vec![1]))
+            .unwrap();
+        repo.add_document(&get_document(2, "contract", vec![]))
+            .unwrap();
+        assert_eq!(repo.len().unwrap(), 2);
+        assert_eq!(repo.get_document(1).unwrap().title, "Document 1");
+        assert_eq!(repo.contains_hash("hash2").unwrap(), Some(2));
+        assert_eq!(repo.contains_hash("hash3").unwrap(), None);
+
+        assert!(repo.is_indexed(1).unwrap());
+        assert_eq!(repo.get_body(1).unwrap(), Some("invoice".into()));
+        repo.remove_document(1).unwrap();
+        assert!(repo.get_document(1).is_err());
+        assert!(!repo.is_indexed(1).unwrap());
+        assert_eq!(repo.get_doc_ids().unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn filter_test() {
+        test_filters(&mut get_repo());
+    }
+
+    #[test]
+    fn search_test() {
+        let mut repo = get_repo();
+        repo.add_document(&get_document(1, "telekom invoice", vec![1, 2]))
+            .unwrap();
+        repo.add_document(&get_document(2, "insurance contract", vec![1]))
+            .unwrap();
+        repo.add_document(&get_document(3, "another invoice", vec![2]))
+            .unwrap();
+
+        let ids = |docs: Vec<DocumentData>| docs.iter().map(|d| d.id).collect::<Vec<u64>>();
+        let filter = |tags: Vec<Vec<u64>>, query: &str| FilterOptions {
+            sort: SortOrder::ImportedDate,
+            tags,
+            query: Some(query.into()),
+            correspondent: None,
+            document_type: None,
+            custom_fields: vec![],
+            amount: None,
+        };
+        assert_eq!(
+            ids(repo
+                .get_filtered_documents(0, 10, filter(vec![], "invoice"))
+                .unwrap()),
+            vec![3, 1]
+        );
+        assert_eq!(
+            ids(repo
+                .get_filtered_documents(0, 10, filter(vec![vec![1]], "invoice"))
+                .unwrap()),
+            vec![1]
+        );
+        // Words shorter than three characters cannot be searched
+        assert!(repo
+            .get_filtered_documents(0, 10, filter(vec![], "an in"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn migration_test() {
+        #[derive(Serialize)]
+        struct Documents {
+            version: usize,
+            docs: Vec<DocumentData>,
+        }
+        let index_dir = tempfile::tempdir().unwrap();
+        let doc_file = index_dir.path().join("docs.yaml");
+        let docs = Documents {
+            version: 4,
+            docs: vec![get_document(1, "", vec![1]), get_document(2, "", vec![])],
+        };
+        std::fs::write(&doc_file, serde_yaml::to_string(&docs).unwrap()).unwrap();
+
+        let repo = SqliteDocumentRepository::new(index_dir.path()).unwrap();
+        assert_eq!(repo.get_doc_ids().unwrap(), vec![1, 2]);
+        assert!(repo.requires_reindex());
+        assert!(!doc_file.exists());
+        assert!(index_dir.path().join("docs.yaml.migrated").exists());
+    }
+
+    #[test]
+    fn iban_migration_test() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let ibans = vec![
+            "DE89 3704 0044 0532 0130 00".to_string(),
+            "DE89 3704 0044 0532 0130 01".to_string(),
+        ];
+        {
+            // Database of schema version 1
+            let connection =
+                rusqlite::Connection::open(index_dir.path().join("docs.sqlite")).unwrap();
+            SqliteDocumentRepository::create_tables(&connection).unwrap();
+            let mut doc = get_document(1, "", vec![]);
+            doc.extracted.iban = ibans.clone();
+            SqliteDocumentRepository::store_metadata(&connection, &doc).unwrap();
+            let mut doc = get_document(2, "", vec![]);
+            doc.extracted.iban = ibans.clone();
+            doc.set_manual_field(MetadataField::Iban);
+            SqliteDocumentRepository::store_metadata(&connection, &doc).unwrap();
+        }
+
+        let repo = SqliteDocumentRepository::new(index_dir.path()).unwrap();
+        assert_eq!(
+            repo.get_document(1).unwrap().extracted.iban,
+            vec!["DE89370400440532013000"]
+        );
+        // IBANs entered by a user are kept
+        assert_eq!(repo.get_document(2).unwrap().extracted.iban, ibans);
+        let version: i64 = repo
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 2);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::index::document_repository::DocumentData;
+use crate::metadata::tag::Tagger;
+
+/// Columns of an export, which are followed by one column per custom field
+const COLUMNS: &[&str] = &[
+    "id",
+    "title",
+    "original_filename",
+    "imported_date",
+    "doc_date",
+    "language",
+    "tags",
+    "correspondent",
+    "document_type",
+];
+
+/// Writes the metadata of the documents as CSV.
+/// Tags, correspondents and types are written by name, custom fields are written as one column each.
+pub fn to_csv(docs: &[DocumentData], tagger: &Tagger) -> String {
+    let fields = tagger.custom_fields().get_all();
+    let mut header: Vec<String> = COLUMNS.iter().map(|c| c.to_string()).collect();
+    header.extend(fields.iter().map(|f| f.name.clone()));
+    let mut csv = row(&header);
+
+    for doc in docs {
+        let tags: Vec<String> = doc
+            .tags
+            .iter()
+            .filter_map(|t| tagger.get_tag(*t))
+            .map(|t| t.name)
+            .collect();
+        let mut values = vec![
+            doc.id.to_string(),
+            doc.title.clone(),
+            doc.original_filename.clone(),
+            doc.imported_date.to_rfc3339(),
+            doc.extracted
+                .doc_date
+                .map(|d| d.date().naive_utc().to_string())
+                .unwrap_or_default(),
+            doc.language.clone().unwrap_or_default(),
+            tags.join("; "),
+            doc.correspondent
+                .and_then(|c| tagger.correspondents().get(c))
+                .map(|c| c.name)
+                .unwrap_or_default(),
+            doc.document_type
+                .and_then(|t| tagger.document_types().get(t))
+                .map(|t| t.name)
+                .unwrap_or_default(),
+        ];
+        values.extend(fields.iter().map(|f| {
+            doc.custom_fields
+                .get(&f.id)
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        }));
+        csv.push_str(&row(&values));
+    }
+    csv
+}
+
+fn row(values: &[String]) -> String {
+    let mut row = values
+        .iter()
+        .map(|v| escape(v))
+        .collect::<Vec<String>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Quotes a value if it contains a separator, a quote or a line break (RFC 4180)
+fn escape(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, to_csv};
+    use crate::index::document_repository::DocumentData;
+    use crate::metadata::custom_field::{CustomFieldConfig, CustomFieldType};
+    use crate::metadata::tag::{MatcherConfig, TagConfig, Tagger};
+    use chrono::TimeZone;
+
+    #[test]
+    fn escape_test() {
+        assert_eq!(escape("Invoice"), "Invoice");
+        assert_eq!(escape("Invoice, March"), "\"Invoice, March\"");
+        assert_eq!(escape("The \"best\" offer"), "\"The \"\"best\"\" offer\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn to_csv_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tagger = Tagger::new(dir.path(), false).unwrap();
+        let tag = tagger
+            .add_tag(TagConfig {
+                id: 0,
+                name: "Tax".into(),
+                color: None,
+                matcher: MatcherConfig::NoMatcher,
+                parent: None,
+            })
+            .unwrap();
+        let field = tagger
+            .custom_fields_mut()
+            .add_or_replace(CustomFieldConfig {
+                id: 0,
+                name: "amount".into(),
+                field_type: CustomFieldType::Money,
+            })
+            .unwrap();
+
+        let mut doc = DocumentData {
+            original_filename: "scan.pdf".into(),
+            title: "Invoice, March".into(),
+            tags: vec![tag],
+            imported_date: chrono::Utc.timestamp(1_600_000_000, 0),
+            language: Some("German".into()),
+            ..DocumentData::test(1)
+        };
+        doc.custom_fields
+            .insert(field, CustomFieldType::Money.parse("12.5").unwrap());
+
+        assert_eq!(
+            to_csv(&[doc], &tagger),
+            "id,title,original_filename,imported_date,doc_date,language,tags,correspondent,document_type,amount\r\n\
+             1,\"Invoice, March\",scan.pdf,2020-09-13T12:26:40+00:00,,German,Tax,,,12.50 EUR\r\n"
+        );
+    }
+}
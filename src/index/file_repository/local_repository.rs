@@ -6,7 +6,9 @@ use crate::index::DocId;
 
 pub struct LocalFileRepository {
     document_dir: PathBuf,
+    archive_dir: PathBuf,
     documents: HashMap<DocId, PathBuf>,
+    archives: HashMap<DocId, PathBuf>,
 }
 
 //Error Handling
@@ -27,10 +29,15 @@ pub enum LocalFileRepositoryError {
 
 impl LocalFileRepository {
     pub fn new(document_dir: &Path) -> Result<LocalFileRepository, LocalFileRepositoryError> {
+        let archive_dir = document_dir.join("archive");
+        std::fs::create_dir_all(&archive_dir)?;
         let docs = LocalFileRepository::load_documents(document_dir)?;
+        let archives = LocalFileRepository::load_documents(&archive_dir)?;
         Ok(LocalFileRepository {
             document_dir: document_dir.into(),
+            archive_dir,
             documents: docs,
+            archives,
         })
     }
 
@@ -40,6 +47,9 @@ impl LocalFileRepository {
         let mut max: u64 = 0;
         for path in paths {
             let p = path?.path();
+            if p.is_dir() {
+                continue;
+            }
             let filename = p
                 .file_stem()
                 .ok_or_else(|| LocalFileRepositoryError::ExtractFilenameError(p.clone()))?;
@@ -65,7 +75,18 @@ impl LocalFileRepository {
         Ok(())
     }
 
+    fn _add_archive(&mut self, id: DocId, file: &Path) -> Result<(), LocalFileRepositoryError> {
+        let new_path = self.archive_dir.join(format!("{}.pdf", id));
+        std::fs::copy(file, &new_path)?;
+        self.archives.insert(id, new_path);
+        Ok(())
+    }
+
     fn _remove_document(&mut self, id: DocId) -> Result<(), LocalFileRepositoryError> {
+        if let Some(f) = self.archives.remove(&id) {
+            info!("Removing archive file `{:#?}` for document {}", &f, id);
+            std::fs::remove_file(&f)?;
+        }
         match self.documents.remove(&id) {
             None => {
                 debug!("No file with id {} in repository", id);
@@ -101,6 +122,10 @@ impl FileRepository for LocalFileRepository {
         self._add_document(id, file).map_err(|e| e.into())
     }
 
+    fn add_archive(&mut self, id: DocId, file: &Path) -> Result<(), FileRepositoryError> {
+        self._add_archive(id, file).map_err(|e| e.into())
+    }
+
     fn remove_document(&mut self, id: DocId) -> Result<(), FileRepositoryError> {
         self._remove_document(id).map_err(|e| e.into())
     }
@@ -108,4 +133,14 @@ impl FileRepository for LocalFileRepository {
     fn get_document(&self, id: DocId) -> Result<PathBuf, FileRepositoryError> {
         self._get_document(id).map_err(|e| e.into())
     }
+
+    fn get_archive(&self, id: DocId) -> Result<Option<PathBuf>, FileRepositoryError> {
+        Ok(self.archives.get(&id).cloned())
+    }
+
+    fn get_doc_ids(&self) -> Result<Vec<DocId>, FileRepositoryError> {
+        let mut ids: Vec<DocId> = self.documents.keys().copied().collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
 }
@@ -10,15 +10,40 @@ pub enum FileRepositoryError {
     FileRepoError(#[from] local_repository::LocalFileRepositoryError),
 }
 
+/// Version of a stored document file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rendition {
+    /// The untouched file as it was imported
+    Original,
+    /// Searchable version created by OCR
+    Archive,
+}
+
+impl Default for Rendition {
+    fn default() -> Self {
+        Rendition::Original
+    }
+}
+
 /// Implements a location where the document files can be stored, like disk or cloud Repository
 pub trait FileRepository {
-    /// Adds a document to the FileRepository
+    /// Adds the original file of a document to the FileRepository
     fn add_document(&mut self, id: super::DocId, file: &Path) -> Result<(), FileRepositoryError>;
 
-    /// Removes a document from the FileRepository
+    /// Adds or replaces the archive rendition of a document
+    fn add_archive(&mut self, id: super::DocId, file: &Path) -> Result<(), FileRepositoryError>;
+
+    /// Removes a document and all of its renditions from the FileRepository
     fn remove_document(&mut self, id: super::DocId) -> Result<(), FileRepositoryError>;
 
-    /// Retrieves a document from the FileRepository
+    /// Retrieves the original file of a document from the FileRepository
     /// Returns the location to the file
     fn get_document(&self, id: super::DocId) -> Result<PathBuf, FileRepositoryError>;
+
+    /// Retrieves the archive rendition of a document, if one was created
+    fn get_archive(&self, id: super::DocId) -> Result<Option<PathBuf>, FileRepositoryError>;
+
+    /// Returns the IDs of all stored documents
+    fn get_doc_ids(&self) -> Result<Vec<super::DocId>, FileRepositoryError>;
 }
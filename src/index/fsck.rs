@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use super::document_repository::{DocumentData, ExtractedData};
+use super::{DocId, Index, IndexError};
+use crate::metadata::content::ContentExtractor;
+use crate::metadata::file_extractor::FileExtractor;
+use crate::metadata::tag::TagId;
+
+/// Inconsistency between the file repository, the document repository, the thumbnails and the tagger
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum FsckIssue {
+    /// A file is stored, but the document repository does not know it
+    FileWithoutMetadata {
+        id: DocId,
+    },
+    /// The document repository contains a document whose file is missing
+    MetadataWithoutFile {
+        id: DocId,
+    },
+    /// The stored file does not match the hash of the document.
+    /// The file may be corrupted or replaced, so this is not repaired automatically.
+    HashMismatch {
+        id: DocId,
+        stored: String,
+        actual: String,
+    },
+    /// The body of the document is not contained in the full text index
+    MissingIndexEntry {
+        id: DocId,
+    },
+    MissingThumbnail {
+        id: DocId,
+    },
+    /// The thumbnail is older than the stored file
+    StaleThumbnail {
+        id: DocId,
+    },
+    /// A thumbnail exists for a document which is not stored
+    OrphanedThumbnail {
+        file: PathBuf,
+    },
+    /// The document references tags which do not exist in the tagger
+    UnknownTags {
+        id: DocId,
+        tags: Vec<TagId>,
+    },
+}
+
+impl std::fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FsckIssue::FileWithoutMetadata { id } => {
+                write!(f, "File of document {} has no metadata", id)
+            }
+            FsckIssue::MetadataWithoutFile { id } => {
+                write!(f, "Document {} has no file", id)
+            }
+            FsckIssue::HashMismatch { id, stored, actual } => write!(
+                f,
+                "File of document {} has hash {}, but {} is stored",
+                id, actual, stored
+            ),
+            FsckIssue::MissingIndexEntry { id } => {
+                write!(f, "Document {} is missing in the full text index", id)
+            }
+            FsckIssue::MissingThumbnail { id } => {
+                write!(f, "Document {} has no thumbnail", id)
+            }
+            FsckIssue::StaleThumbnail { id } => {
+                write!(f, "Thumbnail of document {} is older than its file", id)
+            }
+            FsckIssue::OrphanedThumbnail { file } => {
+                write!(f, "Thumbnail {:?} does not belong to any document", file)
+            }
+            FsckIssue::UnknownTags { id, tags } => {
+                write!(f, "Document {} references unknown tags {:?}", id, tags)
+            }
+        }
+    }
+}
+
+/// Result of a consistency check
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FsckReport {
+    pub checked_documents: usize,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl Index {
+    /// Cross-checks the file repository, the document repository, the full text index,
+    /// the thumbnails and the tags of all documents
+    pub fn fsck(&self) -> Result<FsckReport, IndexError> {
+        let known_tags: HashSet<TagId> = self.get_tags().iter().map(|t| t.id).collect();
+
+        // Hashing the files takes a while, so the repositories are only locked to collect the documents
+        let (file_ids, doc_ids, documents) = {
+            let file_repo = self
+                .file_repo
+                .read()
+                .map_err(|_| IndexError::Lock("file repository".into()))?;
+            let doc_repo = self
+                .doc_repo
+                .read()
+                .map_err(|_| IndexError::Lock("document repository".into()))?;
+            let file_ids: HashSet<DocId> = file_repo.get_doc_ids()?.into_iter().collect();
+            let doc_ids: HashSet<DocId> = doc_repo.get_doc_ids()?.into_iter().collect();
+            let mut ids: Vec<DocId> = file_ids.intersection(&doc_ids).copied().collect();
+            ids.sort_unstable();
+            let mut documents = vec![];
+            for id in ids {
+                documents.push((
+                    doc_repo.get_document(id)?,
+                    file_repo.get_document(id)?,
+                    doc_repo.is_indexed(id)?,
+                ));
+            }
+            (file_ids, doc_ids, documents)
+        };
+        let mut all_ids: Vec<DocId> = file_ids.union(&doc_ids).copied().collect();
+        all_ids.sort_unstable();
+
+        let mut report = FsckReport {
+            checked_documents: all_ids.len(),
+            issues: vec![],
+        };
+        for id in all_ids {
+            if !doc_ids.contains(&id) {
+                report.issues.push(FsckIssue::FileWithoutMetadata { id });
+            } else if !file_ids.contains(&id) {
+                report.issues.push(FsckIssue::MetadataWithoutFile { id });
+            }
+        }
+        for (doc, file, indexed) in documents {
+            let id = doc.id;
+            let actual = FileExtractor::get_file_hash(&file)?;
+            if actual != doc.hash {
+                report.issues.push(FsckIssue::HashMismatch {
+                    id,
+                    stored: doc.hash.clone(),
+                    actual,
+                });
+            }
+            if !indexed {
+                report.issues.push(FsckIssue::MissingIndexEntry { id });
+            }
+
+            let thumbnail = self.thumbnail_path(id);
+            if !thumbnail.exists() {
+                report.issues.push(FsckIssue::MissingThumbnail { id });
+            } else if std::fs::metadata(&thumbnail)?.modified()?
+                < std::fs::metadata(&file)?.modified()?
+            {
+                report.issues.push(FsckIssue::StaleThumbnail { id });
+            }
+
+            let unknown: Vec<TagId> = doc
+                .tags
+                .iter()
+                .filter(|t| !known_tags.contains(t))
+                .copied()
+                .collect();
+            if !unknown.is_empty() {
+                report
+                    .issues
+                    .push(FsckIssue::UnknownTags { id, tags: unknown });
+            }
+        }
+
+        // Only thumbnails named `<id>.jpg` are created by shreddr, other files are left alone
+        for entry in std::fs::read_dir(&self.thumbnails_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("jpg") {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<DocId>().ok());
+            if let Some(id) = id {
+                if !file_ids.contains(&id) && !doc_ids.contains(&id) {
+                    report
+                        .issues
+                        .push(FsckIssue::OrphanedThumbnail { file: path });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Runs a consistency check and repairs all found issues.
+    /// Returns the issues which could not be repaired.
+    pub fn repair(&self) -> Result<Vec<FsckIssue>, IndexError> {
+        let report = self.fsck()?;
+        let mut failed = vec![];
+        for issue in report.issues {
+            if let Err(e) = self.repair_issue(&issue) {
+                error!("Could not repair `{}`: {}", issue, e);
+                failed.push(issue);
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Repairs a single issue found by `fsck`
+    pub fn repair_issue(&self, issue: &FsckIssue) -> Result<(), IndexError> {
+        info!("Repairing: {}", issue);
+        match issue {
+            FsckIssue::FileWithoutMetadata { id } => self.adopt_file(*id),
+            FsckIssue::MetadataWithoutFile { id } => {
+                self.doc_repo
+                    .write()
+                    .map_err(|_| IndexError::Lock("document repository".into()))?
+                    .remove_document(*id)?;
+                let thumbnail = self.thumbnail_path(*id);
+                if thumbnail.exists() {
+                    std::fs::remove_file(thumbnail)?;
+                }
+                Ok(())
+            }
+            // The file was corrupted or replaced, which has to be checked by a user
+            FsckIssue::HashMismatch { .. } => Err(IndexError::NotRepairable(issue.to_string())),
+            FsckIssue::MissingIndexEntry { id } => self.reprocess_document(*id),
+            FsckIssue::MissingThumbnail { id } | FsckIssue::StaleThumbnail { id } => {
+                let file = self.get_document_path(*id)?;
+                ContentExtractor::render_thumbnail(&file, &self.thumbnail_path(*id));
+                Ok(())
+            }
+            FsckIssue::OrphanedThumbnail { file } => Ok(std::fs::remove_file(file)?),
+            FsckIssue::UnknownTags { id, tags } => {
+                let mut doc = self.get_document(*id)?;
+                doc.tags.retain(|t| !tags.contains(t));
+                self.update_doc_metadata(doc)
+            }
+        }
+    }
+
+    /// Creates the metadata of a stored file without metadata and processes it like a new import
+    fn adopt_file(&self, id: DocId) -> Result<(), IndexError> {
+        let file = self.get_document_path(id)?;
+        let original_filename = file
+            .file_name()
+            .ok_or(IndexError::OSString())?
+            .to_str()
+            .ok_or(IndexError::OSString())?
+            .to_string();
+        let file_metadata = ContentExtractor::extract_metadata(&file).unwrap_or_default();
+        let doc = DocumentData {
+            id,
+            title: file_metadata
+                .default_title()
+                .unwrap_or_else(|| original_filename.clone()),
+            original_filename,
+            body: None,
+            tags: vec![],
+            language: None,
+            imported_date: chrono::Utc::now(),
+            file_size: FileExtractor::get_file_size(&file)?,
+            hash: FileExtractor::get_file_hash(&file)?,
+            extracted: ExtractedData::default(),
+            provenance: Default::default(),
+            evidence: vec![],
+            correspondent: None,
+            document_type: None,
+            custom_fields: Default::default(),
+            file_metadata,
+        };
+        self.doc_repo
+            .write()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .insert_document(&doc)?;
+        self.reserve_id(id)?;
+        self.reprocess_document(id)
+    }
+
+    /// Makes sure that the given ID is not handed out again for new imports
+    fn reserve_id(&self, id: DocId) -> Result<(), IndexError> {
+        let id_file = self.data_dir.join("id.dat");
+        let curr_id: DocId = match std::fs::read(&id_file) {
+            Ok(c) => String::from_utf8_lossy(&c).parse()?,
+            Err(_) => 0,
+        };
+        if curr_id < id {
+            std::fs::write(id_file, id.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn thumbnail_path(&self, id: DocId) -> PathBuf {
+        self.thumbnails_dir.join(format!("{}.jpg", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FsckIssue;
+    use crate::index::document_repository::DocumentData;
+    use crate::index::tests::get_index;
+    use crate::metadata::file_extractor::FileExtractor;
+
+    #[test]
+    fn fsck_test() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let index = get_index(data_dir.path());
+        let file = data_dir.path().join("original.pdf");
+        std::fs::write(&file, "not a pdf").unwrap();
+
+        // Document 1 has no metadata, document 2 has no file,
+        // the file of document 3 was changed and it references an unknown tag
+        {
+            let mut file_repo = index.file_repo.write().unwrap();
+            file_repo.add_document(1, &file).unwrap();
+            file_repo.add_document(3, &file).unwrap();
+        }
+        {
+            let mut doc_repo = index.doc_repo.write().unwrap();
+            doc_repo.add_document(&DocumentData::test(2)).unwrap();
+            doc_repo
+                .add_document(&DocumentData::test(3).with_tags(vec![42]))
+                .unwrap();
+        }
+        let thumbnails_dir = data_dir.path().join("thumbnails");
+        for name in &["7.jpg", "cover.jpg", "notes.txt"] {
+            std::fs::write(thumbnails_dir.join(name), "").unwrap();
+        }
+
+        let issues = index.fsck().unwrap().issues;
+        let actual = FileExtractor::get_file_hash(&file).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                FsckIssue::FileWithoutMetadata { id: 1 },
+                FsckIssue::MetadataWithoutFile { id: 2 },
+                FsckIssue::HashMismatch {
+                    id: 3,
+                    stored: "hash3".into(),
+                    actual,
+                },
+                FsckIssue::MissingThumbnail { id: 3 },
+                FsckIssue::UnknownTags {
+                    id: 3,
+                    tags: vec![42]
+                },
+                FsckIssue::OrphanedThumbnail {
+                    file: thumbnails_dir.join("7.jpg")
+                },
+            ]
+        );
+
+        let remaining = index.repair().unwrap();
+        assert!(matches!(
+            remaining.as_slice(),
+            [FsckIssue::HashMismatch { id: 3, .. }]
+        ));
+        assert_eq!(index.get_document(1).unwrap().original_filename, "1.pdf");
+        assert!(index.get_document(2).is_err());
+        // The stored hash is kept
+        let doc = index.get_document(3).unwrap();
+        assert_eq!(doc.hash, "hash3");
+        assert!(doc.tags.is_empty());
+        assert!(!thumbnails_dir.join("7.jpg").exists());
+        assert!(thumbnails_dir.join("cover.jpg").exists());
+        assert!(thumbnails_dir.join("notes.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(data_dir.path().join("id.dat")).unwrap(),
+            "1"
+        );
+    }
+}
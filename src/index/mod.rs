@@ -1,17 +1,25 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
 pub mod file_repository;
-use file_repository::{FileRepository, FileRepositoryError};
+use file_repository::{FileRepository, FileRepositoryError, Rendition};
 
 pub mod document_repository;
+pub mod export;
+pub mod fsck;
 use document_repository::{
-    DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions,
+    DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions, MetadataField,
 };
 
+use crate::metadata::classifier::{tokenize, Classifier};
 use crate::metadata::content::ContentExtractor;
+use crate::metadata::correspondent::{CorrespondentConfig, CorrespondentId};
+use crate::metadata::custom_field::{CustomFieldConfig, CustomFieldId, CustomFieldValue};
+use crate::metadata::document_type::{DocumentTypeConfig, DocumentTypeId};
+use crate::metadata::extraction::{ExtractionPreview, ExtractionRuleConfig, ExtractionRuleId};
 use crate::metadata::file_extractor::{FileExtractError, FileExtractor};
-use crate::metadata::tag::{TagConfig, TagId, Tagger, TaggingError};
+use crate::metadata::tag::{MatcherConfig, TagConfig, TagId, TagNode, Tagger, TaggingError};
 
 /// Identification type of documents
 pub type DocId = u64;
@@ -26,6 +34,20 @@ pub struct Index {
     thumbnails_dir: PathBuf,
     tmp_dir: PathBuf,
     current_job: Arc<RwLock<Option<Job>>>,
+    job_reports: Arc<RwLock<std::collections::VecDeque<JobReport>>>,
+    /// A training job is queued, but has not started yet
+    training_queued: AtomicBool,
+}
+
+/// Side effects of an import, which have to be undone if the import fails
+#[derive(Default)]
+struct ImportTransaction {
+    id: Option<DocId>,
+    /// Whether the ID was counted in `id.dat` and can be given back
+    counted_id: bool,
+    file: bool,
+    thumbnail: Option<PathBuf>,
+    document: bool,
 }
 
 // Interface
@@ -39,6 +61,64 @@ pub struct Job {
 pub enum JobType {
     ImportFile { path: PathBuf, copy: bool },
     ReprocessFile { id: DocId, force_ocr: bool },
+    Repair,
+    Retag { tag: TagId },
+    TrainClassifier,
+    RemoveTag { tag: TagId },
+    MergeTags { from: TagId, into: TagId },
+    AssignCorrespondents,
+    RemoveCorrespondent { correspondent: CorrespondentId },
+    AssignDocumentTypes,
+    RemoveDocumentType { document_type: DocumentTypeId },
+    RemoveCustomField { field: CustomFieldId },
+    ExtractCustomFields,
+}
+
+/// Outcome of a finished job
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobReport {
+    pub job: JobType,
+    pub success: bool,
+    pub summary: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub finished: chrono::DateTime<chrono::Utc>,
+}
+
+/// Number of finished jobs, whose reports are kept
+const JOB_REPORT_COUNT: usize = 20;
+
+/// How the assignment of a tag to a document would change
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum AssignmentChange {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+/// Result of testing an unsaved matcher against a document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatchPreview {
+    pub id: DocId,
+    pub title: String,
+    pub matches: bool,
+    /// Text around the matches
+    pub snippets: Vec<String>,
+    /// Difference to the current assignment of the tag
+    pub change: AssignmentChange,
+    /// The tag was assigned or removed by a user and is not changed by the matcher
+    pub manual: bool,
+}
+
+/// Changes made by a retag job
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RetagReport {
+    pub tag: TagId,
+    /// Documents which gained the tag
+    pub added: usize,
+    /// Documents which lost the tag
+    pub removed: usize,
+    /// Documents without stored body, which could not be matched
+    pub skipped: usize,
 }
 
 impl std::fmt::Display for JobType {
@@ -52,6 +132,39 @@ impl std::fmt::Display for JobType {
             JobType::ReprocessFile { id, force_ocr: _ } => {
                 write!(f, "Currently reprocessing document '{:#?}'", id)
             }
+            JobType::Repair => write!(f, "Currently repairing inconsistencies"),
+            JobType::Retag { tag } => write!(f, "Currently applying tag {} to all documents", tag),
+            JobType::TrainClassifier => write!(f, "Currently training the tag classifier"),
+            JobType::RemoveTag { tag } => {
+                write!(f, "Currently removing tag {} from all documents", tag)
+            }
+            JobType::MergeTags { from, into } => {
+                write!(f, "Currently merging tag {} into tag {}", from, into)
+            }
+            JobType::AssignCorrespondents => {
+                write!(f, "Currently assigning correspondents to all documents")
+            }
+            JobType::RemoveCorrespondent { correspondent } => write!(
+                f,
+                "Currently removing correspondent {} from all documents",
+                correspondent
+            ),
+            JobType::AssignDocumentTypes => {
+                write!(f, "Currently assigning types to all documents")
+            }
+            JobType::RemoveDocumentType { document_type } => write!(
+                f,
+                "Currently removing document type {} from all documents",
+                document_type
+            ),
+            JobType::RemoveCustomField { field } => write!(
+                f,
+                "Currently removing custom field {} from all documents",
+                field
+            ),
+            JobType::ExtractCustomFields => {
+                write!(f, "Currently extracting custom fields of all documents")
+            }
         }
     }
 }
@@ -78,6 +191,8 @@ pub enum IndexError {
     ID(#[from] std::num::ParseIntError),
     #[error("error during file metadata extraction")]
     FileExtractor(#[from] FileExtractError),
+    #[error("`{0}` cannot be repaired automatically")]
+    NotRepairable(String),
 }
 
 impl Index {
@@ -107,6 +222,8 @@ impl Index {
             data_dir: cfg.data_dir.clone(),
             tmp_dir,
             current_job: Arc::new(RwLock::new(None)),
+            job_reports: Arc::new(RwLock::new(std::collections::VecDeque::new())),
+            training_queued: AtomicBool::new(false),
         })
     }
 
@@ -134,9 +251,39 @@ impl Index {
             .map_err(|_| IndexError::Lock("current_job".into()))?
             .replace(job.clone());
 
-        match &job.job {
+        let result = self.run_job(&job.job);
+        let report = JobReport {
+            job: job.job.clone(),
+            success: result.is_ok(),
+            summary: match &result {
+                Ok(summary) => summary.clone(),
+                Err(e) => format!("Failed: {}", e),
+            },
+            finished: chrono::Utc::now(),
+        };
+        info!("Finished job: {}", report.summary);
+        {
+            let mut reports = self
+                .job_reports
+                .write()
+                .map_err(|_| IndexError::Lock("job_reports".into()))?;
+            reports.push_front(report);
+            reports.truncate(JOB_REPORT_COUNT);
+        }
+        let finished = (*self.current_job)
+            .write()
+            .map_err(|_| IndexError::Lock("current_job".into()))?
+            .take()
+            .unwrap_or(job);
+        result.map(|_| finished)
+    }
+
+    /// Executes a job and returns a summary of the outcome
+    fn run_job(&self, job: &JobType) -> Result<String, IndexError> {
+        match job {
             JobType::ImportFile { path, copy } => {
-                self.import_document(path, *copy)?;
+                let id = self.import_document(path, *copy)?;
+                Ok(format!("Imported {:?} as document {}", path, id))
             }
             JobType::ReprocessFile { id, force_ocr } => {
                 if *force_ocr {
@@ -144,17 +291,136 @@ impl Index {
                 } else {
                     self.reprocess_document(*id)?;
                 }
+                Ok(format!("Reprocessed document {}", id))
             }
-        };
-        Ok((*self.current_job)
-            .write()
-            .map_err(|_| IndexError::Lock("current_job".into()))?
-            .take()
-            .unwrap())
+            JobType::Repair => {
+                let failed = self.repair()?;
+                if !failed.is_empty() {
+                    warn!("{} issues could not be repaired", failed.len());
+                }
+                Ok(format!(
+                    "Repair finished, {} issues remaining",
+                    failed.len()
+                ))
+            }
+            JobType::Retag { tag } => {
+                let report = self.retag_documents(*tag)?;
+                Ok(format!(
+                    "{} documents gained and {} documents lost tag {} ({} without body skipped)",
+                    report.added, report.removed, tag, report.skipped
+                ))
+            }
+            JobType::TrainClassifier => {
+                self.training_queued.store(false, Ordering::SeqCst);
+                let tags = self.train_classifier()?;
+                let (mut added, mut removed) = (0, 0);
+                for tag in &tags {
+                    let report = self.retag_documents(*tag)?;
+                    added += report.added;
+                    removed += report.removed;
+                }
+                Ok(format!(
+                    "Trained classifier for {} tags, {} tags added to and {} removed from documents",
+                    tags.len(),
+                    added,
+                    removed
+                ))
+            }
+            JobType::RemoveTag { tag } => {
+                let changed = self.remove_tag(*tag)?;
+                Ok(format!("Removed tag {} from {} documents", tag, changed))
+            }
+            JobType::MergeTags { from, into } => {
+                let changed = self.merge_tags(*from, *into)?;
+                Ok(format!(
+                    "Merged tag {} into tag {} in {} documents",
+                    from, into, changed
+                ))
+            }
+            JobType::AssignCorrespondents => {
+                let changed = self.assign_correspondents()?;
+                Ok(format!(
+                    "Changed the correspondent of {} documents",
+                    changed
+                ))
+            }
+            JobType::RemoveCorrespondent { correspondent } => {
+                let changed = self.remove_correspondent(*correspondent)?;
+                Ok(format!(
+                    "Removed correspondent {} from {} documents",
+                    correspondent, changed
+                ))
+            }
+            JobType::AssignDocumentTypes => {
+                let changed = self.assign_document_types()?;
+                Ok(format!("Changed the type of {} documents", changed))
+            }
+            JobType::RemoveDocumentType { document_type } => {
+                let changed = self.remove_document_type(*document_type)?;
+                Ok(format!(
+                    "Removed document type {} from {} documents",
+                    document_type, changed
+                ))
+            }
+            JobType::RemoveCustomField { field } => {
+                let changed = self.remove_custom_field(*field)?;
+                Ok(format!(
+                    "Removed custom field {} from {} documents",
+                    field, changed
+                ))
+            }
+            JobType::ExtractCustomFields => {
+                let changed = self.extract_custom_fields()?;
+                Ok(format!(
+                    "Changed the custom fields of {} documents",
+                    changed
+                ))
+            }
+        }
+    }
+
+    /// Checks if a training job has to be queued after the tags of a document were edited by hand.
+    /// Returns false if there are no automatic tags or a training job is already queued.
+    pub fn request_training(&self) -> bool {
+        let has_auto_tags = self
+            .tagger
+            .read()
+            .map(|t| !t.get_auto_tags().is_empty())
+            .unwrap_or(false);
+        has_auto_tags && !self.training_queued.swap(true, Ordering::SeqCst)
+    }
+
+    /// Returns the reports of the last finished jobs, newest first
+    pub fn get_job_reports(&self) -> Result<Vec<JobReport>, IndexError> {
+        Ok(self
+            .job_reports
+            .read()
+            .map_err(|_| IndexError::Lock("job_reports".into()))?
+            .iter()
+            .cloned()
+            .collect())
     }
 
-    /// Returns the next ID
-    fn get_next_id(&self) -> Result<DocId, IndexError> {
+    /// Updates the progress of the current job in percent
+    fn set_job_progress(&self, progress: i32) {
+        if let Ok(mut job) = self.current_job.write() {
+            if let Some(j) = job.as_mut() {
+                j.progress = progress;
+            }
+        }
+    }
+
+    /// Returns the next ID and whether it was counted in `id.dat`.
+    /// Document repositories shared between instances allocate the IDs themselves.
+    fn get_next_id(&self) -> Result<(DocId, bool), IndexError> {
+        if let Some(id) = self
+            .doc_repo
+            .write()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .next_id()?
+        {
+            return Ok((id, false));
+        }
         let id_file = self.data_dir.join("id.dat");
 
         //Create ID file if it does not exist
@@ -179,13 +445,39 @@ impl Index {
 
         //Write ID file
         std::fs::write(id_file, curr_id.to_string())?;
-        Ok(curr_id)
+        Ok((curr_id, true))
     }
 
     /// Imports a new document
     /// This function computes the hash value of each document and skips the file, if it is already contained in the repo
     /// To reimport/reprocess a document use the `reprocess_document` function
+    ///
+    /// The import is all-or-nothing: If any step fails, all side effects are undone
+    /// and the file is moved (or copied, if `copy` is set) to the `failed` directory together with an error report.
     pub fn import_document(&self, original_file: &Path, copy: bool) -> Result<DocId, IndexError> {
+        let mut transaction = ImportTransaction::default();
+        match self._import_document(original_file, copy, &mut transaction) {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                error!("Could not import file {:?}: {}", original_file, e);
+                self.rollback_import(&transaction);
+                if let Err(qe) = self.quarantine_file(original_file, copy, &e) {
+                    error!(
+                        "Could not move file {:?} to failed directory: {}",
+                        original_file, qe
+                    );
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn _import_document(
+        &self,
+        original_file: &Path,
+        copy: bool,
+        transaction: &mut ImportTransaction,
+    ) -> Result<DocId, IndexError> {
         let hash = FileExtractor::get_file_hash(original_file)?;
         if let Ok(Some(found_id)) = self
             .doc_repo
@@ -200,13 +492,16 @@ impl Index {
             return Ok(found_id);
         }
         info!("Importing file {:?}", original_file);
-        let id = self.get_next_id()?;
+        let (id, counted_id) = self.get_next_id()?;
+        transaction.id = Some(id);
+        transaction.counted_id = counted_id;
         let original_name = original_file.file_name().ok_or(IndexError::OSString())?;
         // Import
         (*self.file_repo)
             .write()
             .map_err(|_| IndexError::Lock("file repository".into()))?
             .add_document(id, original_file)?;
+        transaction.file = true;
 
         // Get file back from index
         let new_file = (*self.file_repo)
@@ -214,22 +509,31 @@ impl Index {
             .map_err(|_| IndexError::Lock("file repository".into()))?
             .get_document(id)?;
         // Extract
+        let archive_file = self.archive_tmp_file(id)?;
         let body = self
             .extractor
             .write()
             .map_err(|_| IndexError::Lock("extractor".into()))?
-            .extract_body(&new_file);
+            .extract_body(&new_file, &archive_file);
+        self.store_archive(id, &archive_file)?;
         //Create thumbnail
         let mut thumbnail_file = self.thumbnails_dir.join("tmp");
         thumbnail_file.set_file_name(format!("{}.jpg", id));
 
         ContentExtractor::render_thumbnail(&new_file, &thumbnail_file);
+        if thumbnail_file.exists() {
+            transaction.thumbnail = Some(thumbnail_file);
+        }
+
+        let file_metadata = ContentExtractor::extract_metadata(&new_file).unwrap_or_default();
 
         let original_filename: String =
             original_name.to_str().ok_or(IndexError::OSString())?.into();
         let mut doc_data = DocumentData {
             id,
-            title: original_filename.clone(),
+            title: file_metadata
+                .default_title()
+                .unwrap_or_else(|| original_filename.clone()),
             original_filename,
             body,
             tags: vec![],
@@ -238,6 +542,12 @@ impl Index {
             file_size: FileExtractor::get_file_size(original_file)?,
             hash,
             extracted: document_repository::ExtractedData::default(),
+            provenance: Default::default(),
+            evidence: vec![],
+            correspondent: None,
+            document_type: None,
+            custom_fields: Default::default(),
+            file_metadata,
         };
         //Tag
         match self
@@ -257,10 +567,11 @@ impl Index {
             },
         };
         //Doc Repo
+        transaction.document = true;
         self.doc_repo
             .write()
             .map_err(|_| IndexError::Lock("document repository".into()))?
-            .add_document(&doc_data)?;
+            .insert_document(&doc_data)?;
 
         if !copy {
             std::fs::remove_file(original_file)?;
@@ -268,8 +579,137 @@ impl Index {
         Ok(id)
     }
 
-    /// Updates the metadata of a given document
-    pub fn update_doc_metadata(&self, doc: DocumentData) -> Result<(), IndexError> {
+    /// Undoes all side effects of a failed import
+    fn rollback_import(&self, transaction: &ImportTransaction) {
+        let id = match transaction.id {
+            Some(id) => id,
+            None => return,
+        };
+        warn!("Rolling back import of document {}", id);
+        if transaction.document {
+            match self.doc_repo.write() {
+                Ok(mut repo) => {
+                    if let Err(e) = repo.remove_document(id) {
+                        error!("Could not remove document {} from repository: {}", id, e);
+                    }
+                }
+                Err(_) => error!("Could not get lock on document repository"),
+            }
+        }
+        if let Some(thumbnail) = &transaction.thumbnail {
+            if let Err(e) = std::fs::remove_file(thumbnail) {
+                error!("Could not remove thumbnail {:?}: {}", thumbnail, e);
+            }
+        }
+        if transaction.file {
+            match self.file_repo.write() {
+                Ok(mut repo) => {
+                    if let Err(e) = repo.remove_document(id) {
+                        error!("Could not remove file of document {}: {}", id, e);
+                    }
+                }
+                Err(_) => error!("Could not get lock on file repository"),
+            }
+        }
+        if let Ok(archive_file) = self.archive_tmp_file(id) {
+            if archive_file.exists() {
+                let _ = std::fs::remove_file(archive_file);
+            }
+        }
+        if transaction.counted_id {
+            if let Err(e) = self.release_id(id) {
+                error!("Could not release id {}: {}", id, e);
+            }
+        }
+    }
+
+    /// Gives back the ID of a failed import, if no other ID was requested afterwards
+    fn release_id(&self, id: DocId) -> Result<(), IndexError> {
+        let id_file = self.data_dir.join("id.dat");
+        let curr_id: DocId = String::from_utf8_lossy(&std::fs::read(&id_file)?).parse()?;
+        if curr_id == id {
+            std::fs::write(id_file, (id - 1).to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Moves a file which could not be imported into the `failed` directory
+    /// and writes an error report next to it.
+    /// If `copy` is set, the file was not handed over to shreddr and is only copied.
+    fn quarantine_file(
+        &self,
+        file: &Path,
+        copy: bool,
+        error: &IndexError,
+    ) -> Result<(), IndexError> {
+        if !file.is_file() {
+            return Ok(());
+        }
+        let failed_dir = self.data_dir.join("failed");
+        std::fs::create_dir_all(&failed_dir)?;
+        let name = file
+            .file_name()
+            .ok_or(IndexError::OSString())?
+            .to_string_lossy()
+            .into_owned();
+        let mut target = failed_dir.join(&name);
+        if target.exists() {
+            target = failed_dir.join(format!("{}_{}", chrono::Utc::now().timestamp(), name));
+        }
+        if copy {
+            std::fs::copy(file, &target)?;
+        } else if std::fs::rename(file, &target).is_err() {
+            // Rename does not work across file systems
+            std::fs::copy(file, &target)?;
+            std::fs::remove_file(file)?;
+        }
+
+        let mut report = target.as_os_str().to_owned();
+        report.push(".error.txt");
+        std::fs::write(
+            &report,
+            format!(
+                "File: {:?}\nDate: {}\nError: {}\nDetails: {:#?}\n",
+                file,
+                chrono::Utc::now().to_rfc3339(),
+                error,
+                error
+            ),
+        )?;
+        if copy {
+            warn!("Copied file {:?} to {:?}", file, target);
+        } else {
+            warn!("Moved file {:?} to {:?}", file, target);
+        }
+        Ok(())
+    }
+
+    /// Returns the temporary location, to which the OCR writes the archive rendition of a document
+    fn archive_tmp_file(&self, id: DocId) -> Result<PathBuf, IndexError> {
+        let ocr_dir = self.tmp_dir.join("ocr");
+        std::fs::create_dir_all(&ocr_dir)?;
+        Ok(ocr_dir.join(format!("{}.pdf", id)))
+    }
+
+    /// Moves an archive rendition created by the OCR into the file repository
+    fn store_archive(&self, id: DocId, archive_file: &Path) -> Result<(), IndexError> {
+        if !archive_file.exists() {
+            return Ok(());
+        }
+        (*self.file_repo)
+            .write()
+            .map_err(|_| IndexError::Lock("file repository".into()))?
+            .add_archive(id, archive_file)?;
+        std::fs::remove_file(archive_file)?;
+        Ok(())
+    }
+
+    /// Updates the metadata of a given document, the ancestors of its tags are assigned again
+    pub fn update_doc_metadata(&self, mut doc: DocumentData) -> Result<(), IndexError> {
+        self.tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .add_ancestors(&mut doc);
         self.doc_repo
             .write()
             .map_err(|_| IndexError::Lock("document repository".into()))?
@@ -284,11 +724,13 @@ impl Index {
             .map_err(|_| IndexError::Lock("file repository".into()))?
             .get_document(id)?;
         //Extract
+        let archive_file = self.archive_tmp_file(id)?;
         let body = self
             .extractor
             .write()
             .map_err(|_| IndexError::Lock("extractor".into()))?
-            .extract_body(&doc_path);
+            .extract_body(&doc_path, &archive_file);
+        self.store_archive(id, &archive_file)?;
         //Create thumbnail
         let mut thumbnail_file = self.thumbnails_dir.join("tmp");
         thumbnail_file.set_file_name(format!("{}.jpg", id));
@@ -300,9 +742,15 @@ impl Index {
             .map_err(|_| IndexError::Lock("document repository".into()))?
             .get_document(id)?;
 
-        //reset inferred data
+        //reset inferred data, values set by a user are restored after tagging
+        let previous = doc.clone();
         doc.tags = vec![];
+        doc.evidence = vec![];
+        doc.correspondent = None;
+        doc.document_type = None;
+        doc.custom_fields = Default::default();
         doc.extracted = Default::default();
+        doc.file_metadata = ContentExtractor::extract_metadata(&doc_path).unwrap_or_default();
         doc.body = body;
         //Tag
         match self
@@ -321,6 +769,11 @@ impl Index {
                 }
             },
         };
+        doc.keep_manual_values(&previous);
+        self.tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .add_ancestors(&mut doc);
         //Index
         self.doc_repo
             .write()
@@ -342,18 +795,26 @@ impl Index {
             .map_err(|_| IndexError::Lock("file repository".into()))?
             .get_document(id)?;
         //Extract
+        let archive_file = self.archive_tmp_file(id)?;
         let body = self
             .extractor
             .write()
             .map_err(|_| IndexError::Lock("extractor".into()))?
-            .ocr(&doc_path);
+            .ocr(&doc_path, &archive_file);
+        self.store_archive(id, &archive_file)?;
         //Create thumbnail
         let mut thumbnail_file = self.thumbnails_dir.join("tmp");
         thumbnail_file.set_file_name(format!("{}.jpg", id));
         ContentExtractor::render_thumbnail(&doc_path, &thumbnail_file);
-        //reset inferred data
+        //reset inferred data, values set by a user are restored after tagging
+        let previous = doc.clone();
         doc.tags = vec![];
+        doc.evidence = vec![];
+        doc.correspondent = None;
+        doc.document_type = None;
+        doc.custom_fields = Default::default();
         doc.extracted = Default::default();
+        doc.file_metadata = ContentExtractor::extract_metadata(&doc_path).unwrap_or_default();
         doc.body = body;
         //Tag
         match self
@@ -372,6 +833,11 @@ impl Index {
                 }
             },
         };
+        doc.keep_manual_values(&previous);
+        self.tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .add_ancestors(&mut doc);
         //Index
         self.doc_repo
             .write()
@@ -400,6 +866,25 @@ impl Index {
             .map_err(|e| e.into())
     }
 
+    /// Returns the storage location of the given rendition of a document.
+    /// Falls back to the original, if no archive rendition exists.
+    pub fn get_rendition_path(
+        &self,
+        id: DocId,
+        rendition: Rendition,
+    ) -> Result<PathBuf, IndexError> {
+        let repo = self
+            .file_repo
+            .read()
+            .map_err(|_| IndexError::Lock("file repository".into()))?;
+        if rendition == Rendition::Archive {
+            if let Some(archive) = repo.get_archive(id)? {
+                return Ok(archive);
+            }
+        }
+        repo.get_document(id).map_err(|e| e.into())
+    }
+
     /// Returns all documents in the given range without any filters
     #[allow(dead_code)]
     pub fn get_documents(
@@ -423,12 +908,26 @@ impl Index {
     }
 
     /// Returns a subset of documents after filtering them.
+    /// Filtering by a tag also matches documents with one of its descendants.
     pub fn get_sorted_documents(
         &self,
         offset: usize,
         count: usize,
-        filter: FilterOptions,
+        mut filter: FilterOptions,
     ) -> Result<Vec<DocumentData>, IndexError> {
+        {
+            // Documents tagged before a tag got its parent do not have the ancestor yet
+            let tagger = self
+                .tagger
+                .read()
+                .map_err(|_| IndexError::Lock("tagger".into()))?;
+            for group in filter.tags.iter_mut() {
+                *group = group
+                    .iter()
+                    .flat_map(|t| tagger.get_descendants(*t))
+                    .collect();
+            }
+        }
         self.doc_repo
             .read()
             .map_err(|_| IndexError::Lock("document repository".into()))?
@@ -453,9 +952,9 @@ impl Index {
     /////////////////          Tags         ///////////////////////
     ///////////////////////////////////////////////////////////////
 
-    /// Adds a new tag to the system.
-    /// Documents already stored in the system will not be tagged automatically, they have to be reprocessed.
-    pub fn add_tag(&self, tag: TagConfig) -> Result<(), IndexError> {
+    /// Adds a new tag to the system and returns its ID.
+    /// Documents already stored in the system will not be tagged automatically, use `retag_documents`.
+    pub fn add_tag(&self, tag: TagConfig) -> Result<TagId, IndexError> {
         self.tagger
             .write()
             .map_err(|_| IndexError::Lock("tagger".into()))?
@@ -463,9 +962,9 @@ impl Index {
             .map_err(|x| x.into())
     }
 
-    /// Adds or replaces a tag to the system.
-    /// Documents already stored in the system will not be tagged automatically, they have to be reprocessed.
-    pub fn add_or_replace_tag(&self, tag: TagConfig) -> Result<(), IndexError> {
+    /// Adds or replaces a tag to the system and returns its ID.
+    /// Documents already stored in the system will not be tagged automatically, use `retag_documents`.
+    pub fn add_or_replace_tag(&self, tag: TagConfig) -> Result<TagId, IndexError> {
         self.tagger
             .write()
             .map_err(|_| IndexError::Lock("tagger".into()))?
@@ -473,13 +972,242 @@ impl Index {
             .map_err(|x| x.into())
     }
 
-    /// Removes a tag from the repository
-    pub fn remove_tag(&self, id: TagId) -> Result<(), IndexError> {
-        let mut read = self
+    /// Applies the matcher of a tag to the stored bodies of all documents.
+    /// In contrast to reprocessing, nothing is extracted again.
+    /// Tags assigned or removed by a user are not changed.
+    pub fn retag_documents(&self, tag: TagId) -> Result<RetagReport, IndexError> {
+        let ids = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .get_doc_ids()?;
+        let mut report = RetagReport {
+            tag,
+            ..Default::default()
+        };
+        for (i, id) in ids.iter().enumerate() {
+            self.set_job_progress((i * 100 / ids.len()) as i32);
+            let mut doc = {
+                let repo = self
+                    .doc_repo
+                    .read()
+                    .map_err(|_| IndexError::Lock("document repository".into()))?;
+                let mut doc = repo.get_document(*id)?;
+                doc.body = repo.get_body(*id)?;
+                doc
+            };
+            if doc.body.as_ref().map(|b| b.is_empty()).unwrap_or(true) {
+                report.skipped += 1;
+                continue;
+            }
+            if doc.provenance.manual_tags.contains(&tag)
+                || doc.provenance.rejected_tags.contains(&tag)
+            {
+                continue;
+            }
+            let evidence = {
+                let tagger = self
+                    .tagger
+                    .read()
+                    .map_err(|_| IndexError::Lock("tagger".into()))?;
+                tagger.explain(tag, &doc)?
+            };
+            let matches = evidence.is_some();
+            let has_tag = doc.tags.contains(&tag);
+            // Ancestors of other tags are kept, but they are assigned directly if they match
+            let implied = doc.provenance.implied_tags.contains(&tag);
+            if matches && (!has_tag || implied) {
+                if !has_tag {
+                    report.added += 1;
+                }
+                doc.assign_tag(tag);
+            } else if !matches && has_tag && !implied {
+                doc.tags.retain(|t| *t != tag);
+                report.removed += 1;
+            } else if doc.evidence.iter().find(|e| e.tag == tag) == evidence.as_ref() {
+                continue;
+            }
+            doc.set_evidence(tag, evidence);
+            self.update_doc_metadata(doc)?;
+        }
+        info!(
+            "Retagged documents with tag {}: {} added, {} removed, {} skipped",
+            tag, report.added, report.removed, report.skipped
+        );
+        Ok(report)
+    }
+
+    /// Trains the classifier of all automatic tags with the tags assigned or removed by hand.
+    /// Documents which got a tag automatically are not used as examples for it.
+    /// Returns the automatic tags.
+    pub fn train_classifier(&self) -> Result<Vec<TagId>, IndexError> {
+        let tags = self
             .tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .get_auto_tags();
+        let mut documents = vec![];
+        {
+            let repo = self
+                .doc_repo
+                .read()
+                .map_err(|_| IndexError::Lock("document repository".into()))?;
+            if !tags.is_empty() {
+                let ids = repo.get_doc_ids()?;
+                for (i, id) in ids.iter().enumerate() {
+                    self.set_job_progress((i * 100 / ids.len()) as i32);
+                    if let Some(body) = repo.get_body(*id)? {
+                        documents.push((repo.get_document(*id)?, tokenize(&body)));
+                    }
+                }
+            }
+        }
+        let mut classifier = Classifier::default();
+        for tag in &tags {
+            let examples: Vec<_> = documents
+                .iter()
+                .filter_map(|(doc, tokens)| {
+                    if doc.provenance.manual_tags.contains(tag) {
+                        Some((tokens, true))
+                    } else if doc.tags.contains(tag) {
+                        None
+                    } else {
+                        Some((tokens, false))
+                    }
+                })
+                .collect();
+            classifier.train_tag(*tag, &examples);
+        }
+        self.tagger
             .write()
-            .map_err(|_| IndexError::Lock("tagger".into()))?;
-        read.remove_tag(id).map_err(|x| x.into())
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .set_classifier(classifier)?;
+        Ok(tags)
+    }
+
+    /// Tests an unsaved matcher against the stored bodies of all documents without changing them.
+    /// Returns all documents which match or currently have the given tag.
+    pub fn preview_matcher(
+        &self,
+        matcher: &MatcherConfig,
+        tag: Option<TagId>,
+    ) -> Result<Vec<MatchPreview>, IndexError> {
+        let repo = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?;
+        let built = self
+            .tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .build_matcher(matcher, tag)?;
+        let mut previews = vec![];
+        for id in repo.get_doc_ids()? {
+            let mut doc = repo.get_document(id)?;
+            doc.body = repo.get_body(id)?;
+            let snippets = match doc.body {
+                Some(_) => crate::metadata::tag::preview_matcher(&*built, &doc)?,
+                None => None,
+            };
+            let has_tag = tag.map(|t| doc.tags.contains(&t)).unwrap_or(false);
+            if snippets.is_none() && !has_tag {
+                continue;
+            }
+            let manual = tag
+                .map(|t| {
+                    doc.provenance.manual_tags.contains(&t)
+                        || doc.provenance.rejected_tags.contains(&t)
+                })
+                .unwrap_or(false);
+            let change = match (snippets.is_some(), has_tag) {
+                _ if manual => AssignmentChange::Unchanged,
+                (true, false) => AssignmentChange::Added,
+                (false, true) => AssignmentChange::Removed,
+                _ => AssignmentChange::Unchanged,
+            };
+            previews.push(MatchPreview {
+                id,
+                title: doc.title,
+                matches: snippets.is_some(),
+                snippets: snippets.unwrap_or_default(),
+                change,
+                manual,
+            });
+        }
+        Ok(previews)
+    }
+
+    /// Removes a tag from the repository and afterwards from all documents,
+    /// so that no document is tagged with it in between.
+    /// Returns the number of changed documents.
+    pub fn remove_tag(&self, id: TagId) -> Result<usize, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .remove_tag(id)?;
+        self.replace_tag(id, None)
+    }
+
+    /// Removes tag `from` from the repository and afterwards moves all of its documents to tag `into`.
+    /// Returns the number of changed documents.
+    pub fn merge_tags(&self, from: TagId, into: TagId) -> Result<usize, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .merge_tag(from, into)?;
+        self.replace_tag(from, Some(into))
+    }
+
+    /// Checks if tag `from` can be merged into tag `into`, before a merge job is queued
+    pub fn check_merge(&self, from: TagId, into: TagId) -> Result<(), IndexError> {
+        self.tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .check_merge(from, into)
+            .map_err(|x| x.into())
+    }
+
+    /// Renames a tag
+    pub fn rename_tag(&self, id: TagId, name: &str) -> Result<(), IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .rename_tag(id, name)
+            .map_err(|x| x.into())
+    }
+
+    /// Replaces a tag in all documents or removes it, if no replacement is given.
+    /// Ancestors which were only implied by the tag are removed when the documents are stored.
+    fn replace_tag(&self, tag: TagId, replacement: Option<TagId>) -> Result<usize, IndexError> {
+        let changed = self.change_documents(|doc| doc.replace_tag(tag, replacement))?;
+        info!(
+            "Replaced tag {} by {:?} in {} documents",
+            tag, replacement, changed
+        );
+        Ok(changed)
+    }
+
+    /// Applies a change to all documents and stores the documents for which `change` returns true.
+    /// Returns the number of changed documents.
+    fn change_documents(
+        &self,
+        change: impl Fn(&mut DocumentData) -> bool,
+    ) -> Result<usize, IndexError> {
+        let ids = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .get_doc_ids()?;
+        let mut changed = 0;
+        for (i, id) in ids.iter().enumerate() {
+            self.set_job_progress((i * 100 / ids.len()) as i32);
+            let mut doc = self.get_document(*id)?;
+            if change(&mut doc) {
+                self.update_doc_metadata(doc)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
     }
 
     /// Retrieves the tag configuration of a tag given its ID
@@ -494,6 +1222,17 @@ impl Index {
         read.get_tag(id)
     }
 
+    /// Retrieves all tags as tree
+    pub fn get_tag_tree(&self) -> Vec<TagNode> {
+        match self.tagger.read() {
+            Ok(r) => r.get_tag_tree(),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                vec![]
+            }
+        }
+    }
+
     /// Retrieves the configuration of all tags in the system
     pub fn get_tags(&self) -> Vec<TagConfig> {
         let read = match self.tagger.read() {
@@ -505,4 +1244,502 @@ impl Index {
         };
         read.get_tags()
     }
+
+    /// Adds or replaces a correspondent and returns its ID.
+    /// Documents are not assigned automatically, use `assign_correspondents`.
+    pub fn add_or_replace_correspondent(
+        &self,
+        correspondent: CorrespondentConfig,
+    ) -> Result<CorrespondentId, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .correspondents_mut()
+            .add_or_replace(correspondent)
+            .map_err(|e| e.into())
+    }
+
+    /// Assigns the correspondents again to all documents, whose correspondent was not set by a user.
+    /// Returns the number of changed documents.
+    pub fn assign_correspondents(&self) -> Result<usize, IndexError> {
+        let ids = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .get_doc_ids()?;
+        let mut changed = 0;
+        for (i, id) in ids.iter().enumerate() {
+            self.set_job_progress((i * 100 / ids.len()) as i32);
+            let mut doc = {
+                let repo = self
+                    .doc_repo
+                    .read()
+                    .map_err(|_| IndexError::Lock("document repository".into()))?;
+                let mut doc = repo.get_document(*id)?;
+                doc.body = repo.get_body(*id)?;
+                doc
+            };
+            if doc
+                .provenance
+                .manual_fields
+                .contains(&MetadataField::Correspondent)
+            {
+                continue;
+            }
+            let correspondent = self
+                .tagger
+                .read()
+                .map_err(|_| IndexError::Lock("tagger".into()))?
+                .correspondents()
+                .match_document(&doc)?;
+            if correspondent != doc.correspondent {
+                doc.correspondent = correspondent;
+                self.update_doc_metadata(doc)?;
+                changed += 1;
+            }
+        }
+        info!("Assigned correspondents, {} documents changed", changed);
+        Ok(changed)
+    }
+
+    /// Removes a correspondent from the repository and afterwards from all documents,
+    /// so that no document is assigned to it in between.
+    /// Returns the number of changed documents.
+    pub fn remove_correspondent(&self, id: CorrespondentId) -> Result<usize, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .correspondents_mut()
+            .remove(id)?;
+        self.change_documents(|doc| {
+            let assigned = doc.correspondent == Some(id);
+            if assigned {
+                doc.correspondent = None;
+            }
+            assigned
+        })
+    }
+
+    /// Retrieves the configuration of a correspondent given its ID
+    pub fn get_correspondent(&self, id: CorrespondentId) -> Option<CorrespondentConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.correspondents().get(id),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                None
+            }
+        }
+    }
+
+    /// Retrieves all correspondents sorted by name
+    pub fn get_correspondents(&self) -> Vec<CorrespondentConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.correspondents().get_all(),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Adds or replaces a document type and returns its ID.
+    /// Documents are not assigned automatically, use `assign_document_types`.
+    pub fn add_or_replace_document_type(
+        &self,
+        document_type: DocumentTypeConfig,
+    ) -> Result<DocumentTypeId, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .document_types_mut()
+            .add_or_replace(document_type)
+            .map_err(|e| e.into())
+    }
+
+    /// Assigns the types again to all documents, whose type was not set by a user.
+    /// Returns the number of changed documents.
+    pub fn assign_document_types(&self) -> Result<usize, IndexError> {
+        let ids = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .get_doc_ids()?;
+        let mut changed = 0;
+        for (i, id) in ids.iter().enumerate() {
+            self.set_job_progress((i * 100 / ids.len()) as i32);
+            let mut doc = {
+                let repo = self
+                    .doc_repo
+                    .read()
+                    .map_err(|_| IndexError::Lock("document repository".into()))?;
+                let mut doc = repo.get_document(*id)?;
+                doc.body = repo.get_body(*id)?;
+                doc
+            };
+            if doc
+                .provenance
+                .manual_fields
+                .contains(&MetadataField::DocumentType)
+            {
+                continue;
+            }
+            let document_type = self
+                .tagger
+                .read()
+                .map_err(|_| IndexError::Lock("tagger".into()))?
+                .document_types()
+                .match_document(&doc)?;
+            if document_type != doc.document_type {
+                doc.document_type = document_type;
+                self.update_doc_metadata(doc)?;
+                changed += 1;
+            }
+        }
+        info!("Assigned document types, {} documents changed", changed);
+        Ok(changed)
+    }
+
+    /// Removes a document type from the repository and afterwards from all documents,
+    /// so that no document is assigned to it in between.
+    /// Returns the number of changed documents.
+    pub fn remove_document_type(&self, id: DocumentTypeId) -> Result<usize, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .document_types_mut()
+            .remove(id)?;
+        self.change_documents(|doc| {
+            let assigned = doc.document_type == Some(id);
+            if assigned {
+                doc.document_type = None;
+            }
+            assigned
+        })
+    }
+
+    /// Retrieves the configuration of a document type given its ID
+    pub fn get_document_type(&self, id: DocumentTypeId) -> Option<DocumentTypeConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.document_types().get(id),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                None
+            }
+        }
+    }
+
+    /// Retrieves all document types sorted by name
+    pub fn get_document_types(&self) -> Vec<DocumentTypeConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.document_types().get_all(),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Adds or replaces the definition of a custom field and returns its ID
+    pub fn add_or_replace_custom_field(
+        &self,
+        field: CustomFieldConfig,
+    ) -> Result<CustomFieldId, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .custom_fields_mut()
+            .add_or_replace(field)
+            .map_err(|e| TaggingError::from(e).into())
+    }
+
+    /// Removes the definition of a custom field and afterwards its values from all documents,
+    /// so that no value is extracted for it in between.
+    /// Returns the number of changed documents.
+    pub fn remove_custom_field(&self, id: CustomFieldId) -> Result<usize, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .custom_fields_mut()
+            .remove(id)
+            .map_err(TaggingError::from)?;
+        self.change_documents(|doc| {
+            let manual = doc.provenance.manual_custom_fields.contains(&id);
+            doc.provenance.manual_custom_fields.retain(|f| *f != id);
+            doc.custom_fields.remove(&id).is_some() || manual
+        })
+    }
+
+    /// Checks that the custom field exists and the value has its type
+    pub fn check_custom_field(
+        &self,
+        id: CustomFieldId,
+        value: &CustomFieldValue,
+    ) -> Result<(), IndexError> {
+        self.tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .custom_fields()
+            .check_value(id, value)
+            .map_err(|e| TaggingError::from(e).into())
+    }
+
+    /// Retrieves the definition of a custom field given its ID
+    pub fn get_custom_field(&self, id: CustomFieldId) -> Option<CustomFieldConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.custom_fields().get(id),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                None
+            }
+        }
+    }
+
+    /// Retrieves the definitions of all custom fields sorted by ID
+    pub fn get_custom_fields(&self) -> Vec<CustomFieldConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.custom_fields().get_all(),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Adds or replaces an extraction rule and returns its ID.
+    /// Documents are not changed automatically, use `extract_custom_fields`.
+    pub fn add_or_replace_extraction_rule(
+        &self,
+        rule: ExtractionRuleConfig,
+    ) -> Result<ExtractionRuleId, IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .add_or_replace_extraction_rule(rule)
+            .map_err(|e| e.into())
+    }
+
+    /// Removes an extraction rule, the values extracted by it are kept
+    pub fn remove_extraction_rule(&self, id: ExtractionRuleId) -> Result<(), IndexError> {
+        self.tagger
+            .write()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .extraction_rules_mut()
+            .remove(id)
+            .map_err(|e| e.into())
+    }
+
+    /// Extracts the custom fields of all documents again, values set by a user are kept.
+    /// Returns the number of changed documents.
+    pub fn extract_custom_fields(&self) -> Result<usize, IndexError> {
+        let ids = self
+            .doc_repo
+            .read()
+            .map_err(|_| IndexError::Lock("document repository".into()))?
+            .get_doc_ids()?;
+        let mut changed = 0;
+        for (i, id) in ids.iter().enumerate() {
+            self.set_job_progress((i * 100 / ids.len()) as i32);
+            let mut doc = {
+                let repo = self
+                    .doc_repo
+                    .read()
+                    .map_err(|_| IndexError::Lock("document repository".into()))?;
+                let mut doc = repo.get_document(*id)?;
+                doc.body = repo.get_body(*id)?;
+                doc
+            };
+            let previous = doc.clone();
+            doc.custom_fields.clear();
+            self.tagger
+                .read()
+                .map_err(|_| IndexError::Lock("tagger".into()))?
+                .extract_custom_fields(&mut doc);
+            doc.keep_manual_values(&previous);
+            if doc.custom_fields != previous.custom_fields {
+                self.update_doc_metadata(doc)?;
+                changed += 1;
+            }
+        }
+        info!("Extracted custom fields, {} documents changed", changed);
+        Ok(changed)
+    }
+
+    /// Shows what each extraction rule extracts from a document
+    pub fn preview_extraction(&self, id: DocId) -> Result<Vec<ExtractionPreview>, IndexError> {
+        let doc = {
+            let repo = self
+                .doc_repo
+                .read()
+                .map_err(|_| IndexError::Lock("document repository".into()))?;
+            let mut doc = repo.get_document(id)?;
+            doc.body = repo.get_body(id)?;
+            doc
+        };
+        Ok(self
+            .tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?
+            .preview_extraction(&doc))
+    }
+
+    /// Retrieves the configuration of an extraction rule given its ID
+    pub fn get_extraction_rule(&self, id: ExtractionRuleId) -> Option<ExtractionRuleConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.extraction_rules().get(id),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                None
+            }
+        }
+    }
+
+    /// Retrieves all extraction rules sorted by ID
+    pub fn get_extraction_rules(&self) -> Vec<ExtractionRuleConfig> {
+        match self.tagger.read() {
+            Ok(r) => r.extraction_rules().get_all(),
+            Err(e) => {
+                error!("Could not lock tagger {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Exports the metadata of all documents matching the filter as CSV
+    pub fn export_csv(&self, filter: FilterOptions) -> Result<String, IndexError> {
+        let count = self.len()?;
+        let docs = self.get_sorted_documents(0, count, filter)?;
+        let tagger = self
+            .tagger
+            .read()
+            .map_err(|_| IndexError::Lock("tagger".into()))?;
+        Ok(export::to_csv(&docs, &tagger))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::document_repository::sqlite_repository::{
+        SqliteDocumentRepository, SqliteRepositoryError,
+    };
+    use super::document_repository::{
+        DocumentData, DocumentRepository, DocumentRepositoryError, FilterOptions,
+    };
+    use super::file_repository::local_repository::LocalFileRepository;
+    use super::{DocId, Index};
+    use std::path::Path;
+    use std::sync::{Arc, RwLock};
+
+    /// Creates an index in the given data directory, which stores the documents in SQLite
+    pub(crate) fn get_index(data_dir: &Path) -> Index {
+        let index_dir = data_dir.join("index");
+        std::fs::create_dir_all(&index_dir).unwrap();
+        get_index_with_repo(data_dir, SqliteDocumentRepository::new(&index_dir).unwrap())
+    }
+
+    fn get_index_with_repo<R: DocumentRepository + Send + Sync + 'static>(
+        data_dir: &Path,
+        doc_repo: R,
+    ) -> Index {
+        let cfg = crate::cli::ShreddrConfig {
+            data_dir: data_dir.into(),
+            ..Default::default()
+        };
+        let file_repo = LocalFileRepository::new(&data_dir.join("documents")).unwrap();
+        Index::new(
+            &cfg,
+            Arc::new(RwLock::new(file_repo)),
+            Arc::new(RwLock::new(doc_repo)),
+        )
+        .unwrap()
+    }
+
+    /// Stores documents in SQLite, but fails after adding a document
+    struct FailingRepository(SqliteDocumentRepository);
+
+    impl DocumentRepository for FailingRepository {
+        fn add_document(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+            self.0.add_document(doc)?;
+            Err(SqliteRepositoryError::Lock().into())
+        }
+        fn update_metadata(&mut self, doc: &DocumentData) -> Result<(), DocumentRepositoryError> {
+            self.0.update_metadata(doc)
+        }
+        fn remove_document(&mut self, id: DocId) -> Result<(), DocumentRepositoryError> {
+            self.0.remove_document(id)
+        }
+        fn get_document(&self, id: DocId) -> Result<DocumentData, DocumentRepositoryError> {
+            self.0.get_document(id)
+        }
+        fn contains_hash(&self, hash: &str) -> Result<Option<DocId>, DocumentRepositoryError> {
+            self.0.contains_hash(hash)
+        }
+        fn len(&self) -> Result<usize, DocumentRepositoryError> {
+            self.0.len()
+        }
+        fn get_documents(
+            &self,
+            offset: usize,
+            count: usize,
+        ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+            self.0.get_documents(offset, count)
+        }
+        fn get_filtered_documents(
+            &self,
+            offset: usize,
+            count: usize,
+            filter: FilterOptions,
+        ) -> Result<Vec<DocumentData>, DocumentRepositoryError> {
+            self.0.get_filtered_documents(offset, count, filter)
+        }
+        fn get_doc_ids(&self) -> Result<Vec<DocId>, DocumentRepositoryError> {
+            self.0.get_doc_ids()
+        }
+        fn get_body(&self, id: DocId) -> Result<Option<String>, DocumentRepositoryError> {
+            self.0.get_body(id)
+        }
+        fn is_indexed(&self, id: DocId) -> Result<bool, DocumentRepositoryError> {
+            self.0.is_indexed(id)
+        }
+    }
+
+    #[test]
+    fn import_rollback_test() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let index_dir = data_dir.path().join("index");
+        std::fs::create_dir_all(&index_dir).unwrap();
+        let index = get_index_with_repo(
+            data_dir.path(),
+            FailingRepository(SqliteDocumentRepository::new(&index_dir).unwrap()),
+        );
+        let consume_dir = tempfile::tempdir().unwrap();
+        let file = consume_dir.path().join("invoice.pdf");
+        std::fs::write(&file, "not a pdf").unwrap();
+
+        assert!(index.import_document(&file, true).is_err());
+        assert!(index
+            .file_repo
+            .read()
+            .unwrap()
+            .get_doc_ids()
+            .unwrap()
+            .is_empty());
+        assert_eq!(index.len().unwrap(), 0);
+        assert!(!data_dir.path().join("thumbnails").join("1.jpg").exists());
+        assert_eq!(
+            std::fs::read_to_string(data_dir.path().join("id.dat")).unwrap(),
+            "0"
+        );
+        // The file of the user is kept, a copy is stored with the error report
+        let failed_dir = data_dir.path().join("failed");
+        assert!(file.exists());
+        assert!(failed_dir.join("invoice.pdf").exists());
+        assert!(failed_dir.join("invoice.pdf.error.txt").exists());
+
+        // A file handed over to shreddr is moved
+        let file = consume_dir.path().join("contract.pdf");
+        std::fs::write(&file, "not a pdf either").unwrap();
+        assert!(index.import_document(&file, false).is_err());
+        assert!(!file.exists());
+        assert!(failed_dir.join("contract.pdf").exists());
+    }
 }
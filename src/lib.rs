@@ -0,0 +1,25 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+//Logging
+#[macro_use]
+extern crate log;
+
+//Indexing
+extern crate tantivy;
+pub mod cli;
+pub mod index;
+mod persistence;
+
+#[macro_use]
+extern crate serde_derive;
+
+//Webserver
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rust_embed;
+
+//Watcher
+pub mod metadata;
+pub mod server;
+pub mod watch;
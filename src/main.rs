@@ -1,35 +1,18 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-
 use std::path::PathBuf;
 use std::sync::Mutex;
 use std::sync::{Arc, RwLock};
 
 //Logging
-#[macro_use]
 extern crate log;
 extern crate simplelog;
 use simplelog::*;
 
-//Indexing
-extern crate tantivy;
-mod cli;
-mod index;
-
-#[macro_use]
-extern crate serde_derive;
-
-//Webserver
-#[macro_use]
-extern crate rocket;
-#[macro_use]
-extern crate rust_embed;
+use shreddr::{cli, index, server, watch};
 
-//Watcher
-mod metadata;
-mod server;
-mod watch;
-
-use index::document_repository::{local_repository::LocalDocumentRepository, DocumentRepository};
+use index::document_repository::{
+    local_repository::LocalDocumentRepository, postgres_repository::PostgresDocumentRepository,
+    sqlite_repository::SqliteDocumentRepository, DocumentRepository,
+};
 use index::file_repository::local_repository::LocalFileRepository;
 use index::JobType;
 
@@ -58,6 +41,14 @@ pub enum ShreddrError {
     FileRepoError(#[from] index::file_repository::local_repository::LocalFileRepositoryError),
     #[error("Could not initialize document repository: {0}")]
     LocalDocRepoError(#[from] index::document_repository::local_repository::IndexerError),
+    #[error("Could not initialize sqlite document repository: {0}")]
+    SqliteDocRepoError(
+        #[from] index::document_repository::sqlite_repository::SqliteRepositoryError,
+    ),
+    #[error("Could not initialize postgres document repository: {0}")]
+    PostgresDocRepoError(
+        #[from] index::document_repository::postgres_repository::PostgresRepositoryError,
+    ),
     #[error("Requires at least one tesseract language to function")]
     NoTesseractLanguagesError(),
     #[error("Error interacting with the document repository: {0}")]
@@ -88,14 +79,33 @@ fn main() -> Result<(), ShreddrError> {
         }
     };
 
-    // Init local document repository
+    // Init document repository
     let index_dir = cfg.data_dir.join("index");
     std::fs::create_dir_all(&index_dir).unwrap();
-    let doc_repo = match LocalDocumentRepository::new(&index_dir) {
-        Ok(r) => r,
-        Err(e) => {
-            println!("Could not initialize file repository: {}", &e);
-            return Err(e.into());
+    let doc_repo: Arc<RwLock<dyn DocumentRepository + Send + Sync>> = match cfg.document_repository
+    {
+        cli::DocumentRepositoryType::Local => match LocalDocumentRepository::new(&index_dir) {
+            Ok(r) => Arc::new(RwLock::new(r)),
+            Err(e) => {
+                println!("Could not initialize document repository: {}", &e);
+                return Err(e.into());
+            }
+        },
+        cli::DocumentRepositoryType::Sqlite => match SqliteDocumentRepository::new(&index_dir) {
+            Ok(r) => Arc::new(RwLock::new(r)),
+            Err(e) => {
+                println!("Could not initialize document repository: {}", &e);
+                return Err(e.into());
+            }
+        },
+        cli::DocumentRepositoryType::Postgres => {
+            match PostgresDocumentRepository::new(&cfg.postgres) {
+                Ok(r) => Arc::new(RwLock::new(r)),
+                Err(e) => {
+                    println!("Could not initialize document repository: {}", &e);
+                    return Err(e.into());
+                }
+            }
         }
     };
 
@@ -104,18 +114,17 @@ fn main() -> Result<(), ShreddrError> {
         return Err(ShreddrError::NoTesseractLanguagesError());
     }
 
-    let reindexing_ids = match doc_repo.requires_reindex() {
-        true => doc_repo.get_doc_ids()?,
-        false => vec![],
+    let reindexing_ids = {
+        let repo = doc_repo.read().map_err(|_| ShreddrError::LockError())?;
+        match repo.requires_reindex() {
+            true => repo.get_doc_ids()?,
+            false => vec![],
+        }
     };
 
     // Init backend
     let index = Arc::new(
-        match index::Index::new(
-            &cfg,
-            Arc::new(RwLock::new(file_repo)),
-            Arc::new(RwLock::new(doc_repo)),
-        ) {
+        match index::Index::new(&cfg, Arc::new(RwLock::new(file_repo)), doc_repo) {
             Ok(i) => i,
             Err(e) => {
                 println!("Could not create document index: {}", e);
@@ -196,7 +205,7 @@ fn main() -> Result<(), ShreddrError> {
             Ok(_) => {}
             Err(e) => println!("Could not initialize logger {}", e),
         };
-        cli::run_shell(index);
+        cli::run_shell(index, doc_sender);
     }
 
     rt.shutdown_timeout(std::time::Duration::from_millis(100));
@@ -0,0 +1,169 @@
+use crate::metadata::custom_field::Money;
+use crate::metadata::extraction::{parse_decimal, CURRENCY_SYMBOLS};
+use regex::{Regex, RegexBuilder};
+
+/// Currencies which are recognised next to an amount
+const CURRENCIES: &str = r"€|\$|£|¥|\b(?:EURO|Euro|EUR|USD|GBP|CHF|JPY)\b";
+/// Numbers in English (`1,234.56`) and European (`1.234,56`) notation
+const NUMBER: &str = r"-?\b(?:\d{1,3}(?:[.,']\d{3})+(?:[.,]\d{1,2})?|\d+(?:[.,]\d{1,2})?)";
+
+/// Words before the total of an invoice or receipt
+const TOTAL_KEYWORDS: &[&str] = &[
+    "gesamtbetrag",
+    "gesamtsumme",
+    "rechnungsbetrag",
+    "endbetrag",
+    "zahlbetrag",
+    "summe",
+    "total",
+    "amount due",
+    "balance due",
+];
+/// Maximum number of characters between a keyword and the total
+const TOTAL_DISTANCE: usize = 60;
+
+/// Finds amounts of money in the body of a document
+pub struct AmountExtractor {
+    amount: Regex,
+    total: Regex,
+}
+
+impl Default for AmountExtractor {
+    fn default() -> Self {
+        AmountExtractor::new()
+    }
+}
+
+impl AmountExtractor {
+    pub fn new() -> AmountExtractor {
+        let amount = format!(
+            r"(?:(?P<before>{c})\s?(?P<n1>{n}))|(?:(?P<n2>{n})\s?(?P<after>{c}))",
+            c = CURRENCIES,
+            n = NUMBER
+        );
+        let total = format!(r"\b(?:{})\b", TOTAL_KEYWORDS.join("|"));
+        AmountExtractor {
+            amount: Regex::new(&amount).expect("invalid amount pattern"),
+            total: RegexBuilder::new(&total)
+                .case_insensitive(true)
+                .build()
+                .expect("invalid total pattern"),
+        }
+    }
+
+    /// Returns all distinct amounts with a currency in the order they appear,
+    /// and the likely total, i.e. the largest amount directly after a word like `Gesamtbetrag` or `Total`
+    pub fn extract(&self, body: &str) -> (Vec<Money>, Option<Money>) {
+        let found: Vec<(usize, Money)> = self
+            .amount
+            .captures_iter(body)
+            .filter_map(|c| {
+                let currency = c.name("before").or_else(|| c.name("after"))?.as_str();
+                let number = c.name("n1").or_else(|| c.name("n2"))?.as_str();
+                let money = Money {
+                    cents: parse_decimal(number)?.cents()?,
+                    currency: currency_code(currency),
+                };
+                Some((c.get(0)?.start(), money))
+            })
+            .collect();
+
+        let total = self
+            .total
+            .find_iter(body)
+            .filter_map(|keyword| {
+                found
+                    .iter()
+                    .find(|(start, _)| {
+                        *start >= keyword.end()
+                            && *start - keyword.end() <= TOTAL_DISTANCE
+                            && !body[keyword.end()..*start].contains('\n')
+                    })
+                    .map(|(_, money)| money)
+            })
+            .max_by_key(|money| money.cents)
+            .cloned();
+
+        let mut amounts: Vec<Money> = vec![];
+        for (_, money) in found {
+            if !amounts.contains(&money) {
+                amounts.push(money);
+            }
+        }
+        (amounts, total)
+    }
+}
+
+/// ISO 4217 code of a currency symbol or name
+fn currency_code(currency: &str) -> String {
+    match CURRENCY_SYMBOLS
+        .iter()
+        .find(|(symbol, _)| *symbol == currency)
+    {
+        Some((_, code)) => code.to_string(),
+        None if currency.eq_ignore_ascii_case("euro") => "EUR".into(),
+        None => currency.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AmountExtractor;
+    use crate::metadata::custom_field::Money;
+
+    fn money(cents: i64, currency: &str) -> Money {
+        Money {
+            cents,
+            currency: currency.into(),
+        }
+    }
+
+    #[test]
+    fn amounts_test() {
+        let extractor = AmountExtractor::new();
+        let (amounts, _) = extractor.extract(
+            "1.234,56 € und €1,234.56, EUR 12.50, 99,90 EUR, $ 5, CHF 1'000.00, 1.000 Euro, -3,20 €",
+        );
+        assert_eq!(
+            amounts,
+            vec![
+                money(123456, "EUR"),
+                money(1250, "EUR"),
+                money(9990, "EUR"),
+                money(500, "USD"),
+                money(100000, "CHF"),
+                money(100000, "EUR"),
+                money(-320, "EUR"),
+            ]
+        );
+        // Numbers without a currency are no amounts
+        let (amounts, total) = extractor.extract("Kundennummer 12345, Artikel 3, 19 % MwSt");
+        assert!(amounts.is_empty());
+        assert_eq!(total, None);
+        // Currency codes have to be whole words
+        let (amounts, _) = extractor.extract("REURO 12 and 5 EURUSD");
+        assert!(amounts.is_empty());
+    }
+
+    #[test]
+    fn total_test() {
+        let extractor = AmountExtractor::new();
+        let invoice = "Pos. 1  Beratung  2  50,00 €  100,00 €\n\
+                       Zwischensumme  100,00 €\n\
+                       MwSt. 19 %  19,00 €\n\
+                       Summe netto 100,00 €\n\
+                       Gesamtbetrag: 119,00 €\n\
+                       Zahlbar bis zum 31.03.2021";
+        let (amounts, total) = extractor.extract(invoice);
+        assert_eq!(total, Some(money(11900, "EUR")));
+        assert_eq!(amounts.len(), 4);
+
+        let receipt =
+            "Coffee $3.50\nMuffin $2.25\nSubtotal $5.75\nTax $0.46\nTOTAL $6.21\nCash $10.00";
+        assert_eq!(extractor.extract(receipt).1, Some(money(621, "USD")));
+
+        // Totals are on the same line as their keyword
+        let (_, total) = extractor.extract("Total\nThank you for your order! 10.00 EUR");
+        assert_eq!(total, None);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::metadata::tag::TagId;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+//Error Handling
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum ClassifierError {
+    #[error("could not read/write classifier file")]
+    IO(#[from] std::io::Error),
+    #[error("could not (de)serialize classifier")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// Number of documents tagged by hand, which are needed before a tag is predicted
+pub const MIN_EXAMPLES: usize = 3;
+/// Tokens found in fewer training documents are not part of the model
+const MIN_DOCUMENT_FREQUENCY: u32 = 2;
+const MIN_TOKEN_LENGTH: usize = 3;
+const MAX_TOKEN_LENGTH: usize = 30;
+
+/// Splits a text into the set of lowercase words used by the classifier
+pub fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| {
+            let len = t.chars().count();
+            (MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&len)
+                && !t.chars().all(|c| c.is_numeric())
+        })
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Naive Bayes model deciding whether a single tag applies to a document.
+/// Index 0 counts documents without the tag, index 1 documents with the tag.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct TagModel {
+    /// Number of training documents
+    documents: [u32; 2],
+    /// Number of training documents containing a token
+    tokens: HashMap<String, [u32; 2]>,
+    /// Sum of all token counts
+    totals: [u64; 2],
+}
+
+impl TagModel {
+    fn train(examples: &[(&HashSet<String>, bool)]) -> TagModel {
+        let mut model = TagModel::default();
+        for (tokens, has_tag) in examples {
+            let class = *has_tag as usize;
+            model.documents[class] += 1;
+            for token in tokens.iter() {
+                model.tokens.entry(token.clone()).or_default()[class] += 1;
+            }
+        }
+        model
+            .tokens
+            .retain(|_, counts| counts[0] + counts[1] >= MIN_DOCUMENT_FREQUENCY);
+        for counts in model.tokens.values() {
+            model.totals[0] += counts[0] as u64;
+            model.totals[1] += counts[1] as u64;
+        }
+        model
+    }
+
+    /// Probability that the tag applies to a document with the given tokens
+    fn predict(&self, tokens: &HashSet<String>) -> f64 {
+        let vocabulary = self.tokens.len() as f64;
+        let all_documents = (self.documents[0] + self.documents[1]) as f64;
+        let mut scores = [0.0; 2];
+        for (class, score) in scores.iter_mut().enumerate() {
+            *score = (self.documents[class] as f64 / all_documents).ln();
+            let denominator = self.totals[class] as f64 + vocabulary;
+            for token in tokens {
+                if let Some(counts) = self.tokens.get(token) {
+                    // Laplace smoothing
+                    *score += ((counts[class] as f64 + 1.0) / denominator).ln();
+                }
+            }
+        }
+        1.0 / (1.0 + (scores[0] - scores[1]).exp())
+    }
+}
+
+/// Locally trained classifier predicting tags from the words of a document
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Classifier {
+    models: HashMap<TagId, TagModel>,
+}
+
+impl Classifier {
+    /// Loads the classifier, returns an untrained classifier if the file does not exist
+    pub fn load(file: &Path) -> Result<Classifier, ClassifierError> {
+        if !file.exists() {
+            return Ok(Classifier::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(file)?)?)
+    }
+
+    pub fn store(&self, file: &Path) -> Result<(), ClassifierError> {
+        let tmp_file = file.with_extension("tmp");
+        std::fs::write(&tmp_file, serde_json::to_vec(self)?)?;
+        std::fs::rename(tmp_file, file)?;
+        Ok(())
+    }
+
+    /// Trains the model of a tag with documents and whether they have the tag.
+    /// Tags with too few examples are not trained.
+    pub fn train_tag(&mut self, tag: TagId, examples: &[(&HashSet<String>, bool)]) {
+        let positive = examples.iter().filter(|(_, t)| *t).count();
+        if positive < MIN_EXAMPLES || positive == examples.len() {
+            info!(
+                "Not enough examples to train tag {} ({} of {} documents tagged)",
+                tag,
+                positive,
+                examples.len()
+            );
+            self.models.remove(&tag);
+            return;
+        }
+        let model = TagModel::train(examples);
+        info!(
+            "Trained tag {} with {} documents and {} tokens",
+            tag,
+            examples.len(),
+            model.tokens.len()
+        );
+        self.models.insert(tag, model);
+    }
+
+    /// Returns the probability that the tag applies to the text, if the tag is trained
+    pub fn predict(&self, tag: TagId, text: &str) -> Option<f64> {
+        self.models
+            .get(&tag)
+            .map(|model| model.predict(&tokenize(text)))
+    }
+
+    pub fn is_trained(&self, tag: TagId) -> bool {
+        self.models.contains_key(&tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, Classifier};
+
+    #[test]
+    fn classifier_test() {
+        let texts = [
+            (
+                "Stromrechnung der Stadtwerke, Verbrauch in kWh, Abschlag",
+                true,
+            ),
+            ("Stadtwerke Abschlag Strom Verbrauch Zählerstand kWh", true),
+            ("Jahresabrechnung Strom Stadtwerke Verbrauch kWh", true),
+            ("Telekom Rechnung Mobilfunk Datenvolumen Tarif", false),
+            ("Mobilfunk Vertrag Telekom Tarif Rufnummer", false),
+            ("Versicherung Beitrag Police Versicherungsnummer", false),
+            ("Versicherung Police Schaden Beitrag Tarif", false),
+        ];
+        let tokens: Vec<_> = texts.iter().map(|(t, _)| tokenize(t)).collect();
+        let examples: Vec<_> = tokens
+            .iter()
+            .zip(texts.iter())
+            .map(|(tokens, (_, has_tag))| (tokens, *has_tag))
+            .collect();
+
+        let mut classifier = Classifier::default();
+        classifier.train_tag(1, &examples);
+        assert!(classifier.is_trained(1));
+        assert!(classifier.predict(1, "Stadtwerke Abschlag kWh").unwrap() > 0.9);
+        assert!(classifier.predict(1, "Telekom Mobilfunk Tarif").unwrap() < 0.1);
+        assert!(classifier.predict(2, "Stadtwerke").is_none());
+
+        // Too few examples
+        classifier.train_tag(1, &examples[2..]);
+        assert!(!classifier.is_trained(1));
+    }
+
+    #[test]
+    fn tokenize_test() {
+        let tokens = tokenize("Rechnung Nr. 2021-42: IBAN DE12 für Müller");
+        assert!(tokens.contains("rechnung"));
+        assert!(tokens.contains("müller"));
+        assert!(tokens.contains("iban"));
+        assert!(tokens.contains("für"));
+        assert!(!tokens.contains("2021"));
+        assert!(!tokens.contains("nr"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::index::document_repository::FileMetadata;
 use std::path::Path;
 
 mod pdf;
@@ -16,13 +17,14 @@ impl ContentExtractor {
     }
 
     /// Extracts the text from a file.
-    /// If the file does not contain any text, the text is extracted by OCR.
-    pub fn extract_body(&self, file: &Path) -> Option<String> {
+    /// If the file does not contain any text, the text is extracted by OCR
+    /// and a searchable version of the file is written to `archive_file`. The file itself is never modified.
+    pub fn extract_body(&self, file: &Path, archive_file: &Path) -> Option<String> {
         if let Some(ext) = ContentExtractor::extract_extension(file) {
             let text = self._extract_body(file, &ext);
             if text.is_none() || text.as_ref().unwrap().is_empty() {
                 info!("Could not extract text => OCR");
-                return self._ocr(file, &ext);
+                return self._ocr(file, archive_file, &ext);
             } else {
                 return text;
             }
@@ -45,26 +47,32 @@ impl ContentExtractor {
         }
     }
 
-    /// Forces extraction of the body via OCR
-    pub fn ocr(&self, file: &Path) -> Option<String> {
+    /// Forces extraction of the body via OCR.
+    /// The searchable version of the file is written to `archive_file`.
+    pub fn ocr(&self, file: &Path, archive_file: &Path) -> Option<String> {
         if let Some(ext) = ContentExtractor::extract_extension(file) {
-            self._ocr(file, &ext)
+            self._ocr(file, archive_file, &ext)
         } else {
             None
         }
     }
 
-    fn _ocr(&self, file: &Path, extension: &str) -> Option<String> {
+    fn _ocr(&self, file: &Path, archive_file: &Path, extension: &str) -> Option<String> {
         // OCR file
-        let _res = match extension {
-            "pdf" => pdf::renderer::ocr_file(file, &self.tesseract_languages),
+        let res = match extension {
+            "pdf" => pdf::renderer::ocr_file(file, archive_file, &self.tesseract_languages),
             _ => {
                 error!("OCR not supported for file `{:#?}`", file);
                 return None;
             }
         };
+        if res.is_err() {
+            return None;
+        }
 
-        let text = self._extract_body(file, extension).unwrap_or_default();
+        let text = self
+            ._extract_body(archive_file, extension)
+            .unwrap_or_default();
 
         if text.is_empty() {
             error!("OCR attempt did not yield text.");
@@ -73,6 +81,20 @@ impl ContentExtractor {
         Some(text)
     }
 
+    /// Reads the metadata embedded in a file, e.g. its title and author
+    pub fn extract_metadata(file: &Path) -> Option<FileMetadata> {
+        match ContentExtractor::extract_extension(file)?.as_str() {
+            "pdf" => pdf::metadata::extract_metadata(file),
+            _ => {
+                error!(
+                    "Trying to read metadata of unsupported file format `{:#?}`",
+                    file
+                );
+                None
+            }
+        }
+    }
+
     /// Renders a thumbnail of the image
     pub fn render_thumbnail(file: &Path, thumbnail_file: &Path) {
         debug!(
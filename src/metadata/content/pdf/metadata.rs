@@ -0,0 +1,328 @@
+use crate::index::document_repository::FileMetadata;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use lopdf::{Dictionary, Document, Object};
+use regex::Regex;
+use std::path::Path;
+
+/// Properties of the XMP metadata, which are read
+const XMP_PROPERTIES: &[&str] = &[
+    "dc:title",
+    "dc:creator",
+    "dc:description",
+    "pdf:Keywords",
+    "dc:subject",
+    "xmp:CreateDate",
+];
+
+lazy_static! {
+    static ref XMP_READER: XmpReader = XmpReader::new();
+}
+
+/// Reads the Info dictionary, the XMP metadata, the number of pages and the version of a PDF file.
+/// Values of the Info dictionary take precedence over the XMP metadata.
+pub fn extract_metadata(file: &Path) -> Option<FileMetadata> {
+    debug!("Reading metadata of file {:?}", file);
+    let doc = match std::panic::catch_unwind(|| Document::load(file)) {
+        Ok(Ok(doc)) => doc,
+        Ok(Err(e)) => {
+            error!("Could not read metadata of file {:#?}: {}", file, e);
+            return None;
+        }
+        Err(e) => {
+            error!("PDF parser panicked for file {:#?}: {:#?}", file, e);
+            return None;
+        }
+    };
+    let mut metadata = FileMetadata {
+        page_count: Some(doc.get_pages().len() as u32),
+        pdf_version: Some(doc.version.clone()),
+        ..Default::default()
+    };
+
+    let info = get(&doc.trailer, b"Info").and_then(|o| dictionary(&doc, o));
+    if let Some(info) = info {
+        metadata.title = text(&doc, info, b"Title");
+        metadata.author = text(&doc, info, b"Author");
+        metadata.subject = text(&doc, info, b"Subject");
+        metadata.keywords = text(&doc, info, b"Keywords")
+            .map(|k| split_keywords(&k))
+            .unwrap_or_default();
+        metadata.creation_date = text(&doc, info, b"CreationDate").and_then(|d| parse_pdf_date(&d));
+    }
+    if let Some(xmp) = xmp_packet(&doc) {
+        merge_xmp(&mut metadata, &xmp);
+    }
+    Some(metadata)
+}
+
+fn get<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.iter()
+        .find(|(k, _)| k.as_slice() == key)
+        .map(|(_, v)| v)
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        o => Some(o),
+    }
+}
+
+fn dictionary<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    match resolve(doc, object)? {
+        Object::Dictionary(dict) => Some(dict),
+        _ => None,
+    }
+}
+
+/// Returns a text string of a dictionary, empty strings are ignored
+fn text(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<String> {
+    match resolve(doc, get(dict, key)?)? {
+        Object::String(bytes, _) => Some(decode_text(bytes)).filter(|t| !t.is_empty()),
+        _ => None,
+    }
+}
+
+/// Decodes a PDF text string, which is either UTF-16BE with a byte order mark or PDFDocEncoding
+fn decode_text(bytes: &[u8]) -> String {
+    let text = if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(&bytes[3..]).into_owned()
+    } else {
+        // PDFDocEncoding equals Latin-1 for all printable characters but a few symbols
+        bytes.iter().map(|b| *b as char).collect()
+    };
+    text.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .to_string()
+}
+
+fn split_keywords(keywords: &str) -> Vec<String> {
+    keywords
+        .split(&[',', ';'][..])
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+/// Parses dates like `D:20210315120000+01'00'`, all parts after the year are optional
+fn parse_pdf_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim().trim_start_matches("D:");
+    let digits: String = date.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let part = |start: usize, default: u32| {
+        digits
+            .get(start..start + 2)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(default)
+    };
+    let local = NaiveDate::from_ymd_opt(digits[..4].parse().ok()?, part(4, 1), part(6, 1))?
+        .and_hms_opt(part(8, 0), part(10, 0), part(12, 0))?;
+
+    let zone = &date[digits.len()..];
+    let offset_digits: String = zone.chars().filter(|c| c.is_ascii_digit()).collect();
+    let offset_part = |start: usize| -> i32 {
+        offset_digits
+            .get(start..start + 2)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0)
+    };
+    let offset = offset_part(0) * 3600 + offset_part(2) * 60;
+    let offset = if zone.starts_with('-') {
+        -offset
+    } else {
+        offset
+    };
+    FixedOffset::east_opt(offset)?
+        .from_local_datetime(&local)
+        .single()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Returns the XMP metadata stream of the document catalog
+fn xmp_packet(doc: &Document) -> Option<String> {
+    let catalog = dictionary(doc, get(&doc.trailer, b"Root")?)?;
+    match resolve(doc, get(catalog, b"Metadata")?)? {
+        Object::Stream(stream) => {
+            let content = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            Some(String::from_utf8_lossy(&content).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Fills the values missing in the Info dictionary from an XMP packet
+fn merge_xmp(metadata: &mut FileMetadata, xmp: &str) {
+    if metadata.title.is_none() {
+        metadata.title = XMP_READER.values(xmp, "dc:title").into_iter().next();
+    }
+    if metadata.author.is_none() {
+        let creators = XMP_READER.values(xmp, "dc:creator");
+        if !creators.is_empty() {
+            metadata.author = Some(creators.join(", "));
+        }
+    }
+    if metadata.subject.is_none() {
+        metadata.subject = XMP_READER.values(xmp, "dc:description").into_iter().next();
+    }
+    if metadata.keywords.is_empty() {
+        metadata.keywords = match XMP_READER.values(xmp, "pdf:Keywords").first() {
+            Some(keywords) => split_keywords(keywords),
+            None => XMP_READER.values(xmp, "dc:subject"),
+        };
+    }
+    if metadata.creation_date.is_none() {
+        metadata.creation_date = XMP_READER
+            .values(xmp, "xmp:CreateDate")
+            .first()
+            .and_then(|d| parse_xmp_date(d));
+    }
+}
+
+/// Reads the values of the XMP properties with regular expressions, which are compiled once
+struct XmpReader {
+    /// Patterns of a property as attribute and as element
+    properties: Vec<(&'static str, Regex, Regex)>,
+    item: Regex,
+}
+
+impl XmpReader {
+    fn new() -> XmpReader {
+        XmpReader {
+            properties: XMP_PROPERTIES
+                .iter()
+                .map(|property| {
+                    let escaped = regex::escape(property);
+                    let attribute = Regex::new(&format!(r#"\b{}\s*=\s*"([^"]*)""#, escaped))
+                        .expect("invalid XMP attribute pattern");
+                    let element =
+                        Regex::new(&format!(r"(?s)<{0}(?:\s[^>]*)?>(.*?)</{0}>", escaped))
+                            .expect("invalid XMP element pattern");
+                    (*property, attribute, element)
+                })
+                .collect(),
+            item: Regex::new(r"(?s)<rdf:li(?:\s[^>]*)?>(.*?)</rdf:li>")
+                .expect("invalid XMP item pattern"),
+        }
+    }
+
+    /// Returns the values of a property, which are either an attribute, the text of an element
+    /// or the items of a `rdf:Alt`, `rdf:Bag` or `rdf:Seq`
+    fn values(&self, xmp: &str, property: &str) -> Vec<String> {
+        let (attribute, element) = match self.properties.iter().find(|(p, _, _)| *p == property) {
+            Some((_, attribute, element)) => (attribute, element),
+            None => return vec![],
+        };
+        let values: Vec<String> = match element.captures(xmp) {
+            Some(c) if c[1].contains("<rdf:li") => self
+                .item
+                .captures_iter(&c[1])
+                .map(|i| unescape_xml(&i[1]))
+                .collect(),
+            Some(c) => vec![unescape_xml(&c[1])],
+            None => attribute
+                .captures(xmp)
+                .map(|c| vec![unescape_xml(&c[1])])
+                .unwrap_or_default(),
+        };
+        values
+            .into_iter()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parses dates like `2021-03-15T12:00:00+01:00`, `2021-03-15T12:00:00` or `2021-03-15`
+fn parse_xmp_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(d) = DateTime::parse_from_rfc3339(date) {
+        return Some(d.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })?;
+    Some(Utc.from_utc_datetime(&local))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_text, merge_xmp, parse_pdf_date, parse_xmp_date};
+    use crate::index::document_repository::FileMetadata;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn decode_text_test() {
+        assert_eq!(decode_text(b"Rechnung M\xe4rz "), "Rechnung März");
+        assert_eq!(
+            decode_text(&[0xFE, 0xFF, 0x00, 0x4D, 0x00, 0xE4, 0x00, 0x72, 0x00, 0x7A]),
+            "März"
+        );
+        assert_eq!(decode_text(b"\xEF\xBB\xBFM\xC3\xA4rz"), "März");
+    }
+
+    #[test]
+    fn date_test() {
+        let expected = Utc.ymd(2021, 3, 15).and_hms(11, 30, 0);
+        assert_eq!(parse_pdf_date("D:20210315123000+01'00'"), Some(expected));
+        assert_eq!(parse_pdf_date("D:20210315113000Z"), Some(expected));
+        assert_eq!(parse_pdf_date("20210315113000"), Some(expected));
+        assert_eq!(
+            parse_pdf_date("D:2021"),
+            Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(parse_pdf_date("D:20211345"), None);
+        assert_eq!(parse_pdf_date(""), None);
+
+        assert_eq!(parse_xmp_date("2021-03-15T12:30:00+01:00"), Some(expected));
+        assert_eq!(parse_xmp_date("2021-03-15T11:30:00"), Some(expected));
+    }
+
+    #[test]
+    fn xmp_test() {
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF>
+            <rdf:Description rdf:about="" pdf:Keywords="Rechnung, Strom; 2021" xmp:CreateDate="2021-03-15T12:30:00+01:00">
+              <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Stromrechnung &amp; Abschlag</rdf:li></rdf:Alt></dc:title>
+              <dc:creator><rdf:Seq><rdf:li>Stadtwerke</rdf:li><rdf:li>Abrechnung</rdf:li></rdf:Seq></dc:creator>
+            </rdf:Description>
+        </rdf:RDF></x:xmpmeta>"#;
+        let mut metadata = FileMetadata {
+            author: Some("Info Author".into()),
+            ..Default::default()
+        };
+        merge_xmp(&mut metadata, xmp);
+        assert_eq!(metadata.title.as_deref(), Some("Stromrechnung & Abschlag"));
+        // Values of the Info dictionary are kept
+        assert_eq!(metadata.author.as_deref(), Some("Info Author"));
+        assert_eq!(metadata.subject, None);
+        assert_eq!(metadata.keywords, vec!["Rechnung", "Strom", "2021"]);
+        assert_eq!(
+            metadata.creation_date,
+            Some(Utc.ymd(2021, 3, 15).and_hms(11, 30, 0))
+        );
+
+        let mut metadata = FileMetadata::default();
+        merge_xmp(&mut metadata, xmp);
+        assert_eq!(metadata.author.as_deref(), Some("Stadtwerke, Abrechnung"));
+    }
+}
@@ -15,12 +15,20 @@ pub enum OCRError {
     Image(String),
 }
 
-/// OCRs the given file and replaces it with an optimized version where the text is inserted as copyable metadata
-pub fn ocr_file(file: &Path, tesseract_languages: &[String]) -> Result<(), OCRError> {
+/// OCRs the given file and writes an optimized version where the text is inserted as copyable metadata to the output file.
+/// The input file is left untouched.
+pub fn ocr_file(
+    file: &Path,
+    output: &Path,
+    tesseract_languages: &[String],
+) -> Result<(), OCRError> {
     let languages = tesseract_languages.join("+");
     let file = file
         .to_str()
         .ok_or_else(|| OCRError::Image(format!("{:#?}", file)))?;
+    let output = output
+        .to_str()
+        .ok_or_else(|| OCRError::Image(format!("{:#?}", output)))?;
     let ocr_mypdfoutput = Command::new("ocrmypdf")
         .arg("--deskew") //Fix skewed images
         .arg("--clean") // Use unpaper to improve OCR (does not edit final PDF)
@@ -28,7 +36,7 @@ pub fn ocr_file(file: &Path, tesseract_languages: &[String]) -> Result<(), OCREr
         .arg("-l")
         .arg(languages)
         .arg(file)
-        .arg(file)
+        .arg(output)
         .output();
     match ocr_mypdfoutput {
         Ok(output) => {
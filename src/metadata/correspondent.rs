@@ -0,0 +1,240 @@
+use crate::index::document_repository::DocumentData;
+use crate::metadata::registry::{Entry, Registry};
+use crate::metadata::tag::{
+    from_fixed_matcher_config, strip_whitespace, Matcher, MatcherConfig, TaggingError,
+};
+use std::path::Path;
+
+/// Identification type of correspondents
+pub type CorrespondentId = u64;
+
+/// Sender of documents, e.g. a bank, an insurer or an employer
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CorrespondentConfig {
+    pub id: CorrespondentId,
+    pub name: String,
+    pub matcher: MatcherConfig,
+    /// IBANs of the correspondent, compared without whitespace
+    #[serde(default)]
+    pub ibans: Vec<String>,
+    /// Domains of the e-mail addresses of the correspondent, subdomains match as well
+    #[serde(default)]
+    pub email_domains: Vec<String>,
+}
+
+impl Entry for CorrespondentConfig {
+    const KIND: &'static str = "correspondent";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl CorrespondentConfig {
+    fn matches_iban(&self, doc: &DocumentData) -> bool {
+        doc.extracted.iban.iter().any(|iban| {
+            let iban = strip_whitespace(iban);
+            self.ibans
+                .iter()
+                .any(|i| strip_whitespace(i).eq_ignore_ascii_case(&iban))
+        })
+    }
+
+    fn matches_email_domain(&self, doc: &DocumentData) -> bool {
+        doc.extracted.email.iter().any(|email| {
+            let domain = email.rsplit('@').next().unwrap_or_default().to_lowercase();
+            self.email_domains.iter().any(|d| {
+                let d = d.trim_start_matches('@').to_lowercase();
+                domain == d || domain.ends_with(&format!(".{}", d))
+            })
+        })
+    }
+}
+
+/// Stores the correspondents and assigns them to documents
+pub struct Correspondents {
+    correspondents: Registry<CorrespondentConfig>,
+    /// Matchers of all correspondents, sorted by ID
+    matchers: Vec<(CorrespondentId, Box<dyn Matcher + Send + Sync>)>,
+}
+
+impl Correspondents {
+    pub fn new(data_dir: &Path) -> Result<Correspondents, TaggingError> {
+        let mut correspondents = Correspondents {
+            correspondents: Registry::new(data_dir, "correspondents.yaml")?,
+            matchers: vec![],
+        };
+        correspondents.compile();
+        Ok(correspondents)
+    }
+
+    /// Adds or replaces a correspondent and returns its ID
+    pub fn add_or_replace(
+        &mut self,
+        correspondent: CorrespondentConfig,
+    ) -> Result<CorrespondentId, TaggingError> {
+        // Try to build the matcher and fail early
+        from_fixed_matcher_config(&correspondent.matcher)?;
+        let id = self.correspondents.add_or_replace(correspondent)?;
+        self.compile();
+        Ok(id)
+    }
+
+    /// Removes a correspondent, documents referencing it have to be changed separately
+    pub fn remove(&mut self, id: CorrespondentId) -> Result<(), TaggingError> {
+        self.correspondents.remove(id)?;
+        self.compile();
+        Ok(())
+    }
+
+    pub fn get(&self, id: CorrespondentId) -> Option<CorrespondentConfig> {
+        self.correspondents.get(id).cloned()
+    }
+
+    /// Returns all correspondents sorted by name
+    pub fn get_all(&self) -> Vec<CorrespondentConfig> {
+        let mut correspondents: Vec<CorrespondentConfig> =
+            self.correspondents.values().cloned().collect();
+        correspondents.sort_by(|a, b| a.name.cmp(&b.name));
+        correspondents
+    }
+
+    /// Finds the correspondent of a document.
+    /// Extracted IBANs are checked first, then e-mail domains and then the matchers.
+    /// If several correspondents match, the one with the lowest ID wins.
+    pub fn match_document(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<CorrespondentId>, TaggingError> {
+        let correspondents = self.correspondents.sorted();
+        if let Some(c) = correspondents.iter().find(|c| c.matches_iban(doc)) {
+            return Ok(Some(c.id));
+        }
+        if let Some(c) = correspondents.iter().find(|c| c.matches_email_domain(doc)) {
+            return Ok(Some(c.id));
+        }
+        for (id, matcher) in &self.matchers {
+            if matcher.match_document(doc)? {
+                return Ok(Some(*id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rebuilds the matchers, has to be called whenever the correspondents change
+    fn compile(&mut self) {
+        self.matchers.clear();
+        for correspondent in self.correspondents.sorted() {
+            match from_fixed_matcher_config(&correspondent.matcher) {
+                Ok(m) => self.matchers.push((correspondent.id, m)),
+                Err(e) => error!(
+                    "Invalid correspondent in config: {:#?} ({})",
+                    correspondent, e
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CorrespondentConfig, Correspondents};
+    use crate::index::document_repository::{DocumentData, ExtractedData};
+    use crate::metadata::tag::MatcherConfig;
+
+    fn get_document(text: &str, extracted: ExtractedData) -> DocumentData {
+        DocumentData::test(0)
+            .with_body(text)
+            .with_extracted(extracted)
+    }
+
+    fn correspondent(name: &str, matcher: MatcherConfig) -> CorrespondentConfig {
+        CorrespondentConfig {
+            id: 0,
+            name: name.into(),
+            matcher,
+            ibans: vec![],
+            email_domains: vec![],
+        }
+    }
+
+    #[test]
+    fn correspondent_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut correspondents = Correspondents::new(dir.path()).unwrap();
+
+        let mut bank = correspondent(
+            "Bank",
+            MatcherConfig::FullMatcher {
+                match_str: "Kontoauszug".into(),
+                case_insensitive: true,
+            },
+        );
+        bank.ibans = vec!["DE89 3704 0044 0532 0130 00".into()];
+        let bank = correspondents.add_or_replace(bank).unwrap();
+        let mut insurer = correspondent("Insurer", MatcherConfig::NoMatcher);
+        insurer.email_domains = vec!["insurance.example".into()];
+        let insurer = correspondents.add_or_replace(insurer).unwrap();
+
+        let by_text = get_document("Ihr KONTOAUSZUG", ExtractedData::default());
+        assert_eq!(correspondents.match_document(&by_text).unwrap(), Some(bank));
+
+        let by_iban = get_document(
+            "",
+            ExtractedData {
+                iban: vec!["de89370400440532013000".into()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(correspondents.match_document(&by_iban).unwrap(), Some(bank));
+
+        // The e-mail domain wins over the matcher of another correspondent
+        let by_domain = get_document(
+            "Kontoauszug",
+            ExtractedData {
+                email: vec!["claims@mail.Insurance.example".into()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            correspondents.match_document(&by_domain).unwrap(),
+            Some(insurer)
+        );
+        let other_domain = get_document(
+            "",
+            ExtractedData {
+                email: vec!["info@noinsurance.example".into()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(correspondents.match_document(&other_domain).unwrap(), None);
+
+        // Automatic matchers are rejected
+        assert!(correspondents
+            .add_or_replace(correspondent(
+                "Auto",
+                MatcherConfig::Auto { threshold: 0.5 }
+            ))
+            .is_err());
+
+        // Reload from disk
+        let mut correspondents = Correspondents::new(dir.path()).unwrap();
+        let names: Vec<String> = correspondents
+            .get_all()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["Bank", "Insurer"]);
+        correspondents.remove(bank).unwrap();
+        assert!(correspondents.get(bank).is_none());
+        assert_eq!(correspondents.match_document(&by_iban).unwrap(), None);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::metadata::registry::{Entry, Registry};
+use chrono::NaiveDate;
+use std::path::Path;
+
+//Error Handling
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum CustomFieldError {
+    #[error("could not load/write configuration file")]
+    ConfigError(#[from] crate::persistence::PersistenceError),
+    #[error(
+        "field name `{0}` has to start with a letter and contain only letters, digits and `_`"
+    )]
+    InvalidName(String),
+    #[error("a field with the name `{0}` already exists")]
+    DuplicateName(String),
+    #[error("the type of field {0} cannot be changed")]
+    TypeChanged(CustomFieldId),
+    #[error("field {0} does not exist")]
+    UnknownField(CustomFieldId),
+    #[error("value of field {0} has the wrong type")]
+    WrongType(CustomFieldId),
+    #[error("could not parse `{0}` as {1}")]
+    Parse(String, CustomFieldType),
+}
+
+/// Identification type of custom fields
+pub type CustomFieldId = u64;
+
+/// Type of the values of a custom field
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldType {
+    String,
+    Number,
+    Money,
+    Date,
+}
+
+impl std::fmt::Display for CustomFieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CustomFieldType::String => write!(f, "text"),
+            CustomFieldType::Number => write!(f, "number"),
+            CustomFieldType::Money => write!(f, "amount of money"),
+            CustomFieldType::Date => write!(f, "date"),
+        }
+    }
+}
+
+impl CustomFieldType {
+    /// Parses a value entered by a user, dates are expected as `YYYY-MM-DD`
+    pub fn parse(&self, value: &str) -> Result<CustomFieldValue, CustomFieldError> {
+        let value = value.trim();
+        let error = || CustomFieldError::Parse(value.into(), *self);
+        match self {
+            CustomFieldType::String => Ok(CustomFieldValue::String(value.into())),
+            CustomFieldType::Number => value
+                .parse()
+                .map(CustomFieldValue::Number)
+                .map_err(|_| error()),
+            CustomFieldType::Money => Money::parse(value)
+                .map(CustomFieldValue::Money)
+                .ok_or_else(error),
+            CustomFieldType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(CustomFieldValue::Date)
+                .map_err(|_| error()),
+        }
+    }
+}
+
+/// Amount of money in the smallest unit of its currency
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Money {
+    pub cents: i64,
+    /// ISO 4217 code, e.g. `EUR`
+    pub currency: String,
+}
+
+impl Money {
+    /// Parses amounts like `1234.56 EUR` or `-12,5`, the currency defaults to EUR
+    fn parse(value: &str) -> Option<Money> {
+        let (amount, currency) = match value.rsplit_once(' ') {
+            Some((amount, currency)) => (amount.trim(), currency.to_uppercase()),
+            None => (value, "EUR".to_string()),
+        };
+        let (negative, amount) = match amount.strip_prefix('-') {
+            Some(amount) => (true, amount),
+            None => (false, amount),
+        };
+        let (units, fraction) = match amount.split_once(&['.', ','][..]) {
+            Some((units, fraction)) => (units, fraction),
+            None => (amount, ""),
+        };
+        if units.is_empty()
+            || fraction.len() > 2
+            || !(units.chars().all(|c| c.is_ascii_digit())
+                && fraction.chars().all(|c| c.is_ascii_digit()))
+        {
+            return None;
+        }
+        let cents =
+            units.parse::<i64>().ok()? * 100 + format!("{:0<2}", fraction).parse::<i64>().ok()?;
+        Some(Money {
+            cents: if negative { -cents } else { cents },
+            currency,
+        })
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.cents < 0 { "-" } else { "" };
+        let cents = self.cents.abs();
+        write!(
+            f,
+            "{}{}.{:02} {}",
+            sign,
+            cents / 100,
+            cents % 100,
+            self.currency
+        )
+    }
+}
+
+/// Value of a custom field of a document
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldValue {
+    String(String),
+    Number(f64),
+    Money(Money),
+    Date(NaiveDate),
+}
+
+impl CustomFieldValue {
+    pub fn field_type(&self) -> CustomFieldType {
+        match self {
+            CustomFieldValue::String(_) => CustomFieldType::String,
+            CustomFieldValue::Number(_) => CustomFieldType::Number,
+            CustomFieldValue::Money(_) => CustomFieldType::Money,
+            CustomFieldValue::Date(_) => CustomFieldType::Date,
+        }
+    }
+
+    /// Path of the comparable part of the value in its JSON representation
+    pub fn json_path(field_type: CustomFieldType) -> &'static [&'static str] {
+        match field_type {
+            CustomFieldType::String => &["string"],
+            CustomFieldType::Number => &["number"],
+            CustomFieldType::Money => &["money", "cents"],
+            CustomFieldType::Date => &["date"],
+        }
+    }
+
+    /// Compares two values of the same type, amounts of money are compared regardless of currency
+    pub fn compare(&self, other: &CustomFieldValue) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (CustomFieldValue::String(a), CustomFieldValue::String(b)) => Some(a.cmp(b)),
+            (CustomFieldValue::Number(a), CustomFieldValue::Number(b)) => a.partial_cmp(b),
+            (CustomFieldValue::Money(a), CustomFieldValue::Money(b)) => Some(a.cents.cmp(&b.cents)),
+            (CustomFieldValue::Date(a), CustomFieldValue::Date(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CustomFieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CustomFieldValue::String(s) => write!(f, "{}", s),
+            CustomFieldValue::Number(n) => write!(f, "{}", n),
+            CustomFieldValue::Money(m) => write!(f, "{}", m),
+            CustomFieldValue::Date(d) => write!(f, "{}", d),
+        }
+    }
+}
+
+/// Definition of a custom field, e.g. the invoice number or the due date
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CustomFieldConfig {
+    pub id: CustomFieldId,
+    /// Unique name, used as column name in exports
+    pub name: String,
+    pub field_type: CustomFieldType,
+}
+
+impl Entry for CustomFieldConfig {
+    const KIND: &'static str = "custom field";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Stores the definitions of the custom fields
+pub struct CustomFields {
+    fields: Registry<CustomFieldConfig>,
+}
+
+impl CustomFields {
+    pub fn new(data_dir: &Path) -> Result<CustomFields, CustomFieldError> {
+        Ok(CustomFields {
+            fields: Registry::new(data_dir, "custom_fields.yaml")?,
+        })
+    }
+
+    /// Adds or replaces a field and returns its ID. The type of an existing field cannot be changed.
+    pub fn add_or_replace(
+        &mut self,
+        field: CustomFieldConfig,
+    ) -> Result<CustomFieldId, CustomFieldError> {
+        let valid_name = field
+            .name
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphabetic())
+            .unwrap_or(false)
+            && field
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(CustomFieldError::InvalidName(field.name));
+        }
+        if self
+            .fields
+            .values()
+            .any(|f| f.name == field.name && f.id != field.id)
+        {
+            return Err(CustomFieldError::DuplicateName(field.name));
+        }
+        match self.fields.get(field.id) {
+            Some(existing) if existing.field_type != field.field_type => {
+                Err(CustomFieldError::TypeChanged(field.id))
+            }
+            _ => Ok(self.fields.add_or_replace(field)?),
+        }
+    }
+
+    /// Removes a field, the values of the documents have to be removed separately
+    pub fn remove(&mut self, id: CustomFieldId) -> Result<(), CustomFieldError> {
+        Ok(self.fields.remove(id)?)
+    }
+
+    pub fn get(&self, id: CustomFieldId) -> Option<CustomFieldConfig> {
+        self.fields.get(id).cloned()
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&CustomFieldConfig> {
+        self.fields.values().find(|f| f.name == name)
+    }
+
+    /// Returns all fields sorted by ID
+    pub fn get_all(&self) -> Vec<CustomFieldConfig> {
+        self.fields.sorted().into_iter().cloned().collect()
+    }
+
+    /// Checks that the field exists and the value has its type
+    pub fn check_value(
+        &self,
+        id: CustomFieldId,
+        value: &CustomFieldValue,
+    ) -> Result<(), CustomFieldError> {
+        let field = self
+            .fields
+            .get(id)
+            .ok_or(CustomFieldError::UnknownField(id))?;
+        if field.field_type != value.field_type() {
+            return Err(CustomFieldError::WrongType(id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomFieldConfig, CustomFieldType, CustomFieldValue, CustomFields, Money};
+    use chrono::NaiveDate;
+
+    fn field(name: &str, field_type: CustomFieldType) -> CustomFieldConfig {
+        CustomFieldConfig {
+            id: 0,
+            name: name.into(),
+            field_type,
+        }
+    }
+
+    #[test]
+    fn custom_fields_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut fields = CustomFields::new(dir.path()).unwrap();
+        let invoice_no = fields
+            .add_or_replace(field("invoice_no", CustomFieldType::String))
+            .unwrap();
+        let due = fields
+            .add_or_replace(field("due_date", CustomFieldType::Date))
+            .unwrap();
+
+        assert!(fields
+            .add_or_replace(field("due_date", CustomFieldType::Date))
+            .is_err());
+        assert!(fields
+            .add_or_replace(field("due date", CustomFieldType::Date))
+            .is_err());
+        let mut changed = fields.get(due).unwrap();
+        changed.field_type = CustomFieldType::Money;
+        assert!(fields.add_or_replace(changed).is_err());
+
+        let date = CustomFieldType::Date.parse("2021-03-01").unwrap();
+        assert!(fields.check_value(due, &date).is_ok());
+        assert!(fields.check_value(invoice_no, &date).is_err());
+        assert!(fields.check_value(42, &date).is_err());
+
+        let fields = CustomFields::new(dir.path()).unwrap();
+        let names: Vec<String> = fields.get_all().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["invoice_no", "due_date"]);
+    }
+
+    #[test]
+    fn parse_test() {
+        let money = |cents, currency: &str| {
+            CustomFieldValue::Money(Money {
+                cents,
+                currency: currency.into(),
+            })
+        };
+        assert_eq!(
+            CustomFieldType::Money.parse("1234.56 EUR").unwrap(),
+            money(123456, "EUR")
+        );
+        assert_eq!(
+            CustomFieldType::Money.parse("-12,5 usd").unwrap(),
+            money(-1250, "USD")
+        );
+        assert_eq!(
+            CustomFieldType::Money.parse("7").unwrap(),
+            money(700, "EUR")
+        );
+        assert!(CustomFieldType::Money.parse("1.234,56").is_err());
+        assert_eq!(money(-1250, "USD").to_string(), "-12.50 USD");
+        assert_eq!(
+            CustomFieldType::Date.parse("2021-03-01").unwrap(),
+            CustomFieldValue::Date(NaiveDate::from_ymd(2021, 3, 1))
+        );
+        assert!(CustomFieldType::Date.parse("01.03.2021").is_err());
+        assert_eq!(
+            CustomFieldType::Number.parse("2.5").unwrap(),
+            CustomFieldValue::Number(2.5)
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use crate::index::document_repository::DateCandidate;
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use regex::{Regex, RegexBuilder};
+
+/// Number of candidates kept per document
+pub const MAX_DATE_CANDIDATES: usize = 5;
+/// Number of characters shown before and after a date in a snippet
+const SNIPPET_CONTEXT: usize = 30;
+
+/// Words directly before the date of a document
+const DATE_KEYWORDS: &[&str] = &["datum", "date", "dated", "vom", "den", "stand"];
+/// Words before other dates, e.g. of a referenced letter, a due date or a birthday
+const REFERENCE_KEYWORDS: &[&str] = &[
+    "schreiben",
+    "nachricht",
+    "anfrage",
+    "auftrag",
+    "bestellung",
+    "geburtsdatum",
+    "geboren",
+    "fällig",
+    "zahlbar",
+    "bis",
+    "gültig",
+    "letter",
+    "message",
+    "order",
+    "reference",
+    "due",
+    "until",
+    "born",
+    "birth",
+    "expires",
+];
+
+/// Names of months and the language they are written in, `None` if they are the same in German and English
+const MONTHS: &[(&str, u32, Option<&str>)] = &[
+    ("januar", 1, Some("German")),
+    ("jänner", 1, Some("German")),
+    ("january", 1, Some("English")),
+    ("jan", 1, None),
+    ("februar", 2, Some("German")),
+    ("february", 2, Some("English")),
+    ("feb", 2, None),
+    ("märz", 3, Some("German")),
+    ("maerz", 3, Some("German")),
+    ("mär", 3, Some("German")),
+    ("mrz", 3, Some("German")),
+    ("march", 3, Some("English")),
+    ("mar", 3, Some("English")),
+    ("april", 4, None),
+    ("apr", 4, None),
+    ("mai", 5, Some("German")),
+    ("may", 5, Some("English")),
+    ("juni", 6, Some("German")),
+    ("june", 6, Some("English")),
+    ("jun", 6, None),
+    ("juli", 7, Some("German")),
+    ("july", 7, Some("English")),
+    ("jul", 7, None),
+    ("august", 8, None),
+    ("aug", 8, None),
+    ("september", 9, None),
+    ("sept", 9, None),
+    ("sep", 9, None),
+    ("oktober", 10, Some("German")),
+    ("october", 10, Some("English")),
+    ("okt", 10, Some("German")),
+    ("oct", 10, Some("English")),
+    ("november", 11, None),
+    ("nov", 11, None),
+    ("dezember", 12, Some("German")),
+    ("december", 12, Some("English")),
+    ("dez", 12, Some("German")),
+    ("dec", 12, Some("English")),
+];
+
+/// How a date is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateFormat {
+    /// `15.03.2021` or `15.03.21`
+    DayMonthYearDots,
+    /// `2021-03-15`
+    Iso,
+    /// `15/03/2021`, or `03/15/2021` in English documents
+    Slashes,
+    /// `15. März 2021` or `15th March 2021`
+    DayMonthName,
+    /// `March 15, 2021`
+    MonthNameDay,
+}
+
+/// Finds the dates in the body of a document and scores how likely each one is the date of the document
+pub struct DateInference {
+    patterns: Vec<(DateFormat, Regex)>,
+}
+
+impl Default for DateInference {
+    fn default() -> Self {
+        DateInference::new()
+    }
+}
+
+impl DateInference {
+    pub fn new() -> DateInference {
+        let mut months: Vec<&str> = MONTHS.iter().map(|(name, _, _)| *name).collect();
+        // Longer names first, so that `märz` is not matched as `mär`
+        months.sort_by_key(|m| std::cmp::Reverse(m.chars().count()));
+        let months = months.join("|");
+        let patterns = vec![
+            (
+                DateFormat::DayMonthYearDots,
+                r"\b(?P<d>\d{1,2})\.\s?(?P<m>\d{1,2})\.\s?(?P<y>\d{4}|\d{2})\b".to_string(),
+            ),
+            (
+                DateFormat::Iso,
+                r"\b(?P<y>\d{4})-(?P<m>\d{1,2})-(?P<d>\d{1,2})\b".to_string(),
+            ),
+            (
+                DateFormat::Slashes,
+                r"\b(?P<a>\d{1,2})/(?P<b>\d{1,2})/(?P<y>\d{4}|\d{2})\b".to_string(),
+            ),
+            (
+                DateFormat::DayMonthName,
+                format!(
+                    r"\b(?P<d>\d{{1,2}})(?:\.|st|nd|rd|th)?\s+(?P<month>{})\.?\s+(?P<y>\d{{4}})\b",
+                    months
+                ),
+            ),
+            (
+                DateFormat::MonthNameDay,
+                format!(
+                    r"\b(?P<month>{})\.?\s+(?P<d>\d{{1,2}})(?:st|nd|rd|th)?,?\s+(?P<y>\d{{4}})\b",
+                    months
+                ),
+            ),
+        ];
+        DateInference {
+            patterns: patterns
+                .into_iter()
+                .map(|(format, pattern)| {
+                    let regex = RegexBuilder::new(&pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .expect("invalid date pattern");
+                    (format, regex)
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses a text consisting of a single date in any of the formats found in documents,
+    /// e.g. `31.12.2021`, `2021-12-31`, `31/12/2021` or `3. März 2021`.
+    /// Two-digit years are resolved relative to the reference date.
+    pub fn parse(
+        &self,
+        text: &str,
+        language: Option<&str>,
+        reference: NaiveDate,
+    ) -> Option<NaiveDate> {
+        let text = text.trim().trim_end_matches(&[',', ';'][..]);
+        self.patterns.iter().find_map(|(format, regex)| {
+            let captures = regex.captures(text)?;
+            let m = captures.get(0)?;
+            if m.start() != 0 || m.end() != text.len() {
+                return None;
+            }
+            parse(*format, &captures, language, reference).map(|(date, _)| date)
+        })
+    }
+
+    /// Returns the best candidates for the date of the document, the most likely one first.
+    /// The date of the import is used to rule out dates in the future and to prefer recent dates.
+    pub fn candidates(
+        &self,
+        body: &str,
+        language: Option<&str>,
+        imported: DateTime<Utc>,
+    ) -> Vec<DateCandidate> {
+        let reference = imported.naive_utc().date();
+        let mut candidates: Vec<(DateCandidate, usize)> = vec![];
+        for (format, regex) in &self.patterns {
+            for captures in regex.captures_iter(body) {
+                let m = captures.get(0).unwrap();
+                let (date, date_language) = match parse(*format, &captures, language, reference) {
+                    Some(d) => d,
+                    None => continue,
+                };
+                let mut score = 1.0;
+                // Dates in the header of a letter come first
+                score += 0.5 * (1.0 - m.start() as f64 / body.len() as f64);
+                score += context_score(&body[..m.start()]);
+                score += plausibility_score(date, reference);
+                if date_language.is_some() && date_language == language {
+                    score += 0.25;
+                }
+
+                // Keep the best occurrence of each date
+                match candidates
+                    .iter_mut()
+                    .find(|(c, _)| c.date.naive_utc().date() == date)
+                {
+                    Some((c, _)) if c.score >= score => {}
+                    Some(existing) => {
+                        existing.0.score = score;
+                        existing.0.snippet = snippet(body, m.start(), m.end());
+                        existing.1 = m.start();
+                    }
+                    None => candidates.push((
+                        DateCandidate {
+                            date: Utc.from_utc_datetime(&date.and_hms(0, 0, 0)),
+                            score,
+                            snippet: snippet(body, m.start(), m.end()),
+                        },
+                        m.start(),
+                    )),
+                }
+            }
+        }
+        candidates.sort_by(|(a, a_pos), (b, b_pos)| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a_pos.cmp(b_pos))
+        });
+        candidates
+            .into_iter()
+            .take(MAX_DATE_CANDIDATES)
+            .map(|(c, _)| c)
+            .collect()
+    }
+}
+
+/// Builds the date of a match, together with the language it is written in
+fn parse(
+    format: DateFormat,
+    captures: &regex::Captures,
+    language: Option<&str>,
+    reference: NaiveDate,
+) -> Option<(NaiveDate, Option<&'static str>)> {
+    let number = |name: &str| captures.name(name)?.as_str().parse::<u32>().ok();
+    let year = {
+        let y = captures.name("y")?.as_str();
+        let year = y.parse::<i32>().ok()?;
+        if y.len() == 2 {
+            // Two-digit years are in the past century, unless they are not in the future
+            let century = reference.year() / 100 * 100;
+            if century + year <= reference.year() + 1 {
+                century + year
+            } else {
+                century - 100 + year
+            }
+        } else {
+            year
+        }
+    };
+    match format {
+        DateFormat::DayMonthYearDots => {
+            NaiveDate::from_ymd_opt(year, number("m")?, number("d")?).map(|d| (d, Some("German")))
+        }
+        DateFormat::Iso => {
+            NaiveDate::from_ymd_opt(year, number("m")?, number("d")?).map(|d| (d, None))
+        }
+        DateFormat::Slashes => {
+            let (a, b) = (number("a")?, number("b")?);
+            let month_first = language == Some("English");
+            let (first, second) = if month_first {
+                ((b, a), (a, b))
+            } else {
+                ((a, b), (b, a))
+            };
+            NaiveDate::from_ymd_opt(year, first.1, first.0)
+                .or_else(|| NaiveDate::from_ymd_opt(year, second.1, second.0))
+                .map(|d| (d, None))
+        }
+        DateFormat::DayMonthName | DateFormat::MonthNameDay => {
+            let name = captures.name("month")?.as_str().to_lowercase();
+            let (_, month, month_language) = MONTHS.iter().find(|(n, _, _)| *n == name)?;
+            NaiveDate::from_ymd_opt(year, *month, number("d")?).map(|d| (d, *month_language))
+        }
+    }
+}
+
+/// Rates the words before a date
+fn context_score(before: &str) -> f64 {
+    let words: Vec<String> = before
+        .split_whitespace()
+        .rev()
+        .take(3)
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .collect();
+    let mut score = 0.0;
+    if words.iter().any(|w| DATE_KEYWORDS.contains(&w.as_str())) {
+        score += 1.0;
+    }
+    if words
+        .iter()
+        .any(|w| REFERENCE_KEYWORDS.contains(&w.as_str()))
+    {
+        score -= 1.0;
+    }
+    score
+}
+
+/// Rates a date by its distance to the date of the import
+fn plausibility_score(date: NaiveDate, reference: NaiveDate) -> f64 {
+    let days = (reference - date).num_days();
+    if days < -1 {
+        // Documents are not written in the future, these are e.g. due dates
+        return -2.0;
+    }
+    let years = days as f64 / 365.25;
+    if years > 30.0 {
+        // Birthdays or founding dates
+        -2.0
+    } else {
+        -(years * 0.2).min(1.0)
+    }
+}
+
+fn snippet(body: &str, start: usize, end: usize) -> String {
+    let before: String = body[..start]
+        .chars()
+        .rev()
+        .take(SNIPPET_CONTEXT)
+        .collect::<Vec<char>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let after: String = body[end..].chars().take(SNIPPET_CONTEXT).collect();
+    format!("{}{}{}", before, &body[start..end], after)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DateInference;
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    fn infer(body: &str, language: Option<&str>) -> Vec<NaiveDate> {
+        DateInference::new()
+            .candidates(body, language, Utc.ymd(2021, 3, 20).and_hms(12, 0, 0))
+            .into_iter()
+            .map(|c| c.date.naive_utc().date())
+            .collect()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn german_letter_test() {
+        let dates = infer(
+            "Ihr Schreiben vom 01.02.2019\nSehr geehrte Damen und Herren,\nDatum: 15.03.2021\nZahlbar bis 30.03.2021",
+            Some("German"),
+        );
+        assert_eq!(
+            dates,
+            vec![date(2021, 3, 15), date(2019, 2, 1), date(2021, 3, 30)]
+        );
+        assert_eq!(
+            infer("Berlin, 3. März 2021", Some("German")),
+            vec![date(2021, 3, 3)]
+        );
+        assert_eq!(
+            infer("Geburtsdatum 01.01.1980 Stand 10.02.21", Some("German")),
+            vec![date(2021, 2, 10), date(1980, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn english_letter_test() {
+        assert_eq!(
+            infer("Invoice date: 03/15/2021", Some("English")),
+            vec![date(2021, 3, 15)]
+        );
+        assert_eq!(
+            infer("Rechnung 03/02/2021", Some("German")),
+            vec![date(2021, 2, 3)]
+        );
+        assert_eq!(
+            infer(
+                "Your order of March 1st, 2021. Date: 12th March 2021",
+                Some("English")
+            ),
+            vec![date(2021, 3, 12), date(2021, 3, 1)]
+        );
+        assert!(infer("Version 1.2.3, 31/31/2021", None).is_empty());
+    }
+
+    #[test]
+    fn parse_test() {
+        let dates = DateInference::new();
+        let parse = |text, language| dates.parse(text, language, date(2021, 3, 20));
+        assert_eq!(parse("31.12.2021", None), Some(date(2021, 12, 31)));
+        assert_eq!(parse("31.12.21", None), Some(date(2021, 12, 31)));
+        assert_eq!(parse("2021-12-31", None), Some(date(2021, 12, 31)));
+        assert_eq!(parse("31/12/2021", None), Some(date(2021, 12, 31)));
+        assert_eq!(parse("03/02/2021", Some("English")), Some(date(2021, 3, 2)));
+        assert_eq!(parse("31 December 2021", None), Some(date(2021, 12, 31)));
+        assert_eq!(parse("3. März 2021", None), Some(date(2021, 3, 3)));
+        assert_eq!(parse("December 31, 2021;", None), Some(date(2021, 12, 31)));
+        // Only the whole text is parsed
+        assert_eq!(parse("am 31.12.2021", None), None);
+        assert_eq!(parse("31/31/2021", None), None);
+    }
+
+    #[test]
+    fn snippet_test() {
+        let candidates = DateInference::new().candidates(
+            "Hamburg, den 15.03.2021",
+            Some("German"),
+            Utc.ymd(2021, 3, 20).and_hms(0, 0, 0),
+        );
+        assert_eq!(candidates[0].snippet, "Hamburg, den 15.03.2021");
+    }
+}
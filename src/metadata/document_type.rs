@@ -0,0 +1,187 @@
+use crate::index::document_repository::DocumentData;
+use crate::metadata::registry::{Entry, Registry};
+use crate::metadata::tag::{from_fixed_matcher_config, Matcher, MatcherConfig, TaggingError};
+use std::path::Path;
+
+/// Identification type of document types
+pub type DocumentTypeId = u64;
+
+/// Kind of a document, e.g. invoice, contract, payslip, letter or receipt.
+/// Unlike tags, a document has at most one type.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DocumentTypeConfig {
+    pub id: DocumentTypeId,
+    pub name: String,
+    pub matcher: MatcherConfig,
+}
+
+impl Entry for DocumentTypeConfig {
+    const KIND: &'static str = "document type";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Stores the document types and assigns them to documents
+pub struct DocumentTypes {
+    types: Registry<DocumentTypeConfig>,
+    /// Matchers of all types, sorted by ID
+    matchers: Vec<(DocumentTypeId, Box<dyn Matcher + Send + Sync>)>,
+}
+
+impl DocumentTypes {
+    pub fn new(data_dir: &Path) -> Result<DocumentTypes, TaggingError> {
+        let mut types = DocumentTypes {
+            types: Registry::new(data_dir, "document_types.yaml")?,
+            matchers: vec![],
+        };
+        types.compile();
+        Ok(types)
+    }
+
+    /// Adds or replaces a document type and returns its ID
+    pub fn add_or_replace(
+        &mut self,
+        document_type: DocumentTypeConfig,
+    ) -> Result<DocumentTypeId, TaggingError> {
+        // Try to build the matcher and fail early
+        from_fixed_matcher_config(&document_type.matcher)?;
+        let id = self.types.add_or_replace(document_type)?;
+        self.compile();
+        Ok(id)
+    }
+
+    /// Removes a document type, documents referencing it have to be changed separately
+    pub fn remove(&mut self, id: DocumentTypeId) -> Result<(), TaggingError> {
+        self.types.remove(id)?;
+        self.compile();
+        Ok(())
+    }
+
+    pub fn get(&self, id: DocumentTypeId) -> Option<DocumentTypeConfig> {
+        self.types.get(id).cloned()
+    }
+
+    /// Returns all document types sorted by name
+    pub fn get_all(&self) -> Vec<DocumentTypeConfig> {
+        let mut types: Vec<DocumentTypeConfig> = self.types.values().cloned().collect();
+        types.sort_by(|a, b| a.name.cmp(&b.name));
+        types
+    }
+
+    /// Finds the type of a document. If several types match, the one with the lowest ID wins.
+    pub fn match_document(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<DocumentTypeId>, TaggingError> {
+        for (id, matcher) in &self.matchers {
+            if matcher.match_document(doc)? {
+                return Ok(Some(*id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Rebuilds the matchers, has to be called whenever the types change
+    fn compile(&mut self) {
+        self.matchers.clear();
+        for document_type in self.types.sorted() {
+            match from_fixed_matcher_config(&document_type.matcher) {
+                Ok(m) => self.matchers.push((document_type.id, m)),
+                Err(e) => error!(
+                    "Invalid document type in config: {:#?} ({})",
+                    document_type, e
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DocumentTypeConfig, DocumentTypes};
+    use crate::index::document_repository::DocumentData;
+    use crate::metadata::tag::MatcherConfig;
+
+    fn get_document(text: &str) -> DocumentData {
+        DocumentData::test(0).with_body(text)
+    }
+
+    fn document_type(name: &str, match_str: &str) -> DocumentTypeConfig {
+        DocumentTypeConfig {
+            id: 0,
+            name: name.into(),
+            matcher: MatcherConfig::AnyMatcher {
+                match_str: match_str.into(),
+                case_insensitive: true,
+            },
+        }
+    }
+
+    #[test]
+    fn document_type_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut types = DocumentTypes::new(dir.path()).unwrap();
+        let invoice = types
+            .add_or_replace(document_type("Invoice", "Rechnung, Invoice"))
+            .unwrap();
+        let payslip = types
+            .add_or_replace(document_type("Payslip", "Gehaltsnachweis, Payslip"))
+            .unwrap();
+
+        assert_eq!(
+            types
+                .match_document(&get_document("Ihre Rechnung"))
+                .unwrap(),
+            Some(invoice)
+        );
+        assert_eq!(
+            types
+                .match_document(&get_document("Gehaltsnachweis März"))
+                .unwrap(),
+            Some(payslip)
+        );
+        // Only one type is assigned, the lowest ID wins
+        assert_eq!(
+            types
+                .match_document(&get_document("Rechnung zum Gehaltsnachweis"))
+                .unwrap(),
+            Some(invoice)
+        );
+        assert_eq!(
+            types.match_document(&get_document("Vertrag")).unwrap(),
+            None
+        );
+
+        // Replacing keeps the ID
+        let mut renamed = types.get(invoice).unwrap();
+        renamed.name = "Bill".into();
+        assert_eq!(types.add_or_replace(renamed).unwrap(), invoice);
+
+        // Automatic matchers are rejected
+        let mut auto = document_type("Auto", "");
+        auto.matcher = MatcherConfig::Auto { threshold: 0.5 };
+        assert!(types.add_or_replace(auto).is_err());
+
+        // Reload from disk
+        let mut types = DocumentTypes::new(dir.path()).unwrap();
+        let names: Vec<String> = types.get_all().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["Bill", "Payslip"]);
+        types.remove(invoice).unwrap();
+        assert_eq!(
+            types
+                .match_document(&get_document("Ihre Rechnung"))
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use crate::index::document_repository::DocumentData;
+use crate::metadata::correspondent::CorrespondentId;
+use crate::metadata::custom_field::{
+    CustomFieldId, CustomFieldType, CustomFieldValue, CustomFields, Money,
+};
+use crate::metadata::date::DateInference;
+use crate::metadata::registry::{Entry, Registry};
+use crate::metadata::tag::{TagId, TaggingError};
+use regex::{Regex, RegexBuilder};
+use std::path::Path;
+
+/// Identification type of extraction rules
+pub type ExtractionRuleId = u64;
+
+/// Regular expression, whose named capture groups fill the custom fields with the same names,
+/// e.g. `Rechnungsnummer:\s*(?P<invoice_no>\S+)`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExtractionRuleConfig {
+    pub id: ExtractionRuleId,
+    pub name: String,
+    pub regex: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// The rule only applies to documents with this tag
+    #[serde(default)]
+    pub tag: Option<TagId>,
+    /// The rule only applies to documents of this correspondent
+    #[serde(default)]
+    pub correspondent: Option<CorrespondentId>,
+}
+
+impl ExtractionRuleConfig {
+    fn applies_to(&self, doc: &DocumentData) -> bool {
+        self.tag.map_or(true, |t| doc.tags.contains(&t))
+            && self
+                .correspondent
+                .map_or(true, |c| doc.correspondent == Some(c))
+    }
+}
+
+impl Entry for ExtractionRuleConfig {
+    const KIND: &'static str = "extraction rule";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Value captured by a rule
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExtractedValue {
+    pub field: CustomFieldId,
+    /// Captured text
+    pub text: String,
+    /// Byte range of the text in the body
+    pub range: (usize, usize),
+    /// Parsed value, `None` if the text could not be parsed as the type of the field
+    pub value: Option<CustomFieldValue>,
+}
+
+/// What a rule extracts from a document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExtractionPreview {
+    pub rule: ExtractionRuleId,
+    pub name: String,
+    /// The document has the tag and correspondent the rule is restricted to
+    pub applies: bool,
+    pub values: Vec<ExtractedValue>,
+}
+
+/// Stores the extraction rules and fills the custom fields of documents
+pub struct ExtractionRules {
+    rules: Registry<ExtractionRuleConfig>,
+    /// Compiled rules, sorted by ID
+    compiled: Vec<(ExtractionRuleId, Regex)>,
+    dates: DateInference,
+}
+
+impl ExtractionRules {
+    pub fn new(data_dir: &Path) -> Result<ExtractionRules, TaggingError> {
+        let mut rules = ExtractionRules {
+            rules: Registry::new(data_dir, "extraction_rules.yaml")?,
+            compiled: vec![],
+            dates: DateInference::new(),
+        };
+        rules.compile();
+        Ok(rules)
+    }
+
+    /// Adds or replaces a rule and returns its ID.
+    /// Each named capture group has to be the name of a custom field.
+    pub fn add_or_replace(
+        &mut self,
+        rule: ExtractionRuleConfig,
+        fields: &CustomFields,
+    ) -> Result<ExtractionRuleId, TaggingError> {
+        let regex = compile(&rule)?;
+        let mut names = regex.capture_names().flatten().peekable();
+        if names.peek().is_none() {
+            return Err(TaggingError::NoCaptureGroups(rule.regex));
+        }
+        for name in names {
+            if fields.get_by_name(name).is_none() {
+                return Err(TaggingError::UnknownCaptureGroup(name.into()));
+            }
+        }
+        let id = self.rules.add_or_replace(rule)?;
+        self.compile();
+        Ok(id)
+    }
+
+    /// Removes a rule, values extracted by it are kept
+    pub fn remove(&mut self, id: ExtractionRuleId) -> Result<(), TaggingError> {
+        self.rules.remove(id)?;
+        self.compile();
+        Ok(())
+    }
+
+    pub fn get(&self, id: ExtractionRuleId) -> Option<ExtractionRuleConfig> {
+        self.rules.get(id).cloned()
+    }
+
+    /// Returns all rules sorted by ID
+    pub fn get_all(&self) -> Vec<ExtractionRuleConfig> {
+        self.rules.sorted().into_iter().cloned().collect()
+    }
+
+    /// Fills the custom fields of the document, which have no value yet.
+    /// If several rules capture a field, the rule with the lowest ID wins.
+    pub fn apply(&self, doc: &mut DocumentData, fields: &CustomFields) {
+        for preview in self.preview(doc, fields) {
+            if !preview.applies {
+                continue;
+            }
+            for extracted in preview.values {
+                match extracted.value {
+                    Some(value) => {
+                        doc.custom_fields.entry(extracted.field).or_insert(value);
+                    }
+                    None => debug!(
+                        "Rule {} captured `{}` in document {}, which is no valid value of field {}",
+                        preview.rule, extracted.text, doc.id, extracted.field
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Shows what each rule extracts from the document, regardless of whether the rule applies to it
+    pub fn preview(&self, doc: &DocumentData, fields: &CustomFields) -> Vec<ExtractionPreview> {
+        let body = doc.body.as_deref().unwrap_or_default();
+        self.compiled
+            .iter()
+            .filter_map(|(id, regex)| Some((self.rules.get(*id)?, regex)))
+            .map(|(rule, regex)| {
+                let mut values = vec![];
+                if let Some(captures) = regex.captures(body) {
+                    for name in regex.capture_names().flatten() {
+                        let (field, m) = match (fields.get_by_name(name), captures.name(name)) {
+                            (Some(field), Some(m)) => (field, m),
+                            _ => continue,
+                        };
+                        values.push(ExtractedValue {
+                            field: field.id,
+                            text: m.as_str().into(),
+                            range: (m.start(), m.end()),
+                            value: self.parse_extracted(field.field_type, m.as_str(), doc),
+                        });
+                    }
+                }
+                ExtractionPreview {
+                    rule: rule.id,
+                    name: rule.name.clone(),
+                    applies: rule.applies_to(doc),
+                    values,
+                }
+            })
+            .collect()
+    }
+
+    /// Parses text of a document as value of the given type.
+    /// Unlike `CustomFieldType::parse`, common formats of dates, numbers and amounts are accepted.
+    fn parse_extracted(
+        &self,
+        field_type: CustomFieldType,
+        text: &str,
+        doc: &DocumentData,
+    ) -> Option<CustomFieldValue> {
+        let text = text.trim();
+        match field_type {
+            CustomFieldType::String if !text.is_empty() => {
+                Some(CustomFieldValue::String(text.into()))
+            }
+            CustomFieldType::String => None,
+            CustomFieldType::Number => parse_decimal(text)
+                .and_then(|d| d.to_string().parse().ok())
+                .map(CustomFieldValue::Number),
+            CustomFieldType::Money => parse_money(text).map(CustomFieldValue::Money),
+            CustomFieldType::Date => self
+                .dates
+                .parse(
+                    text,
+                    doc.language.as_deref(),
+                    doc.imported_date.naive_utc().date(),
+                )
+                .map(CustomFieldValue::Date),
+        }
+    }
+
+    /// Rebuilds the regular expressions, has to be called whenever the rules change
+    fn compile(&mut self) {
+        self.compiled.clear();
+        for rule in self.rules.sorted() {
+            match compile(rule) {
+                Ok(regex) => self.compiled.push((rule.id, regex)),
+                Err(e) => error!("Invalid extraction rule in config: {:#?} ({})", rule, e),
+            }
+        }
+    }
+}
+
+fn compile(rule: &ExtractionRuleConfig) -> Result<Regex, TaggingError> {
+    Ok(RegexBuilder::new(&rule.regex)
+        .case_insensitive(rule.case_insensitive)
+        .build()?)
+}
+
+///////////////////////////////////// Parsing ///////////////////////////////////////
+
+/// Decimal number split into its digits
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Decimal {
+    pub negative: bool,
+    pub units: String,
+    pub fraction: String,
+}
+
+impl Decimal {
+    /// Value in hundredths, the fraction is rounded to two digits
+    pub fn cents(&self) -> Option<i64> {
+        let units: i64 = self.units.parse().ok()?;
+        let fraction = format!("{:0<3}", self.fraction);
+        let mut cents = units.checked_mul(100)? + fraction[..2].parse::<i64>().ok()?;
+        if fraction.as_bytes()[2] >= b'5' {
+            cents += 1;
+        }
+        Some(if self.negative { -cents } else { cents })
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        if self.fraction.is_empty() {
+            write!(f, "{}{}", sign, self.units)
+        } else {
+            write!(f, "{}{}.{}", sign, self.units, self.fraction)
+        }
+    }
+}
+
+/// Parses numbers in English (`1,234.56`) and European (`1.234,56`) notation.
+/// If only one separator occurs once and is followed by three digits, it separates thousands.
+pub(crate) fn parse_decimal(text: &str) -> Option<Decimal> {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'')
+        .collect();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(t) => (true, t.to_string()),
+        None => (false, text),
+    };
+    if text.is_empty()
+        || !text
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+        || !text.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let decimal_separator = match (text.rfind('.'), text.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) | (None, Some(_)) => {
+            let separator = if text.contains('.') { '.' } else { ',' };
+            let parts: Vec<&str> = text.split(separator).collect();
+            if parts.len() == 2 && parts[1].len() != 3 {
+                Some(separator)
+            } else {
+                None
+            }
+        }
+        (None, None) => None,
+    };
+    let (units, fraction) = match decimal_separator {
+        Some(separator) => {
+            let (units, fraction) = text.rsplit_once(separator)?;
+            (units.to_string(), fraction.to_string())
+        }
+        None => (text.clone(), String::new()),
+    };
+    // All remaining separators separate groups of three digits
+    let mut groups = units.split(&['.', ','][..]);
+    let first = groups.next()?;
+    if first.is_empty() || fraction.contains(&['.', ','][..]) {
+        return None;
+    }
+    let mut digits = first.to_string();
+    for group in groups {
+        if group.len() != 3 {
+            return None;
+        }
+        digits.push_str(group);
+    }
+    Some(Decimal {
+        negative,
+        units: digits,
+        fraction,
+    })
+}
+
+/// Symbols of currencies, which are written instead of their code
+pub(crate) const CURRENCY_SYMBOLS: &[(&str, &str)] =
+    &[("€", "EUR"), ("$", "USD"), ("£", "GBP"), ("¥", "JPY")];
+
+/// Parses amounts like `1.234,56 €`, `EUR 12.50` or `$1,000`, the currency defaults to EUR
+pub(crate) fn parse_money(text: &str) -> Option<Money> {
+    let mut currency = None;
+    let mut amount = text.to_string();
+    for (symbol, code) in CURRENCY_SYMBOLS {
+        if amount.contains(symbol) {
+            currency = Some(code.to_string());
+            amount = amount.replace(symbol, "");
+        }
+    }
+    let mut number = vec![];
+    for word in amount.split_whitespace() {
+        if word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()) {
+            currency = Some(word.into());
+        } else {
+            number.push(word);
+        }
+    }
+    let decimal = parse_decimal(&number.join(" "))?;
+    Some(Money {
+        cents: decimal.cents()?,
+        currency: currency.unwrap_or_else(|| "EUR".into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_decimal, parse_money, ExtractionRuleConfig, ExtractionRules};
+    use crate::index::document_repository::DocumentData;
+    use crate::metadata::custom_field::{
+        CustomFieldConfig, CustomFieldType, CustomFieldValue, CustomFields, Money,
+    };
+    use chrono::NaiveDate;
+
+    fn get_document(text: &str) -> DocumentData {
+        DocumentData::test(0).with_body(text)
+    }
+
+    fn rule(regex: &str) -> ExtractionRuleConfig {
+        ExtractionRuleConfig {
+            id: 0,
+            name: "Invoice".into(),
+            regex: regex.into(),
+            case_insensitive: true,
+            tag: None,
+            correspondent: None,
+        }
+    }
+
+    #[test]
+    fn parse_test() {
+        let cents = |text| parse_decimal(text).and_then(|d| d.cents());
+        assert_eq!(cents("1.234,56"), Some(123456));
+        assert_eq!(cents("1,234.56"), Some(123456));
+        assert_eq!(cents("1.234"), Some(123400));
+        assert_eq!(cents("12,5"), Some(1250));
+        assert_eq!(cents("-0,05"), Some(-5));
+        assert_eq!(cents("2.345,678"), Some(234568));
+        assert_eq!(cents("1 234 567,89"), Some(123456789));
+        assert_eq!(cents("12.34.5"), None);
+        assert_eq!(cents("abc"), None);
+
+        let money = |cents, currency: &str| {
+            Some(Money {
+                cents,
+                currency: currency.into(),
+            })
+        };
+        assert_eq!(parse_money("1.234,56 €"), money(123456, "EUR"));
+        assert_eq!(parse_money("$1,000"), money(100000, "USD"));
+        assert_eq!(parse_money("CHF 12.50"), money(1250, "CHF"));
+        assert_eq!(parse_money("12,50"), money(1250, "EUR"));
+    }
+
+    #[test]
+    fn extraction_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut fields = CustomFields::new(dir.path()).unwrap();
+        let mut add_field = |name: &str, field_type| {
+            fields
+                .add_or_replace(CustomFieldConfig {
+                    id: 0,
+                    name: name.into(),
+                    field_type,
+                })
+                .unwrap()
+        };
+        let invoice_no = add_field("invoice_no", CustomFieldType::String);
+        let amount = add_field("amount", CustomFieldType::Money);
+        let due = add_field("due", CustomFieldType::Date);
+
+        let mut rules = ExtractionRules::new(dir.path()).unwrap();
+        rules
+            .add_or_replace(
+                rule(r"Rechnungsnummer:\s*(?P<invoice_no>\S+).*?Betrag:\s*(?P<amount>[\d.,]+ ?€)"),
+                &fields,
+            )
+            .unwrap();
+        let mut scoped = rule(r"fällig am (?P<due>\S+)");
+        scoped.tag = Some(1);
+        let scoped = rules.add_or_replace(scoped, &fields).unwrap();
+        // Lower IDs win
+        rules
+            .add_or_replace(rule(r"Nr\. (?P<invoice_no>\d+)"), &fields)
+            .unwrap();
+
+        // Capture groups have to name custom fields
+        assert!(rules
+            .add_or_replace(rule(r"(?P<unknown>\d+)"), &fields)
+            .is_err());
+        assert!(rules.add_or_replace(rule(r"\d+"), &fields).is_err());
+
+        let mut doc =
+            get_document("Rechnungsnummer: R-42 Nr. 17 Betrag: 1.234,50 € fällig am 31.12.2021");
+        let preview = rules.preview(&doc, &fields);
+        assert_eq!(preview.len(), 3);
+        assert_eq!(preview[0].values[0].text, "R-42");
+        assert_eq!(preview[0].values[0].range, (17, 21));
+        assert!(!preview[1].applies);
+        assert_eq!(preview[1].values[0].field, due);
+
+        rules.apply(&mut doc, &fields);
+        assert_eq!(doc.custom_fields.len(), 2);
+        assert_eq!(
+            doc.custom_fields[&invoice_no],
+            CustomFieldValue::String("R-42".into())
+        );
+        assert_eq!(
+            doc.custom_fields[&amount],
+            CustomFieldValue::Money(Money {
+                cents: 123450,
+                currency: "EUR".into()
+            })
+        );
+
+        doc.tags = vec![1];
+        rules.apply(&mut doc, &fields);
+        assert_eq!(
+            doc.custom_fields[&due],
+            CustomFieldValue::Date(NaiveDate::from_ymd(2021, 12, 31))
+        );
+
+        // Reload from disk
+        let mut rules = ExtractionRules::new(dir.path()).unwrap();
+        assert_eq!(rules.get_all().len(), 3);
+        rules.remove(scoped).unwrap();
+        assert!(rules.get(scoped).is_none());
+    }
+}
@@ -0,0 +1,330 @@
+use regex::Regex;
+
+/// Lengths of the IBANs of common countries, IBANs of other countries have 15 to 34 characters
+const IBAN_LENGTHS: &[(&str, usize)] = &[
+    ("AT", 20),
+    ("BE", 16),
+    ("CH", 21),
+    ("CZ", 24),
+    ("DE", 22),
+    ("DK", 18),
+    ("ES", 24),
+    ("FI", 18),
+    ("FR", 27),
+    ("GB", 22),
+    ("IE", 22),
+    ("IT", 27),
+    ("LI", 21),
+    ("LU", 20),
+    ("NL", 18),
+    ("NO", 15),
+    ("PL", 28),
+    ("PT", 25),
+    ("SE", 24),
+];
+
+/// Formats of the VAT identification numbers of the EU member states, without the country prefix
+const VAT_FORMATS: &[(&str, &str)] = &[
+    ("AT", r"U\d{8}"),
+    ("BE", r"[01]\d{9}"),
+    ("BG", r"\d{9,10}"),
+    ("CY", r"\d{8}[A-Z]"),
+    ("CZ", r"\d{8,10}"),
+    ("DE", r"\d{9}"),
+    ("DK", r"\d{8}"),
+    ("EE", r"\d{9}"),
+    ("EL", r"\d{9}"),
+    ("ES", r"[A-Z0-9]\d{7}[A-Z0-9]"),
+    ("FI", r"\d{8}"),
+    ("FR", r"[A-HJ-NP-Z0-9]{2}\d{9}"),
+    ("HR", r"\d{11}"),
+    ("HU", r"\d{8}"),
+    ("IE", r"\d[0-9A-Z+*]\d{5}[A-W][A-I]?"),
+    ("IT", r"\d{11}"),
+    ("LT", r"\d{9}|\d{12}"),
+    ("LU", r"\d{8}"),
+    ("LV", r"\d{11}"),
+    ("MT", r"\d{8}"),
+    ("NL", r"\d{9}B\d{2}"),
+    ("PL", r"\d{10}"),
+    ("PT", r"\d{9}"),
+    ("RO", r"\d{8,10}"),
+    ("SE", r"\d{12}"),
+    ("SI", r"\d{8}"),
+    ("SK", r"\d{10}"),
+    ("XI", r"\d{9}|\d{12}"),
+];
+
+/// Finds and validates bank account and tax identifiers in the body of a document.
+/// All identifiers are returned without whitespace and in upper case.
+pub struct IdentifierExtractor {
+    iban: Regex,
+    bic: Regex,
+    vat_id: Regex,
+    vat_formats: Vec<(&'static str, Regex)>,
+    tax_number: Regex,
+    tax_number_keyword: Regex,
+}
+
+impl Default for IdentifierExtractor {
+    fn default() -> Self {
+        IdentifierExtractor::new()
+    }
+}
+
+impl IdentifierExtractor {
+    pub fn new() -> IdentifierExtractor {
+        let countries: Vec<&str> = VAT_FORMATS.iter().map(|(c, _)| *c).collect();
+        IdentifierExtractor {
+            iban: Regex::new(r"\b[A-Z]{2}\d{2} ?(?:[A-Z0-9]{4} ?){2,7}[A-Z0-9]{1,4}\b")
+                .expect("invalid IBAN pattern"),
+            // BICs look like ordinary words, so only those after `BIC` or `SWIFT` are taken
+            bic: Regex::new(
+                r"(?i:\b(?:BIC|SWIFT)(?:[- ]?Code)?)\.?:?\s*(?P<bic>[A-Z]{4} ?[A-Z]{2} ?[A-Z0-9]{2}(?: ?[A-Z0-9]{3})?)\b",
+            )
+            .expect("invalid BIC pattern"),
+            vat_id: Regex::new(&format!(
+                r"\b(?P<country>{}) ?(?P<number>[0-9A-Z+*](?: ?[0-9A-Z+*]){{7,11}})\b",
+                countries.join("|")
+            ))
+            .expect("invalid VAT ID pattern"),
+            vat_formats: VAT_FORMATS
+                .iter()
+                .map(|(country, format)| {
+                    let regex =
+                        Regex::new(&format!("^(?:{})$", format)).expect("invalid VAT ID format");
+                    (*country, regex)
+                })
+                .collect(),
+            // Tax numbers of the federal states, e.g. `123/456/78901` or `12/345/67890`
+            tax_number: Regex::new(r"\b\d{2,3}/\d{3,4}/\d{4,5}\b")
+                .expect("invalid tax number pattern"),
+            // Other notations are only taken after a keyword
+            tax_number_keyword: Regex::new(
+                r"(?i:\b(?:Steuernummer|Steuer-Nr|St\.?-?Nr))\.?:?\s*(?P<number>\d{3} \d{3,4} \d{4,5}|\d{13})\b",
+            )
+            .expect("invalid tax number pattern"),
+        }
+    }
+
+    /// Returns the IBANs with a valid checksum
+    pub fn ibans(&self, body: &str) -> Vec<String> {
+        let mut ibans = vec![];
+        let mut start = 0;
+        while let Some(m) = self.iban.find_at(body, start) {
+            // The pattern also takes the next token in capitals, e.g. `BIC`, so the longest prefix
+            // which is a valid IBAN is used and the search continues after it
+            let text = m.as_str();
+            let iban = (1..=text.len())
+                .rev()
+                .filter(|end| *end == text.len() || text[*end..].starts_with(' '))
+                .find_map(|end| normalize_iban(&text[..end]).map(|iban| (iban, end)));
+            start = match iban {
+                Some((iban, end)) => {
+                    ibans.push(iban);
+                    m.start() + end
+                }
+                None => m.end(),
+            };
+        }
+        distinct(ibans.into_iter())
+    }
+
+    pub fn bics(&self, body: &str) -> Vec<String> {
+        distinct(
+            self.bic
+                .captures_iter(body)
+                .filter_map(|c| c.name("bic"))
+                .map(|m| strip_spaces(m.as_str())),
+        )
+    }
+
+    /// Returns the VAT identification numbers, German ones are checked by their check digit
+    pub fn vat_ids(&self, body: &str) -> Vec<String> {
+        distinct(self.vat_id.captures_iter(body).filter_map(|c| {
+            let country = c.name("country")?.as_str();
+            let number = strip_spaces(c.name("number")?.as_str());
+            let (_, format) = self.vat_formats.iter().find(|(c, _)| *c == country)?;
+            if !format.is_match(&number) || (country == "DE" && !valid_german_vat_id(&number)) {
+                return None;
+            }
+            Some(format!("{}{}", country, number))
+        }))
+    }
+
+    /// Returns the German tax numbers (Steuernummern), the parts of a number are separated by `/`
+    pub fn tax_numbers(&self, body: &str) -> Vec<String> {
+        let with_keyword = self
+            .tax_number_keyword
+            .captures_iter(body)
+            .filter_map(|c| c.name("number"))
+            .map(|m| m.as_str().split(' ').collect::<Vec<&str>>().join("/"));
+        let with_slashes = self
+            .tax_number
+            .find_iter(body)
+            .map(|m| m.as_str().to_string());
+        distinct(with_keyword.chain(with_slashes).filter(|n| {
+            let digits = n.chars().filter(|c| c.is_ascii_digit()).count();
+            digits == 10 || digits == 11 || digits == 13
+        }))
+    }
+}
+
+/// Removes whitespace, converts the IBAN to upper case and checks its length and checksum (ISO 13616, mod 97)
+pub fn normalize_iban(iban: &str) -> Option<String> {
+    let iban = strip_spaces(iban).to_uppercase();
+    if !iban.is_ascii()
+        || iban.len() < 4
+        || !iban[..2].chars().all(|c| c.is_ascii_uppercase())
+        || !iban[2..4].chars().all(|c| c.is_ascii_digit())
+        || !iban.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    let valid_length = match IBAN_LENGTHS.iter().find(|(c, _)| *c == &iban[..2]) {
+        Some((_, length)) => iban.len() == *length,
+        None => (15..=34).contains(&iban.len()),
+    };
+    if !valid_length {
+        return None;
+    }
+    // The country and checksum are moved to the end, letters count as 10 to 35
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .fold(0u32, |remainder, c| {
+            let value = c.to_digit(36).unwrap();
+            if value < 10 {
+                (remainder * 10 + value) % 97
+            } else {
+                (remainder * 100 + value) % 97
+            }
+        });
+    if remainder == 1 {
+        Some(iban)
+    } else {
+        None
+    }
+}
+
+/// Checks the last digit of a German VAT ID (ISO 7064, MOD 11,10)
+fn valid_german_vat_id(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 9 {
+        return false;
+    }
+    let mut product = 10;
+    for digit in &digits[..8] {
+        let mut sum = (digit + product) % 10;
+        if sum == 0 {
+            sum = 10;
+        }
+        product = (2 * sum) % 11;
+    }
+    (11 - product) % 10 == digits[8]
+}
+
+fn strip_spaces(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn distinct(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for value in values {
+        if !result.contains(&value) {
+            result.push(value);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_iban, valid_german_vat_id, IdentifierExtractor};
+
+    #[test]
+    fn iban_test() {
+        assert_eq!(
+            normalize_iban("DE89 3704 0044 0532 0130 00"),
+            Some("DE89370400440532013000".into())
+        );
+        assert_eq!(
+            normalize_iban("gb82west12345698765432"),
+            Some("GB82WEST12345698765432".into())
+        );
+        // Wrong checksum, wrong length for the country
+        assert_eq!(normalize_iban("DE89 3704 0044 0532 0130 01"), None);
+        assert_eq!(normalize_iban("DE89 3704 0044 0532 0130 0"), None);
+        assert_eq!(normalize_iban("DE"), None);
+
+        let extractor = IdentifierExtractor::new();
+        assert_eq!(
+            extractor.ibans(
+                "IBAN: DE89 3704 0044 0532 0130 00 BIC: COBADEFFXXX\n\
+                 IBAN AT61 1904 3002 3457 3201, OCR: DE89 3704 0O44 0532 0130 00, DE89370400440532013000"
+            ),
+            vec!["DE89370400440532013000", "AT611904300234573201"]
+        );
+        // Tokens in capitals after an IBAN are not part of it
+        assert_eq!(
+            extractor.ibans("IBAN AT61 1904 3002 3457 3201 BIC BKAUATWW"),
+            vec!["AT611904300234573201"]
+        );
+        assert_eq!(
+            extractor.ibans(
+                "Konto AT61 1904 3002 3457 3201 Bank Austria\n\
+                 AT61 1904 3002 3457 3201 WIEN\n\
+                 AT61 1904 3002 3457 3201 DE89 3704 0044 0532 0130 00 GB82 WEST 1234 5698 7654 32"
+            ),
+            vec![
+                "AT611904300234573201",
+                "DE89370400440532013000",
+                "GB82WEST12345698765432"
+            ]
+        );
+    }
+
+    #[test]
+    fn bic_test() {
+        let extractor = IdentifierExtractor::new();
+        assert_eq!(
+            extractor.bics(
+                "IBAN DE89 3704 0044 0532 0130 00, BIC: COBADEFFXXX\nSWIFT-Code MARK DE F1 100"
+            ),
+            vec!["COBADEFFXXX", "MARKDEF1100"]
+        );
+        // Words in capitals are no BICs
+        assert!(extractor.bics("RECHNUNG BESTELLUNG").is_empty());
+    }
+
+    #[test]
+    fn vat_id_test() {
+        assert!(valid_german_vat_id("136695976"));
+        assert!(!valid_german_vat_id("136695977"));
+
+        let extractor = IdentifierExtractor::new();
+        assert_eq!(
+            extractor.vat_ids(
+                "USt-IdNr.: DE 136 695 976, ATU12345678, NL123456789B01, FR40303265045\n\
+                 DE123456789 has a wrong check digit, IT 123 is too short"
+            ),
+            vec![
+                "DE136695976",
+                "ATU12345678",
+                "NL123456789B01",
+                "FR40303265045"
+            ]
+        );
+    }
+
+    #[test]
+    fn tax_number_test() {
+        let extractor = IdentifierExtractor::new();
+        assert_eq!(
+            extractor.tax_numbers(
+                "Steuernummer: 123 456 78901, St.-Nr. 2181508150123\nStNr 12/345/67890, Datum 12/03/2021"
+            ),
+            vec!["123/456/78901", "2181508150123", "12/345/67890"]
+        );
+    }
+}
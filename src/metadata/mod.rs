@@ -1,3 +1,12 @@
+pub mod amount;
+pub mod classifier;
 pub mod content;
+pub mod correspondent;
+pub mod custom_field;
+pub mod date;
+pub mod document_type;
+pub mod extraction;
 pub mod file_extractor;
+pub mod identifier;
+pub mod registry;
 pub mod tag;
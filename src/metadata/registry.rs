@@ -0,0 +1,157 @@
+use crate::persistence::{self, PersistenceError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Configuration stored in a registry, identified by an ID assigned by the registry
+pub trait Entry: Serialize + DeserializeOwned + Clone {
+    /// Kind of the entries, used in log messages
+    const KIND: &'static str;
+
+    fn id(&self) -> u64;
+    fn set_id(&mut self, id: u64);
+    fn name(&self) -> &str;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(bound = "T: Entry")]
+struct RegistryConfig<T> {
+    curr_id: u64,
+    entries: Vec<T>,
+}
+
+impl<T> Default for RegistryConfig<T> {
+    fn default() -> Self {
+        RegistryConfig {
+            curr_id: 0,
+            entries: vec![],
+        }
+    }
+}
+
+/// Entries keyed by their ID, persisted as YAML file in the data directory
+pub struct Registry<T: Entry> {
+    entries: HashMap<u64, T>,
+    file: PathBuf,
+    snapshot_dir: PathBuf,
+}
+
+impl<T: Entry> Registry<T> {
+    pub fn new(data_dir: &Path, file_name: &str) -> Result<Registry<T>, PersistenceError> {
+        let mut registry = Registry {
+            entries: HashMap::new(),
+            file: data_dir.join(file_name),
+            snapshot_dir: data_dir.join("index").join("snapshots"),
+        };
+        registry.load()?;
+        Ok(registry)
+    }
+
+    /// Adds an entry with a new ID or replaces the entry with the same ID and returns its ID
+    pub fn add_or_replace(&mut self, mut entry: T) -> Result<u64, PersistenceError> {
+        let mut cfg: RegistryConfig<T> =
+            persistence::load_or_default(&self.file, &self.snapshot_dir)?;
+        if !self.entries.contains_key(&entry.id()) {
+            cfg.curr_id += 1;
+            entry.set_id(cfg.curr_id);
+            info!(
+                "Adding new {} with id {} and name `{}`",
+                T::KIND,
+                entry.id(),
+                entry.name()
+            );
+        } else {
+            info!("Replacing {} {}", T::KIND, entry.id());
+            cfg.entries.retain(|e| e.id() != entry.id());
+        }
+        let id = entry.id();
+        cfg.entries.push(entry);
+        persistence::store(&self.file, &self.snapshot_dir, &cfg)?;
+        self.load()?;
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<(), PersistenceError> {
+        info!("Remove {} {}", T::KIND, id);
+        let mut cfg: RegistryConfig<T> =
+            persistence::load_or_default(&self.file, &self.snapshot_dir)?;
+        cfg.entries.retain(|e| e.id() != id);
+        persistence::store(&self.file, &self.snapshot_dir, &cfg)?;
+        self.load()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&T> {
+        self.entries.get(&id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.values()
+    }
+
+    /// Returns all entries sorted by ID
+    pub fn sorted(&self) -> Vec<&T> {
+        let mut entries: Vec<&T> = self.entries.values().collect();
+        entries.sort_unstable_by_key(|e| e.id());
+        entries
+    }
+
+    fn load(&mut self) -> Result<(), PersistenceError> {
+        let cfg: RegistryConfig<T> = persistence::load_or_default(&self.file, &self.snapshot_dir)?;
+        self.entries = cfg.entries.into_iter().map(|e| (e.id(), e)).collect();
+        info!("Loaded {} entries from {:?}", self.entries.len(), self.file);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Registry};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    struct TestEntry {
+        id: u64,
+        name: String,
+    }
+
+    impl Entry for TestEntry {
+        const KIND: &'static str = "test entry";
+
+        fn id(&self) -> u64 {
+            self.id
+        }
+
+        fn set_id(&mut self, id: u64) {
+            self.id = id;
+        }
+
+        fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    fn entry(id: u64, name: &str) -> TestEntry {
+        TestEntry {
+            id,
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn registry_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry: Registry<TestEntry> = Registry::new(dir.path(), "test.yaml").unwrap();
+        assert_eq!(registry.add_or_replace(entry(0, "a")).unwrap(), 1);
+        // Unknown IDs are replaced by a new one
+        assert_eq!(registry.add_or_replace(entry(7, "b")).unwrap(), 2);
+        assert_eq!(registry.add_or_replace(entry(1, "c")).unwrap(), 1);
+        assert_eq!(registry.get(1), Some(&entry(1, "c")));
+
+        // IDs of removed entries are not reused
+        registry.remove(2).unwrap();
+        assert_eq!(registry.add_or_replace(entry(0, "d")).unwrap(), 3);
+
+        let registry: Registry<TestEntry> = Registry::new(dir.path(), "test.yaml").unwrap();
+        assert_eq!(registry.sorted(), vec![&entry(1, "c"), &entry(3, "d")]);
+    }
+}
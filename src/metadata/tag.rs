@@ -1,9 +1,22 @@
-use crate::index::document_repository::DocumentData;
+use crate::index::document_repository::{DocumentData, TagEvidence};
 use crate::index::DocId;
-use regex::{Regex, RegexBuilder};
-use std::collections::HashMap;
+use crate::metadata::amount::AmountExtractor;
+use crate::metadata::classifier::{Classifier, ClassifierError};
+use crate::metadata::correspondent::Correspondents;
+use crate::metadata::custom_field::{CustomFieldError, CustomFields};
+use crate::metadata::date::DateInference;
+use crate::metadata::document_type::DocumentTypes;
+use crate::metadata::extraction::{
+    ExtractionPreview, ExtractionRuleConfig, ExtractionRuleId, ExtractionRules,
+};
+use crate::metadata::identifier::IdentifierExtractor;
+use crate::persistence;
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder, RegexSet};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use whatlang::detect;
 
 //Error Handling
@@ -13,13 +26,46 @@ pub enum TaggingError {
     #[error("cannot compile regex expression")]
     RegexError(#[from] regex::Error),
     #[error("could not load/write configuration file")]
-    ConfigError(#[from] confy::ConfyError),
+    ConfigError(#[from] crate::persistence::PersistenceError),
     #[error("body of document {0} is empty")]
     EmptyBody(DocId),
+    #[error("tag {0} does not exist")]
+    UnknownTag(TagId),
+    #[error("parent tag {0} does not exist")]
+    UnknownParent(TagId),
+    #[error("tag {0} cannot be its own ancestor")]
+    Cycle(TagId),
+    #[error("could not load/write classifier")]
+    ClassifierError(#[from] ClassifierError),
+    #[error("matcher `{0}` is not supported here")]
+    UnsupportedMatcher(String),
+    #[error("invalid custom field")]
+    CustomFieldError(#[from] CustomFieldError),
+    #[error("expression `{0}` has no named capture groups")]
+    NoCaptureGroups(String),
+    #[error("capture group `{0}` is not the name of a custom field")]
+    UnknownCaptureGroup(String),
 }
 
+/// Number of characters shown before and after a match in a snippet
+const SNIPPET_CONTEXT: usize = 40;
+/// Maximum number of snippets returned per document
+const MAX_SNIPPETS: usize = 5;
+/// Maximum number of byte ranges stored as evidence per tag
+const MAX_EVIDENCE_MATCHES: usize = 20;
+
 pub trait Matcher {
     fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError>;
+
+    /// Matches the document and returns the byte ranges of the body which caused the match,
+    /// `None` if the document does not match.
+    /// Matchers which do not look at the body match without any ranges.
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        Ok(self.match_document(doc)?.then(Vec::new))
+    }
 }
 
 ///////////////////////////////////// NoMatcher ///////////////////////////////////////
@@ -62,6 +108,21 @@ impl Matcher for RegexMatcher {
         };
         Ok(self.regex.is_match(body))
     }
+
+    /// Stops after the number of matches kept as evidence
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        let body = doc.body.as_ref().ok_or(TaggingError::EmptyBody(doc.id))?;
+        let matches: Vec<(usize, usize)> = self
+            .regex
+            .find_iter(body)
+            .take(MAX_EVIDENCE_MATCHES)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        Ok(Some(matches).filter(|m| !m.is_empty()))
+    }
 }
 
 ///////////////////////////////////// Full Matcher ///////////////////////////////////////
@@ -86,6 +147,13 @@ impl Matcher for FullMatcher {
     fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
         self.regex.match_document(doc)
     }
+
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        self.regex.find_matches(doc)
+    }
 }
 
 ///////////////////////////////////// Any Matcher ///////////////////////////////////////
@@ -96,13 +164,7 @@ pub struct AnyMatcher {
 
 impl AnyMatcher {
     pub fn new(term: &str, case_insensitive: bool) -> Result<AnyMatcher, TaggingError> {
-        let terms = "(".to_owned()
-            + &term
-                .split(',')
-                .map(|s| s.trim())
-                .collect::<Vec<&str>>()
-                .join("|")
-            + ")";
+        let terms = AnyMatcher::pattern(term);
         Ok(AnyMatcher {
             regex: RegexMatcher::new(
                 RegexBuilder::new(&terms)
@@ -111,19 +173,577 @@ impl AnyMatcher {
             ),
         })
     }
+
+    /// Converts the comma separated terms into a regular expression
+    fn pattern(term: &str) -> String {
+        "(".to_owned()
+            + &term
+                .split(',')
+                .map(|s| s.trim())
+                .collect::<Vec<&str>>()
+                .join("|")
+            + ")"
+    }
 }
 
 impl Matcher for AnyMatcher {
     fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
         self.regex.match_document(doc)
     }
+
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        self.regex.find_matches(doc)
+    }
+}
+
+///////////////////////////////////// Fuzzy Matcher ///////////////////////////////////////
+
+/// Case insensitive matcher tolerating OCR errors.
+/// It ignores whitespace, treats commonly confused characters (0/O, 1/l/I, rn/m) as equal
+/// and allows the given number of remaining edits (Levenshtein distance).
+pub struct FuzzyMatcher {
+    term: Vec<char>,
+    max_distance: usize,
+}
+
+impl FuzzyMatcher {
+    pub fn new(term: &str, max_distance: usize) -> FuzzyMatcher {
+        let term: Vec<char> = FuzzyMatcher::normalize(term)
+            .into_iter()
+            .map(|(c, _, _)| c)
+            .collect();
+        // Otherwise every text would match
+        let max_distance = max_distance.min(term.len().saturating_sub(1));
+        FuzzyMatcher { term, max_distance }
+    }
+
+    /// Lowercases the text, removes whitespace and replaces commonly confused characters.
+    /// Returns the characters together with the byte range they originate from.
+    fn normalize(text: &str) -> Vec<(char, usize, usize)> {
+        let mut normalized: Vec<(char, usize, usize)> = vec![];
+        for (start, c) in text.char_indices() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let end = start + c.len_utf8();
+            for lower in c.to_lowercase() {
+                let lower = match lower {
+                    '0' => 'o',
+                    '1' | 'i' | '|' => 'l',
+                    c => c,
+                };
+                match normalized.last_mut() {
+                    Some(last) if last.0 == 'r' && lower == 'n' => *last = ('m', last.1, end),
+                    _ => normalized.push((lower, start, end)),
+                }
+            }
+        }
+        normalized
+    }
+
+    /// Finds all parts of the text within the allowed distance of the term,
+    /// returns their start and end indices in the normalized text
+    fn find(&self, text: &[(char, usize, usize)]) -> Vec<(usize, usize)> {
+        let m = self.term.len();
+        let mut matches = vec![];
+        if m == 0 {
+            return matches;
+        }
+        // Distance of the term prefixes to the best text part ending at the current position,
+        // together with the start of this part
+        let mut prev: Vec<(usize, usize)> = (0..=m).map(|i| (i, 0)).collect();
+        let mut curr: Vec<(usize, usize)> = vec![(0, 0); m + 1];
+        // Best match of the current run of overlapping matches
+        let mut best: Option<(usize, usize, usize)> = None;
+        for (j, (c, _, _)) in text.iter().enumerate() {
+            curr[0] = (0, j + 1);
+            for i in 1..=m {
+                let substitute = (
+                    prev[i - 1].0 + (self.term[i - 1] != *c) as usize,
+                    prev[i - 1].1,
+                );
+                let skip_text = (prev[i].0 + 1, prev[i].1);
+                let skip_term = (curr[i - 1].0 + 1, curr[i - 1].1);
+                curr[i] = *[substitute, skip_text, skip_term]
+                    .iter()
+                    .min_by_key(|(d, _)| *d)
+                    .unwrap();
+            }
+            let (distance, start) = curr[m];
+            if distance <= self.max_distance {
+                best = match best {
+                    Some((s, e, d)) if start < e => {
+                        if distance < d {
+                            Some((start, j + 1, distance))
+                        } else {
+                            Some((s, e, d))
+                        }
+                    }
+                    Some((s, e, _)) => {
+                        matches.push((s, e));
+                        Some((start, j + 1, distance))
+                    }
+                    None => Some((start, j + 1, distance)),
+                };
+            }
+            std::mem::swap(&mut prev, &mut curr);
+        }
+        if let Some((s, e, _)) = best {
+            matches.push((s, e));
+        }
+        matches
+    }
+}
+
+impl Matcher for FuzzyMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        Ok(self.find_matches(doc)?.is_some())
+    }
+
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        let body = doc.body.as_ref().ok_or(TaggingError::EmptyBody(doc.id))?;
+        let text = FuzzyMatcher::normalize(body);
+        let matches: Vec<(usize, usize)> = self
+            .find(&text)
+            .into_iter()
+            .map(|(start, end)| (text[start].1, text[end - 1].2))
+            .collect();
+        Ok(Some(matches).filter(|m| !m.is_empty()))
+    }
+}
+
+///////////////////////////////////// Boolean Matchers ///////////////////////////////////////
+
+/// Matcher matching if all of its sub-matchers match.
+/// Without sub-matchers, it matches no document.
+pub struct AndMatcher {
+    matchers: Vec<Box<dyn Matcher + Send + Sync>>,
+}
+
+impl AndMatcher {
+    pub fn new(matchers: Vec<Box<dyn Matcher + Send + Sync>>) -> AndMatcher {
+        AndMatcher { matchers }
+    }
+}
+
+impl Matcher for AndMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        for matcher in &self.matchers {
+            if !matcher.match_document(doc)? {
+                return Ok(false);
+            }
+        }
+        Ok(!self.matchers.is_empty())
+    }
+
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        if self.matchers.is_empty() {
+            return Ok(None);
+        }
+        let mut matches = vec![];
+        for matcher in &self.matchers {
+            match matcher.find_matches(doc)? {
+                Some(mut m) => matches.append(&mut m),
+                None => return Ok(None),
+            }
+        }
+        matches.sort_unstable();
+        Ok(Some(matches))
+    }
+}
+
+/// Matcher matching if at least one of its sub-matchers matches
+pub struct OrMatcher {
+    matchers: Vec<Box<dyn Matcher + Send + Sync>>,
+}
+
+impl OrMatcher {
+    pub fn new(matchers: Vec<Box<dyn Matcher + Send + Sync>>) -> OrMatcher {
+        OrMatcher { matchers }
+    }
+}
+
+impl Matcher for OrMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        for matcher in &self.matchers {
+            if matcher.match_document(doc)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Evaluates all sub-matchers to collect the ranges of each matching one
+    fn find_matches(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Option<Vec<(usize, usize)>>, TaggingError> {
+        let mut matches = None;
+        for matcher in &self.matchers {
+            if let Some(mut m) = matcher.find_matches(doc)? {
+                matches.get_or_insert_with(Vec::new).append(&mut m);
+            }
+        }
+        if let Some(m) = &mut matches {
+            m.sort_unstable();
+        }
+        Ok(matches)
+    }
+}
+
+/// Matcher inverting its sub-matcher
+pub struct NotMatcher {
+    matcher: Box<dyn Matcher + Send + Sync>,
+}
+
+impl NotMatcher {
+    pub fn new(matcher: Box<dyn Matcher + Send + Sync>) -> NotMatcher {
+        NotMatcher { matcher }
+    }
+}
+
+impl Matcher for NotMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        Ok(!self.matcher.match_document(doc)?)
+    }
+}
+
+///////////////////////////////////// Field Matchers ///////////////////////////////////////
+
+/// Metadata field of a document which can be matched instead of the body
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentField {
+    OriginalFilename,
+    Title,
+    Language,
+    Iban,
+    Email,
+    Phone,
+    Link,
+    /// Author embedded in the file
+    Author,
+    /// Subject embedded in the file
+    Subject,
+    /// Keywords embedded in the file
+    Keywords,
+}
+
+impl DocumentField {
+    fn values<'a>(&self, doc: &'a DocumentData) -> Vec<&'a str> {
+        match self {
+            DocumentField::OriginalFilename => vec![&doc.original_filename],
+            DocumentField::Title => vec![&doc.title],
+            DocumentField::Language => doc.language.as_deref().into_iter().collect(),
+            DocumentField::Iban => doc.extracted.iban.iter().map(|s| s.as_str()).collect(),
+            DocumentField::Email => doc.extracted.email.iter().map(|s| s.as_str()).collect(),
+            DocumentField::Phone => doc.extracted.phone.iter().map(|s| s.as_str()).collect(),
+            DocumentField::Link => doc.extracted.link.iter().map(|s| s.as_str()).collect(),
+            DocumentField::Author => doc.file_metadata.author.as_deref().into_iter().collect(),
+            DocumentField::Subject => doc.file_metadata.subject.as_deref().into_iter().collect(),
+            DocumentField::Keywords => doc
+                .file_metadata
+                .keywords
+                .iter()
+                .map(|s| s.as_str())
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for DocumentField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DocumentField::OriginalFilename => write!(f, "original filename"),
+            DocumentField::Title => write!(f, "title"),
+            DocumentField::Language => write!(f, "language"),
+            DocumentField::Iban => write!(f, "IBAN"),
+            DocumentField::Email => write!(f, "e-mail address"),
+            DocumentField::Phone => write!(f, "phone number"),
+            DocumentField::Link => write!(f, "link"),
+            DocumentField::Author => write!(f, "author"),
+            DocumentField::Subject => write!(f, "subject"),
+            DocumentField::Keywords => write!(f, "keyword"),
+        }
+    }
+}
+
+/// How the value of a field is compared
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldCondition {
+    Equals,
+    StartsWith,
+    EndsWith,
+    Contains,
+    Regex,
+}
+
+impl std::fmt::Display for FieldCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FieldCondition::Equals => write!(f, "equals"),
+            FieldCondition::StartsWith => write!(f, "starts with"),
+            FieldCondition::EndsWith => write!(f, "ends with"),
+            FieldCondition::Contains => write!(f, "contains"),
+            FieldCondition::Regex => write!(f, "matches"),
+        }
+    }
+}
+
+/// Matcher matching if any value of a metadata field fulfills the condition.
+/// IBANs are compared without whitespace.
+pub struct FieldMatcher {
+    field: DocumentField,
+    regex: Regex,
+}
+
+impl FieldMatcher {
+    pub fn new(
+        field: DocumentField,
+        condition: FieldCondition,
+        value: &str,
+        case_insensitive: bool,
+    ) -> Result<FieldMatcher, TaggingError> {
+        let value = match field {
+            DocumentField::Iban if condition != FieldCondition::Regex => strip_whitespace(value),
+            _ => value.to_string(),
+        };
+        let pattern = match condition {
+            FieldCondition::Equals => format!("^{}$", regex::escape(&value)),
+            FieldCondition::StartsWith => format!("^{}", regex::escape(&value)),
+            FieldCondition::EndsWith => format!("{}$", regex::escape(&value)),
+            FieldCondition::Contains => regex::escape(&value),
+            FieldCondition::Regex => value,
+        };
+        Ok(FieldMatcher {
+            field,
+            regex: RegexBuilder::new(&pattern)
+                .case_insensitive(case_insensitive)
+                .build()?,
+        })
+    }
+}
+
+impl Matcher for FieldMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        Ok(self
+            .field
+            .values(doc)
+            .into_iter()
+            .any(|v| match self.field {
+                DocumentField::Iban => self.regex.is_match(&strip_whitespace(v)),
+                _ => self.regex.is_match(v),
+            }))
+    }
+}
+
+fn digits(s: &str) -> String {
+    s.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+pub(crate) fn strip_whitespace(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Matcher matching documents whose file size in bytes lies within the bounds
+pub struct FileSizeMatcher {
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl FileSizeMatcher {
+    pub fn new(min: Option<u64>, max: Option<u64>) -> FileSizeMatcher {
+        FileSizeMatcher { min, max }
+    }
+}
+
+impl Matcher for FileSizeMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        Ok(self.min.map_or(true, |min| doc.file_size >= min)
+            && self.max.map_or(true, |max| doc.file_size <= max))
+    }
+}
+
+/// Matcher matching documents whose extracted date lies within the bounds (both inclusive).
+/// Documents without a date never match.
+pub struct DateMatcher {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+impl DateMatcher {
+    pub fn new(from: Option<NaiveDate>, to: Option<NaiveDate>) -> DateMatcher {
+        DateMatcher { from, to }
+    }
+}
+
+impl Matcher for DateMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        let date = match doc.extracted.doc_date {
+            Some(d) => d.date().naive_utc(),
+            None => return Ok(false),
+        };
+        Ok(self.from.map_or(true, |from| date >= from) && self.to.map_or(true, |to| date <= to))
+    }
+}
+
+///////////////////////////////////// Auto Matcher ///////////////////////////////////////
+
+/// Matcher using the classifier trained on the documents tagged by hand.
+/// Matches if the predicted probability is at least the threshold.
+pub struct AutoMatcher {
+    tag: TagId,
+    threshold: f64,
+    classifier: Arc<Classifier>,
+}
+
+impl AutoMatcher {
+    pub fn new(tag: TagId, threshold: f64, classifier: Arc<Classifier>) -> AutoMatcher {
+        AutoMatcher {
+            tag,
+            threshold,
+            classifier,
+        }
+    }
+}
+
+impl Matcher for AutoMatcher {
+    fn match_document(&self, doc: &DocumentData) -> Result<bool, TaggingError> {
+        let body = doc.body.as_ref().ok_or(TaggingError::EmptyBody(doc.id))?;
+        Ok(self
+            .classifier
+            .predict(self.tag, body)
+            .map(|p| p >= self.threshold)
+            .unwrap_or(false))
+    }
+}
+
+///////////////////////////////////// Compiled Tags ///////////////////////////////////////
+
+/// Matchers of all tags, which are compiled once when the tags change.
+/// Tags matching the body with a single regular expression are evaluated together in one pass.
+struct CompiledTags {
+    /// All valid tags, sorted by ID
+    tags: Vec<Tag>,
+    /// Tags evaluated by the regex set, in the order of its patterns
+    set_tags: Vec<TagId>,
+    set: RegexSet,
+}
+
+impl CompiledTags {
+    fn new(configs: &HashMap<TagId, TagConfig>, classifier: &Arc<Classifier>) -> CompiledTags {
+        let mut tags = vec![];
+        for tag_cfg in configs.values() {
+            match from_tag_config(tag_cfg, classifier) {
+                Ok(t) => tags.push(t),
+                Err(e) => error!("Invalid tag in config: {:#?} ({})", tag_cfg, e),
+            }
+        }
+        tags.sort_unstable_by_key(|t| t.id);
+
+        let (set_tags, patterns): (Vec<TagId>, Vec<String>) = tags
+            .iter()
+            .filter_map(|t| regex_pattern(&configs[&t.id].matcher).map(|p| (t.id, p)))
+            .unzip();
+        let (set_tags, set) = match RegexSet::new(&patterns) {
+            Ok(set) => (set_tags, set),
+            Err(e) => {
+                warn!("Could not combine regular expressions of tags: {}", e);
+                (vec![], RegexSet::empty())
+            }
+        };
+        debug!(
+            "Compiled {} tags, {} of them in a regex set",
+            tags.len(),
+            set_tags.len()
+        );
+        CompiledTags {
+            tags,
+            set_tags,
+            set,
+        }
+    }
+
+    fn get(&self, id: TagId) -> Option<&Tag> {
+        self.tags
+            .binary_search_by_key(&id, |t| t.id)
+            .ok()
+            .map(|i| &self.tags[i])
+    }
+
+    /// Returns the IDs of all matching tags in ascending order with the byte ranges which caused the match
+    fn match_document(
+        &self,
+        doc: &DocumentData,
+    ) -> Result<Vec<(TagId, Vec<(usize, usize)>)>, TaggingError> {
+        let mut matches = vec![];
+        let mut evaluated: HashSet<TagId> = HashSet::new();
+        if let Some(body) = &doc.body {
+            // The set only tells which patterns match, so the ranges are searched for the hits alone
+            for id in self.set.matches(body).iter().map(|i| self.set_tags[i]) {
+                if let Some(tag) = self.get(id) {
+                    let ranges = tag.matcher.find_matches(doc)?.unwrap_or_default();
+                    matches.push((id, ranges));
+                }
+            }
+            evaluated.extend(self.set_tags.iter().copied());
+        }
+        for tag in self.tags.iter().filter(|t| !evaluated.contains(&t.id)) {
+            if let Some(ranges) = tag.matcher.find_matches(doc)? {
+                matches.push((tag.id, ranges));
+            }
+        }
+        matches.sort_unstable_by_key(|(id, _)| *id);
+        Ok(matches)
+    }
+}
+
+/// Returns the regular expression of matchers, which only match the body with a single regular expression
+fn regex_pattern(cfg: &MatcherConfig) -> Option<String> {
+    let (pattern, case_insensitive) = match cfg {
+        MatcherConfig::FullMatcher {
+            match_str,
+            case_insensitive,
+        } => (match_str.clone(), *case_insensitive),
+        MatcherConfig::AnyMatcher {
+            match_str,
+            case_insensitive,
+        } => (AnyMatcher::pattern(match_str), *case_insensitive),
+        MatcherConfig::RegexMatcher { match_str } => (match_str.clone(), false),
+        _ => return None,
+    };
+    match case_insensitive {
+        true => Some(format!("(?i:{})", pattern)),
+        false => Some(pattern),
+    }
 }
 
 ///////////////////////////////////// Tagger ///////////////////////////////////////
 ///
 pub struct Tagger {
     tags: HashMap<TagId, TagConfig>,
+    compiled: CompiledTags,
     tags_file: PathBuf,
+    classifier: Arc<Classifier>,
+    classifier_file: PathBuf,
+    snapshot_dir: PathBuf,
+    correspondents: Correspondents,
+    document_types: DocumentTypes,
+    custom_fields: CustomFields,
+    extraction_rules: ExtractionRules,
+    dates: DateInference,
+    amounts: AmountExtractor,
+    identifiers: IdentifierExtractor,
     extract_extended_metadata: bool,
 }
 
@@ -131,19 +751,33 @@ impl Tagger {
     pub fn new(data_dir: &Path, extract_extended_metadata: bool) -> Result<Tagger, TaggingError> {
         let mut tagger = Tagger {
             tags: HashMap::new(),
+            compiled: CompiledTags::new(&HashMap::new(), &Arc::default()),
             tags_file: data_dir.join("tags.toml"),
+            classifier: Arc::new(Classifier::load(&data_dir.join("classifier.json"))?),
+            classifier_file: data_dir.join("classifier.json"),
+            snapshot_dir: data_dir.join("index").join("snapshots"),
+            correspondents: Correspondents::new(data_dir)?,
+            document_types: DocumentTypes::new(data_dir)?,
+            custom_fields: CustomFields::new(data_dir)?,
+            extraction_rules: ExtractionRules::new(data_dir)?,
+            dates: DateInference::new(),
+            amounts: AmountExtractor::new(),
+            identifiers: IdentifierExtractor::new(),
             extract_extended_metadata,
         };
         tagger.load_config()?;
         Ok(tagger)
     }
 
-    pub fn add_tag(&mut self, mut tag: TagConfig) -> Result<(), TaggingError> {
+    /// Adds a new tag and returns its ID
+    pub fn add_tag(&mut self, mut tag: TagConfig) -> Result<TagId, TaggingError> {
         // Try to build tag and fail early
-        from_tag_config(&tag)?;
+        from_tag_config(&tag, &self.classifier)?;
+        self.check_parent(&tag)?;
 
         //Load config
-        let mut cfg: TagsConfig = confy::load_path(&self.tags_file)?;
+        let mut cfg: TagsConfig =
+            persistence::load_or_default(&self.tags_file, &self.snapshot_dir)?;
 
         cfg.curr_id += 1;
         tag.id = cfg.curr_id;
@@ -151,20 +785,25 @@ impl Tagger {
         //Store config
         debug!("Storing tag {:#?} in {:?}", &tag, self.tags_file);
         cfg.tags.push(tag.clone());
-        confy::store_path(&self.tags_file, cfg)?;
+        persistence::store(&self.tags_file, &self.snapshot_dir, &cfg)?;
 
         //Add to hashmap
-        self.tags.insert(tag.id, tag);
+        let id = tag.id;
+        self.tags.insert(id, tag);
+        self.compile();
 
-        Ok(())
+        Ok(id)
     }
 
-    pub fn add_or_replace_tag(&mut self, mut tag: TagConfig) -> Result<(), TaggingError> {
+    /// Adds or replaces a tag and returns its ID
+    pub fn add_or_replace_tag(&mut self, mut tag: TagConfig) -> Result<TagId, TaggingError> {
         // Try to build tag and fail early
-        from_tag_config(&tag)?;
+        from_tag_config(&tag, &self.classifier)?;
+        self.check_parent(&tag)?;
 
         //Load config
-        let mut cfg: TagsConfig = confy::load_path(&self.tags_file)?;
+        let mut cfg: TagsConfig =
+            persistence::load_or_default(&self.tags_file, &self.snapshot_dir)?;
 
         if !self.tags.contains_key(&tag.id) {
             //Set new ID
@@ -180,20 +819,28 @@ impl Tagger {
         //Store config
         debug!("Storing tag {:#?} in {:?}", &tag, self.tags_file);
         cfg.tags.push(tag.clone());
-        confy::store_path(&self.tags_file, cfg)?;
+        persistence::store(&self.tags_file, &self.snapshot_dir, &cfg)?;
 
         //Add to hashmap
-        self.tags.insert(tag.id, tag);
+        let id = tag.id;
+        self.tags.insert(id, tag);
+        self.compile();
 
-        Ok(())
+        Ok(id)
     }
 
+    /// Removes a tag, its children are moved to its parent
     pub fn remove_tag(&mut self, id: TagId) -> Result<(), TaggingError> {
         info!("Remove tag {} from tag repository", id);
         //Change in  config
-        let mut cfg: TagsConfig = confy::load_path(&self.tags_file)?;
+        let mut cfg: TagsConfig =
+            persistence::load_or_default(&self.tags_file, &self.snapshot_dir)?;
+        let parent = self.tags.get(&id).and_then(|t| t.parent);
+        for tag in cfg.tags.iter_mut().filter(|t| t.parent == Some(id)) {
+            tag.parent = parent;
+        }
         cfg.tags.retain(|t| t.id != id);
-        confy::store_path(&self.tags_file, cfg)?;
+        persistence::store(&self.tags_file, &self.snapshot_dir, &cfg)?;
         //Reload tags
         self.load_config()
     }
@@ -206,65 +853,341 @@ impl Tagger {
         self.tags.values().cloned().collect()
     }
 
+    /// Renames a tag, the documents keep it as its ID does not change
+    pub fn rename_tag(&mut self, id: TagId, name: &str) -> Result<(), TaggingError> {
+        let mut tag = self
+            .tags
+            .get(&id)
+            .cloned()
+            .ok_or(TaggingError::UnknownTag(id))?;
+        tag.name = name.into();
+        self.add_or_replace_tag(tag)?;
+        Ok(())
+    }
+
+    /// Checks if tag `from` can be merged into tag `into`
+    pub fn check_merge(&self, from: TagId, into: TagId) -> Result<(), TaggingError> {
+        if !self.tags.contains_key(&from) {
+            return Err(TaggingError::UnknownTag(from));
+        }
+        if !self.tags.contains_key(&into) {
+            return Err(TaggingError::UnknownTag(into));
+        }
+        // The children of `from` are moved to `into`
+        if self.get_descendants(from).contains(&into) {
+            return Err(TaggingError::Cycle(into));
+        }
+        Ok(())
+    }
+
+    /// Removes tag `from` and moves its children to tag `into`.
+    /// The documents have to be moved to `into` afterwards.
+    pub fn merge_tag(&mut self, from: TagId, into: TagId) -> Result<(), TaggingError> {
+        self.check_merge(from, into)?;
+        info!("Merging tag {} into tag {}", from, into);
+        let mut cfg: TagsConfig =
+            persistence::load_or_default(&self.tags_file, &self.snapshot_dir)?;
+        for tag in cfg.tags.iter_mut().filter(|t| t.parent == Some(from)) {
+            tag.parent = Some(into);
+        }
+        cfg.tags.retain(|t| t.id != from);
+        persistence::store(&self.tags_file, &self.snapshot_dir, &cfg)?;
+        self.load_config()
+    }
+
+    /// Returns all tags as tree, sorted by name
+    pub fn get_tag_tree(&self) -> Vec<TagNode> {
+        self.get_children(None)
+    }
+
+    fn get_children(&self, parent: Option<TagId>) -> Vec<TagNode> {
+        let mut children: Vec<TagNode> = self
+            .tags
+            .values()
+            .filter(|t| t.parent == parent)
+            .map(|t| TagNode {
+                tag: t.clone(),
+                children: self.get_children(Some(t.id)),
+            })
+            .collect();
+        children.sort_by(|a, b| a.tag.name.cmp(&b.tag.name));
+        children
+    }
+
+    /// Returns the tag and all of its descendants
+    pub fn get_descendants(&self, id: TagId) -> Vec<TagId> {
+        let mut descendants = vec![id];
+        let mut i = 0;
+        while i < descendants.len() {
+            let parent = descendants[i];
+            descendants.extend(
+                self.tags
+                    .values()
+                    .filter(|t| t.parent == Some(parent))
+                    .map(|t| t.id),
+            );
+            i += 1;
+        }
+        descendants
+    }
+
+    /// Returns the parent, the grandparent and all further ancestors of a tag
+    pub fn get_ancestors(&self, id: TagId) -> Vec<TagId> {
+        let mut ancestors = vec![];
+        let mut current = self.tags.get(&id).and_then(|t| t.parent);
+        while let Some(parent) = current {
+            if parent == id || ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent);
+            current = self.tags.get(&parent).and_then(|t| t.parent);
+        }
+        ancestors
+    }
+
+    /// Assigns the ancestors of all tags of a document, unless a user removed them.
+    /// Ancestors which are not implied by any remaining tag are removed.
+    pub fn add_ancestors(&self, doc: &mut DocumentData) {
+        let implied = std::mem::take(&mut doc.provenance.implied_tags);
+        doc.tags.retain(|t| !implied.contains(t));
+        for tag in doc.tags.clone() {
+            for ancestor in self.get_ancestors(tag) {
+                if !doc.tags.contains(&ancestor)
+                    && !doc.provenance.rejected_tags.contains(&ancestor)
+                {
+                    doc.tags.push(ancestor);
+                    doc.provenance.implied_tags.push(ancestor);
+                }
+            }
+        }
+    }
+
+    /// Checks that the parent of a tag exists and the tag is not its own ancestor
+    fn check_parent(&self, tag: &TagConfig) -> Result<(), TaggingError> {
+        let mut visited = HashSet::new();
+        let mut current = tag.parent;
+        while let Some(parent) = current {
+            if parent == tag.id || !visited.insert(parent) {
+                return Err(TaggingError::Cycle(tag.id));
+            }
+            current = self
+                .tags
+                .get(&parent)
+                .ok_or(TaggingError::UnknownParent(parent))?
+                .parent;
+        }
+        Ok(())
+    }
+
     fn load_config(&mut self) -> Result<(), TaggingError> {
         debug!("Parsing tags from {:?}", self.tags_file);
-        let cfg: TagsConfig = confy::load_path(&self.tags_file)?;
+        let cfg: TagsConfig = persistence::load_or_default(&self.tags_file, &self.snapshot_dir)?;
         //Clear previous tags
         self.tags.clear();
         for tag in cfg.tags {
             self.tags.insert(tag.id, tag);
         }
         info!("Loaded {} tags", self.tags.len());
+        self.compile();
         Ok(())
     }
 
+    /// Rebuilds the matchers, has to be called whenever the tags or the classifier change
+    fn compile(&mut self) {
+        self.compiled = CompiledTags::new(&self.tags, &self.classifier);
+    }
+
     pub fn tag_document(&self, doc: &mut DocumentData) -> Result<(), TaggingError> {
-        //TODO improve
-        let mut ids: Vec<TagId> = vec![];
-        for (_, tag_cfg) in self.tags.iter() {
-            let tag = match from_tag_config(tag_cfg) {
-                Ok(t) => t,
-                Err(e) => {
-                    error!("Invalid tag in config: {:#?} ({})", tag_cfg, e);
-                    continue;
-                }
-            };
-            if tag.matcher.match_document(doc)? {
-                doc.tags.push(tag.id);
-                ids.push(tag.id);
-            }
+        // Metadata is needed by the field matchers, the language is needed to interpret dates
+        self.infer_language(doc)?;
+        self.extract_meta(doc)?;
+        let matches = self.compiled.match_document(doc)?;
+        let ids: Vec<TagId> = matches.iter().map(|(id, _)| *id).collect();
+        for (id, ranges) in matches {
+            doc.assign_tag(id);
+            let evidence = self.evidence(id, doc, ranges)?;
+            doc.set_evidence(id, Some(evidence));
+        }
+        self.add_ancestors(doc);
+        if doc.correspondent.is_none() {
+            doc.correspondent = self.correspondents.match_document(doc)?;
         }
+        if doc.document_type.is_none() {
+            doc.document_type = self.document_types.match_document(doc)?;
+        }
+        // Rules may be restricted to the tags and correspondent assigned above
+        self.extract_custom_fields(doc);
         info!("Tagged document {} with tags {:?}", doc.id, ids);
-        self.extract_meta(doc)?;
-        self.infer_language(doc)?;
+        Ok(())
+    }
+
+    pub fn correspondents(&self) -> &Correspondents {
+        &self.correspondents
+    }
+
+    pub fn correspondents_mut(&mut self) -> &mut Correspondents {
+        &mut self.correspondents
+    }
+
+    pub fn document_types(&self) -> &DocumentTypes {
+        &self.document_types
+    }
+
+    pub fn document_types_mut(&mut self) -> &mut DocumentTypes {
+        &mut self.document_types
+    }
+
+    pub fn custom_fields(&self) -> &CustomFields {
+        &self.custom_fields
+    }
+
+    pub fn custom_fields_mut(&mut self) -> &mut CustomFields {
+        &mut self.custom_fields
+    }
+
+    pub fn extraction_rules(&self) -> &ExtractionRules {
+        &self.extraction_rules
+    }
+
+    pub fn extraction_rules_mut(&mut self) -> &mut ExtractionRules {
+        &mut self.extraction_rules
+    }
+
+    /// Adds or replaces an extraction rule, its capture groups have to name custom fields
+    pub fn add_or_replace_extraction_rule(
+        &mut self,
+        rule: ExtractionRuleConfig,
+    ) -> Result<ExtractionRuleId, TaggingError> {
+        self.extraction_rules
+            .add_or_replace(rule, &self.custom_fields)
+    }
+
+    /// Shows what each extraction rule extracts from the document
+    pub fn preview_extraction(&self, doc: &DocumentData) -> Vec<ExtractionPreview> {
+        self.extraction_rules.preview(doc, &self.custom_fields)
+    }
+
+    /// Fills the custom fields of the document, which have no value yet
+    pub fn extract_custom_fields(&self, doc: &mut DocumentData) {
+        self.extraction_rules.apply(doc, &self.custom_fields);
+    }
+
+    /// Returns the IDs of all tags matching the document
+    pub fn match_tags(&self, doc: &DocumentData) -> Result<Vec<TagId>, TaggingError> {
+        Ok(self
+            .compiled
+            .match_document(doc)?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    /// Checks if a single tag matches the document, without changing the document.
+    /// Returns the evidence why it matches, `None` if it does not match.
+    pub fn explain(
+        &self,
+        id: TagId,
+        doc: &DocumentData,
+    ) -> Result<Option<TagEvidence>, TaggingError> {
+        let matches = match self.compiled.get(id) {
+            Some(tag) => tag.matcher.find_matches(doc)?,
+            None => {
+                // Tags with an invalid configuration are not compiled
+                let tag_cfg = self.tags.get(&id).ok_or(TaggingError::UnknownTag(id))?;
+                from_tag_config(tag_cfg, &self.classifier)?
+                    .matcher
+                    .find_matches(doc)?
+            }
+        };
+        matches.map(|m| self.evidence(id, doc, m)).transpose()
+    }
+
+    /// Builds the evidence of a matching tag from the byte ranges which caused the match
+    fn evidence(
+        &self,
+        id: TagId,
+        doc: &DocumentData,
+        mut matches: Vec<(usize, usize)>,
+    ) -> Result<TagEvidence, TaggingError> {
+        let tag_cfg = self.tags.get(&id).ok_or(TaggingError::UnknownTag(id))?;
+        matches.truncate(MAX_EVIDENCE_MATCHES);
+        Ok(TagEvidence {
+            tag: id,
+            rule: tag_cfg.matcher.to_string(),
+            snippets: snippets(doc, &matches),
+            matches,
+        })
+    }
+
+    /// Builds an unsaved matcher. Automatic matchers use the trained model of the given tag.
+    pub fn build_matcher(
+        &self,
+        cfg: &MatcherConfig,
+        tag: Option<TagId>,
+    ) -> Result<Box<dyn Matcher + Send + Sync>, TaggingError> {
+        from_matcher_config(cfg, tag.unwrap_or_default(), &self.classifier)
+    }
+
+    /// Returns the IDs of all tags, which are matched by the classifier
+    pub fn get_auto_tags(&self) -> Vec<TagId> {
+        self.tags
+            .values()
+            .filter(|t| t.matcher.uses_classifier())
+            .map(|t| t.id)
+            .collect()
+    }
+
+    /// Replaces the classifier with a newly trained one and stores it
+    pub fn set_classifier(&mut self, classifier: Classifier) -> Result<(), TaggingError> {
+        classifier.store(&self.classifier_file)?;
+        self.classifier = Arc::new(classifier);
+        self.compile();
         Ok(())
     }
 
     fn extract_meta(&self, doc: &mut DocumentData) -> Result<(), TaggingError> {
-        let parsed =
-            commonregex::common_regex(doc.body.as_ref().ok_or(TaggingError::EmptyBody(doc.id))?);
+        let body = doc.body.as_deref().ok_or(TaggingError::EmptyBody(doc.id))?;
+        let parsed = commonregex::common_regex(body);
         //Dates
-        let dates = parsed.dates;
-        debug!("Extracted dates from document {}: {:?}", doc.id, dates);
-        if !dates.is_empty() {
-            doc.extracted.doc_date =
-                diligent_date_parser::parse_date(dates[0]).map(|d| d.with_timezone(&chrono::Utc));
-            match &doc.extracted.doc_date {
-                Some(d) => info!("Extracted date {} for document {}", &d, doc.id),
-                None => info!("Could not extract date for document {}", doc.id),
-            }
+        let candidates = self
+            .dates
+            .candidates(body, doc.language.as_deref(), doc.imported_date);
+        debug!(
+            "Found date candidates in document {}: {:?}",
+            doc.id, candidates
+        );
+        // The creation date of the file is only used if the text contains no date
+        doc.extracted.doc_date = candidates
+            .first()
+            .map(|c| c.date)
+            .or(doc.file_metadata.creation_date);
+        doc.extracted.date_candidates = candidates;
+        match &doc.extracted.doc_date {
+            Some(d) => info!("Extracted date {} for document {}", &d, doc.id),
+            None => info!("Could not extract date for document {}", doc.id),
         }
 
         if self.extract_extended_metadata {
-            //IBAN
-            doc.extracted.iban = parsed.ibans.iter().map(|s| s.to_string()).collect();
+            //Bank account and tax identifiers, only valid IBANs are kept
+            doc.extracted.iban = self.identifiers.ibans(body);
+            doc.extracted.bic = self.identifiers.bics(body);
+            doc.extracted.vat_id = self.identifiers.vat_ids(body);
+            doc.extracted.tax_number = self.identifiers.tax_numbers(body);
 
-            //Telephone numbers
+            //Telephone numbers, which are not part of an identifier
+            let identifiers: Vec<String> = doc
+                .extracted
+                .iban
+                .iter()
+                .chain(&doc.extracted.vat_id)
+                .chain(&doc.extracted.tax_number)
+                .map(|i| digits(i))
+                .collect();
             doc.extracted.phone = parsed
                 .phones
                 .iter()
+                .filter(|t| !identifiers.iter().any(|i| i.contains(&digits(t))))
                 .map(|s| s.to_string())
-                .filter(|t| !doc.extracted.iban.iter().any(|i| i.contains(t)))
                 .collect();
 
             //E-Mail
@@ -277,6 +1200,11 @@ impl Tagger {
                 .map(|s| s.to_string())
                 .filter(|s| !doc.extracted.email.contains(s))
                 .collect();
+
+            //Amounts
+            let (amounts, total) = self.amounts.extract(body);
+            doc.extracted.amounts = amounts;
+            doc.extracted.total = total;
         }
 
         Ok(())
@@ -331,6 +1259,17 @@ pub struct TagConfig {
     pub name: String,
     pub color: Option<String>,
     pub matcher: MatcherConfig,
+    /// Assigning a tag implies all of its ancestors, which are assigned together with it
+    #[serde(default)]
+    pub parent: Option<TagId>,
+}
+
+/// Tag together with its child tags
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagNode {
+    #[serde(flatten)]
+    pub tag: TagConfig,
+    pub children: Vec<TagNode>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -346,11 +1285,184 @@ pub enum MatcherConfig {
     RegexMatcher {
         match_str: String,
     },
+    /// Matches terms with OCR errors up to the given edit distance
+    FuzzyMatcher {
+        match_str: String,
+        max_distance: usize,
+    },
     NoMatcher,
+    /// Matches if all sub-matchers match
+    And {
+        matchers: Vec<MatcherConfig>,
+    },
+    /// Matches if any sub-matcher matches
+    Or {
+        matchers: Vec<MatcherConfig>,
+    },
+    /// Matches if the sub-matcher does not match
+    Not {
+        matcher: Box<MatcherConfig>,
+    },
+    /// Matches a metadata field instead of the body
+    FieldMatcher {
+        field: DocumentField,
+        condition: FieldCondition,
+        value: String,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
+    /// Matches the file size in bytes
+    FileSizeMatcher {
+        min: Option<u64>,
+        max: Option<u64>,
+    },
+    /// Matches the extracted document date
+    DateMatcher {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+    /// Matches if the classifier predicts the tag with at least the given probability
+    Auto {
+        threshold: f64,
+    },
+}
+
+impl MatcherConfig {
+    /// Checks if the matcher or one of its sub-matchers uses the classifier
+    pub fn uses_classifier(&self) -> bool {
+        match self {
+            MatcherConfig::Auto { .. } => true,
+            MatcherConfig::And { matchers } | MatcherConfig::Or { matchers } => {
+                matchers.iter().any(|m| m.uses_classifier())
+            }
+            MatcherConfig::Not { matcher } => matcher.uses_classifier(),
+            _ => false,
+        }
+    }
+}
+
+/// Describes the rule of a matcher, as shown to users as evidence of a match
+impl std::fmt::Display for MatcherConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let case = |case_insensitive: &bool| match case_insensitive {
+            true => " (case insensitive)",
+            false => "",
+        };
+        let join = |f: &mut std::fmt::Formatter, matchers: &[MatcherConfig], op: &str| {
+            let rules: Vec<String> = matchers.iter().map(|m| m.to_string()).collect();
+            write!(f, "({})", rules.join(op))
+        };
+        match self {
+            MatcherConfig::FullMatcher {
+                match_str,
+                case_insensitive,
+            } => write!(f, "contains {:?}{}", match_str, case(case_insensitive)),
+            MatcherConfig::AnyMatcher {
+                match_str,
+                case_insensitive,
+            } => write!(
+                f,
+                "contains any of {:?}{}",
+                match_str,
+                case(case_insensitive)
+            ),
+            MatcherConfig::RegexMatcher { match_str } => write!(f, "matches /{}/", match_str),
+            MatcherConfig::FuzzyMatcher {
+                match_str,
+                max_distance,
+            } => write!(
+                f,
+                "contains {:?} with up to {} errors",
+                match_str, max_distance
+            ),
+            MatcherConfig::NoMatcher => write!(f, "never matches"),
+            MatcherConfig::And { matchers } => join(f, matchers, " AND "),
+            MatcherConfig::Or { matchers } => join(f, matchers, " OR "),
+            MatcherConfig::Not { matcher } => write!(f, "NOT {}", matcher),
+            MatcherConfig::FieldMatcher {
+                field,
+                condition,
+                value,
+                case_insensitive,
+            } => write!(
+                f,
+                "{} {} {:?}{}",
+                field,
+                condition,
+                value,
+                case(case_insensitive)
+            ),
+            MatcherConfig::FileSizeMatcher { min, max } => match (min, max) {
+                (Some(min), Some(max)) => write!(f, "file size between {} and {} bytes", min, max),
+                (Some(min), None) => write!(f, "file size at least {} bytes", min),
+                (None, Some(max)) => write!(f, "file size at most {} bytes", max),
+                (None, None) => write!(f, "any file size"),
+            },
+            MatcherConfig::DateMatcher { from, to } => match (from, to) {
+                (Some(from), Some(to)) => write!(f, "document date between {} and {}", from, to),
+                (Some(from), None) => write!(f, "document date on or after {}", from),
+                (None, Some(to)) => write!(f, "document date on or before {}", to),
+                (None, None) => write!(f, "any document date"),
+            },
+            MatcherConfig::Auto { threshold } => write!(
+                f,
+                "classifier predicts the tag with at least {:.0}% probability",
+                threshold * 100.0
+            ),
+        }
+    }
 }
 
-fn from_matcher_config(
+/// Tests a matcher built by `Tagger::build_matcher` against a document.
+/// Returns `None` if the matcher does not match, otherwise the text snippets around the matches.
+pub fn preview_matcher(
+    matcher: &dyn Matcher,
+    doc: &DocumentData,
+) -> Result<Option<Vec<String>>, TaggingError> {
+    Ok(matcher.find_matches(doc)?.map(|m| snippets(doc, &m)))
+}
+
+/// Returns the snippets around the first matches in the body of the document
+fn snippets(doc: &DocumentData, matches: &[(usize, usize)]) -> Vec<String> {
+    let body = doc.body.as_deref().unwrap_or_default();
+    matches
+        .iter()
+        .take(MAX_SNIPPETS)
+        .map(|(start, end)| snippet(body, *start, *end))
+        .collect()
+}
+
+/// Cuts the match and its surrounding context out of the text
+fn snippet(text: &str, start: usize, end: usize) -> String {
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let to = text[end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map(|(i, _)| end + i)
+        .unwrap_or_else(|| text.len());
+    let mut snippet = text[from..to]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if from > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if to < text.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Compiles the matcher of the tag `tag`
+pub fn from_matcher_config(
     cfg: &MatcherConfig,
+    tag: TagId,
+    classifier: &Arc<Classifier>,
 ) -> Result<Box<dyn Matcher + Send + Sync>, TaggingError> {
     match cfg {
         MatcherConfig::FullMatcher {
@@ -364,36 +1476,83 @@ fn from_matcher_config(
         MatcherConfig::RegexMatcher { match_str } => {
             Ok(Box::new(RegexMatcher::parse_string(match_str)?))
         }
+        MatcherConfig::FuzzyMatcher {
+            match_str,
+            max_distance,
+        } => Ok(Box::new(FuzzyMatcher::new(match_str, *max_distance))),
         MatcherConfig::NoMatcher => Ok(Box::new(NoMatcher::new())),
+        MatcherConfig::And { matchers } => Ok(Box::new(AndMatcher::new(
+            matchers
+                .iter()
+                .map(|m| from_matcher_config(m, tag, classifier))
+                .collect::<Result<_, _>>()?,
+        ))),
+        MatcherConfig::Or { matchers } => Ok(Box::new(OrMatcher::new(
+            matchers
+                .iter()
+                .map(|m| from_matcher_config(m, tag, classifier))
+                .collect::<Result<_, _>>()?,
+        ))),
+        MatcherConfig::Not { matcher } => Ok(Box::new(NotMatcher::new(from_matcher_config(
+            matcher, tag, classifier,
+        )?))),
+        MatcherConfig::FieldMatcher {
+            field,
+            condition,
+            value,
+            case_insensitive,
+        } => Ok(Box::new(FieldMatcher::new(
+            *field,
+            *condition,
+            value,
+            *case_insensitive,
+        )?)),
+        MatcherConfig::FileSizeMatcher { min, max } => {
+            Ok(Box::new(FileSizeMatcher::new(*min, *max)))
+        }
+        MatcherConfig::DateMatcher { from, to } => Ok(Box::new(DateMatcher::new(*from, *to))),
+        MatcherConfig::Auto { threshold } => Ok(Box::new(AutoMatcher::new(
+            tag,
+            *threshold,
+            classifier.clone(),
+        ))),
     }
 }
 
-fn from_tag_config(cfg: &TagConfig) -> Result<Tag, TaggingError> {
+/// Builds a matcher for entities other than tags, e.g. correspondents.
+/// The classifier only learns tags, so automatic matchers are rejected.
+pub(crate) fn from_fixed_matcher_config(
+    cfg: &MatcherConfig,
+) -> Result<Box<dyn Matcher + Send + Sync>, TaggingError> {
+    if cfg.uses_classifier() {
+        return Err(TaggingError::UnsupportedMatcher(cfg.to_string()));
+    }
+    from_matcher_config(cfg, 0, &Arc::default())
+}
+
+fn from_tag_config(cfg: &TagConfig, classifier: &Arc<Classifier>) -> Result<Tag, TaggingError> {
     Ok(Tag {
         id: cfg.id,
         name: cfg.name.clone(),
-        matcher: from_matcher_config(&cfg.matcher)?,
+        matcher: from_matcher_config(&cfg.matcher, cfg.id, classifier)?,
     })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{AnyMatcher, FullMatcher, Matcher, NoMatcher, RegexMatcher};
-    use crate::index::document_repository::{DocumentData, ExtractedData};
+    use super::{
+        from_matcher_config, from_tag_config, preview_matcher, AnyMatcher, DocumentField,
+        FieldCondition, FullMatcher, FuzzyMatcher, Matcher, MatcherConfig, NoMatcher, RegexMatcher,
+        TagConfig, TagId, Tagger, TaggingError,
+    };
+    use crate::index::document_repository::DocumentData;
+
+    fn build(cfg: &MatcherConfig) -> Box<dyn Matcher + Send + Sync> {
+        from_matcher_config(cfg, 0, &Default::default()).unwrap()
+    }
 
     fn get_document_with_text(text: &str) -> DocumentData {
-        DocumentData {
-            id: 0,
-            original_filename: String::new(),
-            title: String::new(),
-            body: Some(text.into()),
-            tags: vec![],
-            imported_date: chrono::Utc::now(),
-            hash: String::new(),
-            file_size: 0,
-            language: None,
-            extracted: ExtractedData::default(),
-        }
+        DocumentData::test(0).with_body(text)
     }
 
     #[test]
@@ -506,4 +1665,426 @@ mod tests {
             .match_document(&get_document_with_text("test"))
             .unwrap());
     }
+
+    #[test]
+    fn fuzzymatcher_test() {
+        let matcher = FuzzyMatcher::new("Versicherung", 1);
+        assert!(matcher
+            .match_document(&get_document_with_text("Versicherung"))
+            .unwrap());
+        assert!(matcher
+            .match_document(&get_document_with_text("Ihre VERSICHERUNG"))
+            .unwrap());
+        // OCR confusions and whitespace are free
+        assert!(matcher
+            .match_document(&get_document_with_text("Vers1cherung"))
+            .unwrap());
+        assert!(matcher
+            .match_document(&get_document_with_text("Versi cherung"))
+            .unwrap());
+        assert!(matcher
+            .match_document(&get_document_with_text("VersIcherung"))
+            .unwrap());
+        // One edit
+        assert!(matcher
+            .match_document(&get_document_with_text("Versichrung"))
+            .unwrap());
+        assert!(matcher
+            .match_document(&get_document_with_text("Versicherunq"))
+            .unwrap());
+        assert!(!matcher
+            .match_document(&get_document_with_text("Versichenrunq"))
+            .unwrap());
+        assert!(!matcher
+            .match_document(&get_document_with_text("Verein"))
+            .unwrap());
+
+        let exact = FuzzyMatcher::new("Formular 10", 0);
+        assert!(exact
+            .match_document(&get_document_with_text("Forrnu1ar lO"))
+            .unwrap());
+        assert!(exact
+            .match_document(&get_document_with_text("Forrnular IO"))
+            .unwrap());
+        assert!(!exact
+            .match_document(&get_document_with_text("Formular 11"))
+            .unwrap());
+
+        // The byte range of the original text is returned
+        let text = "Ihre Vers1 cherung für Müller";
+        let matches = matcher
+            .find_matches(&get_document_with_text(text))
+            .unwrap()
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(&text[matches[0].0..matches[0].1], "Vers1 cherung");
+    }
+
+    #[test]
+    fn preview_test() {
+        let cfg = MatcherConfig::AnyMatcher {
+            match_str: "invoice,receipt".into(),
+            case_insensitive: true,
+        };
+        let doc = get_document_with_text(&format!(
+            "{} Invoice number 42 {}",
+            "a".repeat(50),
+            "b".repeat(50)
+        ));
+        let matcher = build(&cfg);
+        let snippets = preview_matcher(&*matcher, &doc).unwrap().unwrap();
+        assert_eq!(snippets.len(), 1);
+        assert!(snippets[0].starts_with("..."));
+        assert!(snippets[0].ends_with("..."));
+        assert!(snippets[0].contains("Invoice number 42"));
+
+        let doc = get_document_with_text("Rechnung für Müller");
+        assert!(preview_matcher(&*matcher, &doc).unwrap().is_none());
+
+        let cfg = MatcherConfig::RegexMatcher {
+            match_str: "M.ller".into(),
+        };
+        assert_eq!(
+            preview_matcher(&*build(&cfg), &doc).unwrap().unwrap(),
+            vec!["Rechnung für Müller".to_string()]
+        );
+    }
+
+    #[test]
+    fn boolean_matcher_test() {
+        let full = |s: &str| MatcherConfig::FullMatcher {
+            match_str: s.into(),
+            case_insensitive: true,
+        };
+        let cfg = MatcherConfig::And {
+            matchers: vec![
+                full("rechnung"),
+                MatcherConfig::Or {
+                    matchers: vec![full("telekom"), full("vodafone")],
+                },
+                MatcherConfig::Not {
+                    matcher: Box::new(full("mahnung")),
+                },
+            ],
+        };
+        // Stored in the tags file
+        let cfg: MatcherConfig =
+            serde_yaml::from_str(&serde_yaml::to_string(&cfg).unwrap()).unwrap();
+        let matcher = build(&cfg);
+
+        let matches = |text: &str| {
+            matcher
+                .match_document(&get_document_with_text(text))
+                .unwrap()
+        };
+        assert!(matches("Ihre Rechnung der Telekom"));
+        assert!(matches("Vodafone Rechnung"));
+        assert!(!matches("Telekom Mahnung zur Rechnung"));
+        assert!(!matches("Rechnung der Stadtwerke"));
+        assert!(!matches("Telekom Vertrag"));
+
+        let empty = build(&MatcherConfig::And { matchers: vec![] });
+        assert!(!empty
+            .match_document(&get_document_with_text("Test"))
+            .unwrap());
+    }
+
+    #[test]
+    fn field_matcher_test() {
+        let mut doc = get_document_with_text("");
+        doc.original_filename = "scan_0042.pdf".into();
+        doc.extracted.iban = vec!["DE12 5001 0517 0648 4898 90".into()];
+        doc.file_size = 2048;
+        doc.extracted.doc_date =
+            Some(chrono::TimeZone::ymd(&chrono::Utc, 2021, 3, 15).and_hms(0, 0, 0));
+
+        let matches = |cfg: MatcherConfig| build(&cfg).match_document(&doc).unwrap();
+        let field = |field, condition, value: &str| MatcherConfig::FieldMatcher {
+            field,
+            condition,
+            value: value.into(),
+            case_insensitive: true,
+        };
+        assert!(matches(field(
+            DocumentField::OriginalFilename,
+            FieldCondition::StartsWith,
+            "SCAN_"
+        )));
+        assert!(!matches(field(
+            DocumentField::OriginalFilename,
+            FieldCondition::EndsWith,
+            "scan_"
+        )));
+        assert!(matches(field(
+            DocumentField::Iban,
+            FieldCondition::Equals,
+            "DE12500105170648489890"
+        )));
+        assert!(!matches(field(
+            DocumentField::Iban,
+            FieldCondition::Equals,
+            "DE12 5001"
+        )));
+        assert!(!matches(field(
+            DocumentField::Language,
+            FieldCondition::Contains,
+            ""
+        )));
+
+        assert!(matches(MatcherConfig::FileSizeMatcher {
+            min: Some(1024),
+            max: None
+        }));
+        assert!(!matches(MatcherConfig::FileSizeMatcher {
+            min: None,
+            max: Some(1024)
+        }));
+
+        let cfg: MatcherConfig =
+            serde_yaml::from_str("DateMatcher:\n  from: 2021-01-01\n  to: 2021-03-15\n").unwrap();
+        assert!(matches(cfg));
+        assert!(!matches(MatcherConfig::DateMatcher {
+            from: chrono::NaiveDate::from_ymd_opt(2021, 3, 16),
+            to: None
+        }));
+    }
+
+    #[test]
+    fn evidence_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tagger = Tagger::new(dir.path(), false).unwrap();
+        let matcher = MatcherConfig::Or {
+            matchers: vec![
+                MatcherConfig::FullMatcher {
+                    match_str: "invoice".into(),
+                    case_insensitive: true,
+                },
+                MatcherConfig::FileSizeMatcher {
+                    min: Some(1024),
+                    max: None,
+                },
+            ],
+        };
+        let id = tagger
+            .add_tag(TagConfig {
+                id: 0,
+                name: "Invoice".into(),
+                color: None,
+                matcher,
+                parent: None,
+            })
+            .unwrap();
+
+        let mut doc = get_document_with_text("Your Invoice for March");
+        tagger.tag_document(&mut doc).unwrap();
+        assert_eq!(doc.tags, vec![id]);
+        assert_eq!(doc.evidence.len(), 1);
+        let evidence = &doc.evidence[0];
+        assert_eq!(evidence.tag, id);
+        assert_eq!(
+            evidence.rule,
+            "(contains \"invoice\" (case insensitive) OR file size at least 1024 bytes)"
+        );
+        assert_eq!(evidence.matches, vec![(5, 12)]);
+        assert_eq!(
+            evidence.snippets,
+            vec!["Your Invoice for March".to_string()]
+        );
+
+        // Removing the tag by hand drops its evidence
+        doc.remove_manual_tag(id);
+        assert!(doc.evidence.is_empty());
+    }
+
+    #[test]
+    fn hierarchy_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tagger = Tagger::new(dir.path(), false).unwrap();
+        let tag = |name: &str, parent| TagConfig {
+            id: 0,
+            name: name.into(),
+            color: None,
+            matcher: MatcherConfig::NoMatcher,
+            parent,
+        };
+        let finance = tagger.add_tag(tag("Finance", None)).unwrap();
+        let insurance = tagger.add_tag(tag("Insurance", Some(finance))).unwrap();
+        let car = tagger.add_tag(tag("Car", Some(insurance))).unwrap();
+        let bank = tagger.add_tag(tag("Bank", Some(finance))).unwrap();
+
+        let mut descendants = tagger.get_descendants(finance);
+        descendants.sort_unstable();
+        assert_eq!(descendants, vec![finance, insurance, car, bank]);
+        assert_eq!(tagger.get_ancestors(car), vec![insurance, finance]);
+
+        // Assigning a tag assigns its ancestors, unless a user removed them
+        let mut doc = DocumentData::test(0).with_tags(vec![car, bank]);
+        tagger.add_ancestors(&mut doc);
+        assert_eq!(doc.tags, vec![car, bank, insurance, finance]);
+        let mut doc = DocumentData::test(0).with_tags(vec![car]);
+        doc.provenance.rejected_tags = vec![insurance];
+        tagger.add_ancestors(&mut doc);
+        assert_eq!(doc.tags, vec![car, finance]);
+        let kfz = tagger
+            .add_tag(TagConfig {
+                matcher: MatcherConfig::AnyMatcher {
+                    match_str: "kfz".into(),
+                    case_insensitive: true,
+                },
+                ..tag("Kfz", Some(car))
+            })
+            .unwrap();
+        let mut doc = DocumentData::test(0).with_body("Ihre Kfz-Versicherung");
+        tagger.tag_document(&mut doc).unwrap();
+        assert_eq!(doc.tags, vec![kfz, car, insurance, finance]);
+        assert_eq!(doc.provenance.implied_tags, vec![car, insurance, finance]);
+
+        // Removing a tag removes the ancestors it implied, unless they were assigned directly
+        doc.assign_tag(insurance);
+        tagger.remove_tag(kfz).unwrap();
+        assert!(doc.replace_tag(kfz, None));
+        tagger.add_ancestors(&mut doc);
+        assert_eq!(doc.tags, vec![insurance, finance]);
+        assert_eq!(doc.provenance.implied_tags, vec![finance]);
+
+        let tree = tagger.get_tag_tree();
+        assert_eq!(tree.len(), 1);
+        let names: Vec<&str> = tree[0]
+            .children
+            .iter()
+            .map(|n| n.tag.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Bank", "Insurance"]);
+        assert_eq!(tree[0].children[1].children[0].tag.id, car);
+
+        // Cycles and unknown parents are rejected
+        let mut cyclic = tagger.get_tag(finance).unwrap();
+        cyclic.parent = Some(car);
+        assert!(matches!(
+            tagger.add_or_replace_tag(cyclic),
+            Err(TaggingError::Cycle(_))
+        ));
+        assert!(matches!(
+            tagger.add_tag(tag("Orphan", Some(42))),
+            Err(TaggingError::UnknownParent(42))
+        ));
+
+        // Children move up when their parent is removed
+        tagger.remove_tag(insurance).unwrap();
+        assert_eq!(tagger.get_tag(car).unwrap().parent, Some(finance));
+
+        // Merging moves the children and cannot create cycles
+        assert!(matches!(
+            tagger.merge_tag(finance, car),
+            Err(TaggingError::Cycle(_))
+        ));
+        let other = tagger.add_tag(tag("Other", None)).unwrap();
+        tagger.merge_tag(finance, other).unwrap();
+        assert!(tagger.get_tag(finance).is_none());
+        assert_eq!(tagger.get_tag(car).unwrap().parent, Some(other));
+        assert_eq!(tagger.get_tag(bank).unwrap().parent, Some(other));
+
+        tagger.rename_tag(other, "Finance").unwrap();
+        assert_eq!(tagger.get_tag(other).unwrap().name, "Finance");
+    }
+
+    /// Synthetic corpus of documents and regex based tags
+    fn corpus(tags: usize, docs: usize) -> (tempfile::TempDir, Tagger, Vec<DocumentData>) {
+        const WORDS: [&str; 16] = [
+            "Rechnung",
+            "invoice",
+            "Vertrag",
+            "contract",
+            "Telekom",
+            "Stadtwerke",
+            "insurance",
+            "Versicherung",
+            "Kontoauszug",
+            "statement",
+            "Miete",
+            "rent",
+            "Steuer",
+            "tax",
+            "Arzt",
+            "doctor",
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let mut tagger = Tagger::new(dir.path(), false).unwrap();
+        for i in 0..tags {
+            let word = WORDS[i % WORDS.len()];
+            let matcher = match i % 3 {
+                0 => MatcherConfig::FullMatcher {
+                    match_str: format!("{} {}", word, i),
+                    case_insensitive: true,
+                },
+                1 => MatcherConfig::AnyMatcher {
+                    match_str: format!("{}{},{}", word, i, WORDS[(i + 1) % WORDS.len()]),
+                    case_insensitive: false,
+                },
+                _ => MatcherConfig::RegexMatcher {
+                    match_str: format!(r"{}\s+\d{{{}}}", word, i % 4 + 1),
+                },
+            };
+            tagger
+                .add_tag(TagConfig {
+                    id: 0,
+                    name: format!("Tag {}", i),
+                    color: None,
+                    matcher,
+                    parent: None,
+                })
+                .unwrap();
+        }
+        // Linear congruential generator for reproducible texts
+        let mut seed: usize = 42;
+        let documents = (0..docs)
+            .map(|_| {
+                let text: Vec<String> = (0..500)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        match (seed >> 33) % 4 {
+                            0 => ((seed >> 20) % 100).to_string(),
+                            _ => WORDS[(seed >> 40) % WORDS.len()].to_string(),
+                        }
+                    })
+                    .collect();
+                get_document_with_text(&text.join(" "))
+            })
+            .collect();
+        (dir, tagger, documents)
+    }
+
+    /// Matches all tags like before the matchers were compiled once
+    fn match_tags_uncompiled(tagger: &Tagger, doc: &DocumentData) -> Vec<TagId> {
+        let classifier = Default::default();
+        let mut ids: Vec<TagId> = tagger
+            .get_tags()
+            .iter()
+            .filter(|cfg| {
+                from_tag_config(cfg, &classifier)
+                    .unwrap()
+                    .matcher
+                    .match_document(doc)
+                    .unwrap()
+            })
+            .map(|cfg| cfg.id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn compiled_tags_test() {
+        let (_dir, tagger, docs) = corpus(30, 20);
+        let mut matched = 0;
+        for doc in &docs {
+            let ids = tagger.match_tags(doc).unwrap();
+            assert_eq!(ids, match_tags_uncompiled(&tagger, doc));
+            for id in &ids {
+                assert!(tagger.explain(*id, doc).unwrap().is_some());
+            }
+            matched += ids.len();
+        }
+        assert!(matched > 0);
+    }
 }
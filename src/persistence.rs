@@ -0,0 +1,204 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Number of previous versions kept for each file
+const SNAPSHOT_COUNT: usize = 5;
+
+//Error Handling
+use thiserror::Error;
+#[derive(Error, Debug)]
+pub enum PersistenceError {
+    #[error("could not read/write file `{0}`")]
+    IO(#[from] std::io::Error),
+    #[error("could not (de)serialize file `{0}`")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("file `{0}` is corrupted and no valid snapshot exists")]
+    NoValidSnapshot(PathBuf),
+}
+
+/// Loads a YAML file, creating it with the default value if it does not exist.
+/// If the file cannot be parsed, it is restored from the newest valid snapshot.
+pub fn load_or_default<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    snapshot_dir: &Path,
+) -> Result<T, PersistenceError> {
+    if !path.exists() {
+        let value = T::default();
+        store(path, snapshot_dir, &value)?;
+        return Ok(value);
+    }
+    load(path, snapshot_dir)
+}
+
+/// Loads a YAML file.
+/// If the file is not valid YAML, it is restored from the newest valid snapshot.
+/// Valid YAML which does not match the expected structure is an error, as the snapshots would be outdated.
+pub fn load<T: DeserializeOwned>(path: &Path, snapshot_dir: &Path) -> Result<T, PersistenceError> {
+    let content = std::fs::read_to_string(path)?;
+    match serde_yaml::from_str::<serde_yaml::Value>(&content) {
+        Ok(value) => Ok(serde_yaml::from_value(value)?),
+        Err(e) => {
+            error!(
+                "Could not parse {:?}: {}. Trying to recover from snapshots",
+                path, e
+            );
+            recover(path, snapshot_dir)
+        }
+    }
+}
+
+/// Stores a value as YAML file.
+/// The value is written to a temporary file, which is synced to disk and renamed to the given path afterwards.
+/// The previous version of the file is kept as snapshot in the snapshot directory.
+pub fn store<T: Serialize>(
+    path: &Path,
+    snapshot_dir: &Path,
+    value: &T,
+) -> Result<(), PersistenceError> {
+    let content = serde_yaml::to_string(value)?;
+    if path.exists() {
+        snapshot(path, snapshot_dir)?;
+    }
+    write_atomic(path, content.as_bytes())
+}
+
+/// Writes the content to a temporary file next to the given path, syncs it and renames it to the path
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), PersistenceError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    // Persist the rename itself, not supported on all platforms
+    if let Some(dir) = path.parent() {
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Returns the file name, which is used as prefix of the snapshots
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Copies the current version of the file into the snapshot directory and removes old snapshots
+fn snapshot(path: &Path, snapshot_dir: &Path) -> Result<(), PersistenceError> {
+    std::fs::create_dir_all(snapshot_dir)?;
+    let name = file_name(path);
+    let snapshot_file = snapshot_dir.join(format!(
+        "{}.{}",
+        name,
+        chrono::Utc::now().timestamp_millis()
+    ));
+    std::fs::copy(path, &snapshot_file)?;
+
+    for old in snapshots(&name, snapshot_dir)?
+        .into_iter()
+        .skip(SNAPSHOT_COUNT)
+    {
+        debug!("Removing old snapshot {:?}", old);
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Returns all snapshots of the file with the given name, newest first
+fn snapshots(name: &str, snapshot_dir: &Path) -> Result<Vec<PathBuf>, PersistenceError> {
+    if !snapshot_dir.exists() {
+        return Ok(vec![]);
+    }
+    let prefix = format!("{}.", name);
+    let mut snapshots: Vec<(i64, PathBuf)> = vec![];
+    for entry in std::fs::read_dir(snapshot_dir)? {
+        let path = entry?.path();
+        let timestamp = file_name(&path)
+            .strip_prefix(&prefix)
+            .and_then(|t| t.parse::<i64>().ok());
+        if let Some(t) = timestamp {
+            snapshots.push((t, path));
+        }
+    }
+    snapshots.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    Ok(snapshots.into_iter().map(|(_, p)| p).collect())
+}
+
+/// Restores the file from the newest snapshot which can be parsed.
+/// The corrupted file is kept in the snapshot directory.
+fn recover<T: DeserializeOwned>(path: &Path, snapshot_dir: &Path) -> Result<T, PersistenceError> {
+    for snapshot_file in snapshots(&file_name(path), snapshot_dir)? {
+        let content = match std::fs::read_to_string(&snapshot_file) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Could not read snapshot {:?}: {}", snapshot_file, e);
+                continue;
+            }
+        };
+        match serde_yaml::from_str(&content) {
+            Ok(value) => {
+                let corrupted = snapshot_dir.join(format!(
+                    "{}.corrupted-{}",
+                    file_name(path),
+                    chrono::Utc::now().timestamp_millis()
+                ));
+                std::fs::rename(path, &corrupted)?;
+                write_atomic(path, content.as_bytes())?;
+                warn!(
+                    "Recovered {:?} from snapshot {:?}, moved corrupted file to {:?}",
+                    path, snapshot_file, corrupted
+                );
+                return Ok(value);
+            }
+            Err(e) => warn!("Snapshot {:?} is not valid either: {}", snapshot_file, e),
+        }
+    }
+    Err(PersistenceError::NoValidSnapshot(path.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, load_or_default, store, SNAPSHOT_COUNT};
+
+    #[test]
+    fn recover_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("docs.yaml");
+        let snapshot_dir = dir.path().join("snapshots");
+
+        let value: Vec<u64> = load_or_default(&file, &snapshot_dir).unwrap();
+        assert!(value.is_empty());
+        for i in 1..=(SNAPSHOT_COUNT as u64 + 2) {
+            store(&file, &snapshot_dir, &(1..=i).collect::<Vec<u64>>()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(
+            std::fs::read_dir(&snapshot_dir).unwrap().count(),
+            SNAPSHOT_COUNT
+        );
+
+        // Simulate crash during write
+        std::fs::write(&file, "- 1\n- 2\n- [").unwrap();
+        let recovered: Vec<u64> = load(&file, &snapshot_dir).unwrap();
+        assert_eq!(
+            recovered,
+            (1..=SNAPSHOT_COUNT as u64 + 1).collect::<Vec<u64>>()
+        );
+        let reloaded: Vec<u64> = load(&file, &snapshot_dir).unwrap();
+        assert_eq!(recovered, reloaded);
+
+        // Valid YAML with another structure is not replaced by an older snapshot
+        std::fs::write(&file, "docs: []\n").unwrap();
+        assert!(load::<Vec<u64>>(&file, &snapshot_dir).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "docs: []\n");
+    }
+}
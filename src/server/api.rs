@@ -1,4 +1,5 @@
-use crate::index::JobType;
+use crate::index::fsck::FsckReport;
+use crate::index::{JobReport, JobType};
 use crossbeam_channel::Sender;
 use rocket::State;
 
@@ -44,3 +45,29 @@ pub fn job_status(
         },
     }
 }
+
+/// GET the reports of the last finished jobs
+#[get("/job/reports")]
+pub fn job_reports(
+    index: State<Arc<Index>>,
+) -> Result<Json<Vec<JobReport>>, Box<dyn std::error::Error>> {
+    Ok(Json(index.get_job_reports()?))
+}
+
+//////////////////////////////////////////////
+//////////        Consistency   ////////////////
+//////////////////////////////////////////////
+
+/// GET a consistency report of all stores
+#[get("/fsck")]
+pub fn fsck(index: State<Arc<Index>>) -> Result<Json<FsckReport>, Box<dyn std::error::Error>> {
+    Ok(Json(index.fsck()?))
+}
+
+/// POST which starts the repair of all found inconsistencies
+#[post("/fsck/repair")]
+pub fn fsck_repair(send: State<Mutex<Sender<JobType>>>) -> Result<(), Box<dyn std::error::Error>> {
+    let guard = send.lock().unwrap();
+    guard.send(JobType::Repair)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::index::{Index, JobType};
+use crate::metadata::correspondent::{CorrespondentConfig, CorrespondentId};
+use crossbeam_channel::Sender;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// GET all correspondents as JSON, sorted by name
+#[get("/json")]
+pub fn correspondents_json(index: State<Arc<Index>>) -> Json<Vec<CorrespondentConfig>> {
+    Json((*index).get_correspondents())
+}
+
+/// GET a specific correspondent as JSON config
+#[get("/<id>/json")]
+pub fn correspondent_json(
+    index: State<Arc<Index>>,
+    id: CorrespondentId,
+) -> Json<Option<CorrespondentConfig>> {
+    Json((*index).get_correspondent(id))
+}
+
+/// POST a new correspondent, or replace an existing one with the same ID.
+/// A job assigns the correspondents to all documents again. Returns the ID of the correspondent.
+#[post("/", format = "json", data = "<correspondent>")]
+pub fn create_or_update_correspondent(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    correspondent: Json<CorrespondentConfig>,
+) -> Result<Json<CorrespondentId>, Box<dyn std::error::Error>> {
+    let id = (*index).add_or_replace_correspondent(correspondent.into_inner())?;
+    send.lock().unwrap().send(JobType::AssignCorrespondents)?;
+    Ok(Json(id))
+}
+
+/// DELETE a specific correspondent. A job removes it from all documents and the correspondent afterwards.
+#[delete("/<id>")]
+pub fn remove_correspondent(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: CorrespondentId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if (*index).get_correspondent(id).is_none() {
+        return Err(format!("correspondent {} does not exist", id).into());
+    }
+    send.lock()
+        .unwrap()
+        .send(JobType::RemoveCorrespondent { correspondent: id })?;
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::index::{Index, JobType};
+use crate::metadata::custom_field::{CustomFieldConfig, CustomFieldId};
+use crossbeam_channel::Sender;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// GET the definitions of all custom fields as JSON, sorted by ID
+#[get("/json")]
+pub fn custom_fields_json(index: State<Arc<Index>>) -> Json<Vec<CustomFieldConfig>> {
+    Json((*index).get_custom_fields())
+}
+
+/// GET the definition of a specific custom field as JSON
+#[get("/<id>/json")]
+pub fn custom_field_json(
+    index: State<Arc<Index>>,
+    id: CustomFieldId,
+) -> Json<Option<CustomFieldConfig>> {
+    Json((*index).get_custom_field(id))
+}
+
+/// POST a new custom field, or rename an existing one with the same ID. Returns the ID of the field.
+#[post("/", format = "json", data = "<field>")]
+pub fn create_or_update_custom_field(
+    index: State<Arc<Index>>,
+    field: Json<CustomFieldConfig>,
+) -> Result<Json<CustomFieldId>, Box<dyn std::error::Error>> {
+    Ok(Json(
+        (*index).add_or_replace_custom_field(field.into_inner())?,
+    ))
+}
+
+/// DELETE a specific custom field. A job removes its values from all documents and the field afterwards.
+#[delete("/<id>")]
+pub fn remove_custom_field(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: CustomFieldId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if (*index).get_custom_field(id).is_none() {
+        return Err(format!("custom field {} does not exist", id).into());
+    }
+    send.lock()
+        .unwrap()
+        .send(JobType::RemoveCustomField { field: id })?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::index::{Index, JobType};
+use crate::metadata::document_type::{DocumentTypeConfig, DocumentTypeId};
+use crossbeam_channel::Sender;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// GET all document types as JSON, sorted by name
+#[get("/json")]
+pub fn document_types_json(index: State<Arc<Index>>) -> Json<Vec<DocumentTypeConfig>> {
+    Json((*index).get_document_types())
+}
+
+/// GET a specific document type as JSON config
+#[get("/<id>/json")]
+pub fn document_type_json(
+    index: State<Arc<Index>>,
+    id: DocumentTypeId,
+) -> Json<Option<DocumentTypeConfig>> {
+    Json((*index).get_document_type(id))
+}
+
+/// POST a new document type, or replace an existing one with the same ID.
+/// A job assigns the types to all documents again. Returns the ID of the type.
+#[post("/", format = "json", data = "<document_type>")]
+pub fn create_or_update_document_type(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    document_type: Json<DocumentTypeConfig>,
+) -> Result<Json<DocumentTypeId>, Box<dyn std::error::Error>> {
+    let id = (*index).add_or_replace_document_type(document_type.into_inner())?;
+    send.lock().unwrap().send(JobType::AssignDocumentTypes)?;
+    Ok(Json(id))
+}
+
+/// DELETE a specific document type. A job removes it from all documents and the type afterwards.
+#[delete("/<id>")]
+pub fn remove_document_type(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: DocumentTypeId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if (*index).get_document_type(id).is_none() {
+        return Err(format!("document type {} does not exist", id).into());
+    }
+    send.lock()
+        .unwrap()
+        .send(JobType::RemoveDocumentType { document_type: id })?;
+    Ok(())
+}
@@ -1,9 +1,17 @@
 use super::pages::{get_content_page, get_content_page_with_named_template};
-use crate::index::document_repository::{DocumentData, FilterOptions, SortOrder};
-use crate::index::{DocId, Index};
+use crate::index::document_repository::{
+    AmountFilter, AmountScope, Comparison, CustomFieldFilter, DocumentData, FilterOptions,
+    MetadataField, SortOrder,
+};
+use crate::index::file_repository::Rendition;
+use crate::index::{DocId, Index, JobType};
+use crate::metadata::correspondent::CorrespondentId;
+use crate::metadata::custom_field::{CustomFieldId, CustomFieldValue};
+use crate::metadata::document_type::DocumentTypeId;
+use crate::metadata::extraction::{parse_money, ExtractionPreview};
 use crate::metadata::tag::TagId;
-use crate::JobType;
 use rocket::http::ContentType;
+use rocket::request::LenientForm;
 use rocket::Data;
 use rocket_multipart_form_data::{
     MultipartFormData, MultipartFormDataField, MultipartFormDataOptions,
@@ -35,39 +43,128 @@ pub fn index_get_json_fail() -> response::status::BadRequest<&'static str> {
     response::status::BadRequest(Some("Missing parameter(s): offset, count"))
 }
 
-/// GET a list of (filtered) documents as JSON document
-#[get("/json?<offset>&<count>&<order>&<tag>&<query>", format = "json")]
-pub fn index_get_json(
-    index: State<Arc<Index>>,
-    offset: usize,
-    count: usize,
+/// Filter and sort order of a list of documents, given as query parameters
+#[derive(FromForm, Debug)]
+pub struct DocumentFilter {
+    /// 0: imported date, 1: inferred date, 3: document type, 4: value of `sort_field`, otherwise relevance
     order: Option<usize>,
+    sort_field: Option<CustomFieldId>,
+    /// Comma separated IDs of tags
     tag: Option<String>,
     query: Option<String>,
-) -> Result<Json<Vec<DocumentData>>, Box<dyn std::error::Error>> {
-    let order_parsed = match order {
-        Some(0) => SortOrder::ImportedDate,
-        Some(1) => SortOrder::InferredDate,
-        _ => SortOrder::NoOrder,
-    };
-    let mut tags = std::collections::HashSet::<TagId>::new();
-    let tag_str = tag.unwrap_or_default();
-    if !tag_str.is_empty() {
-        let tag_strs = tag_str.split(',');
-        for tag_s in tag_strs {
-            tags.insert(tag_s.parse::<TagId>()?);
+    correspondent: Option<CorrespondentId>,
+    document_type: Option<DocumentTypeId>,
+    /// Custom field, whose value is compared to `value` by `compare` (`eq`, `lt` or `gt`)
+    field: Option<CustomFieldId>,
+    compare: Option<String>,
+    value: Option<String>,
+    /// Amount like `100`, `1.234,56 €` or `USD 12.50`, which amounts of a document in the same currency
+    /// are compared to by `amount_compare`. The currency defaults to EUR.
+    amount: Option<String>,
+    amount_compare: Option<String>,
+    /// Compares the total of a document (`total`, the default) or any of its amounts (`any`)
+    amount_scope: Option<String>,
+}
+
+impl DocumentFilter {
+    fn into_filter_options(
+        self,
+        index: &Index,
+    ) -> Result<FilterOptions, Box<dyn std::error::Error>> {
+        let order_parsed = match (self.order, self.sort_field) {
+            (Some(0), _) => SortOrder::ImportedDate,
+            (Some(1), _) => SortOrder::InferredDate,
+            (Some(3), _) => SortOrder::DocumentType,
+            (Some(4), Some(field)) => SortOrder::CustomField(field),
+            _ => SortOrder::NoOrder,
+        };
+        let mut tags = std::collections::HashSet::<TagId>::new();
+        let tag_str = self.tag.unwrap_or_default();
+        if !tag_str.is_empty() {
+            let tag_strs = tag_str.split(',');
+            for tag_s in tag_strs {
+                tags.insert(tag_s.parse::<TagId>()?);
+            }
         }
+
+        let mut custom_fields = vec![];
+        if let Some(field) = self.field {
+            let config = index
+                .get_custom_field(field)
+                .ok_or_else(|| format!("custom field {} does not exist", field))?;
+            custom_fields.push(CustomFieldFilter {
+                field,
+                comparison: parse_comparison(self.compare.as_deref())?,
+                value: config.field_type.parse(&self.value.unwrap_or_default())?,
+            });
+        }
+
+        let amount = match self.amount {
+            Some(amount) => Some(AmountFilter {
+                scope: match self.amount_scope.as_deref() {
+                    None | Some("total") => AmountScope::Total,
+                    Some("any") => AmountScope::Any,
+                    Some(s) => return Err(format!("unknown amount scope `{}`", s).into()),
+                },
+                comparison: parse_comparison(self.amount_compare.as_deref())?,
+                amount: parse_money(&amount)
+                    .ok_or_else(|| format!("`{}` is not an amount", amount))?,
+            }),
+            None => None,
+        };
+
+        Ok(FilterOptions {
+            sort: order_parsed,
+            tags: tags.into_iter().map(|t| vec![t]).collect(),
+            query: self.query,
+            correspondent: self.correspondent,
+            document_type: self.document_type,
+            custom_fields,
+            amount,
+        })
     }
+}
 
-    let filter = FilterOptions {
-        sort: order_parsed,
-        tags: tags.into_iter().collect(),
-        query,
-    };
+fn parse_comparison(comparison: Option<&str>) -> Result<Comparison, Box<dyn std::error::Error>> {
+    match comparison {
+        None | Some("eq") => Ok(Comparison::Equals),
+        Some("lt") => Ok(Comparison::LessThan),
+        Some("gt") => Ok(Comparison::GreaterThan),
+        Some(c) => Err(format!("unknown comparison `{}`", c).into()),
+    }
+}
+
+/// GET a list of (filtered) documents as JSON document
+#[get("/json?<offset>&<count>&<filter..>", format = "json")]
+pub fn index_get_json(
+    index: State<Arc<Index>>,
+    offset: usize,
+    count: usize,
+    filter: LenientForm<DocumentFilter>,
+) -> Result<Json<Vec<DocumentData>>, Box<dyn std::error::Error>> {
+    let filter = filter.into_inner().into_filter_options(&index)?;
     let docs = (*index).get_sorted_documents(offset, count, filter)?;
     Ok(Json(docs))
 }
 
+/// GET the metadata of all (filtered) documents as CSV file
+#[get("/export?<filter..>")]
+pub fn export_csv<'r>(
+    index: State<Arc<Index>>,
+    filter: LenientForm<DocumentFilter>,
+) -> Result<response::Response<'r>, Box<dyn std::error::Error>> {
+    let filter = filter.into_inner().into_filter_options(&index)?;
+    let csv = (*index).export_csv(filter)?;
+    Ok(response::Response::build()
+        .header(ContentType::CSV)
+        .raw_header(
+            "Content-Disposition",
+            "attachment; filename=\"documents.csv\"",
+        )
+        .sized_body(std::io::Cursor::new(csv))
+        .finalize())
+}
+
 /// POST a new document
 #[post("/", data = "<data>")]
 pub fn upload(
@@ -126,6 +223,11 @@ pub fn document<'r>(index: State<Arc<Index>>, id: DocId) -> response::Result<'r>
     let mut map = HashMap::new();
     let extracted_obj = serde_json::to_string(&doc.extracted).unwrap();
     map.insert("extracted", extracted_obj.as_str());
+    // Embedded metadata is arbitrary text, which must not close the script tag
+    let file_metadata_obj = serde_json::to_string(&doc.file_metadata)
+        .unwrap()
+        .replace('<', "\\u003c");
+    map.insert("file_metadata", file_metadata_obj.as_str());
     map.insert("title", doc.title.as_str());
     map.insert("original_filename", doc.original_filename.as_str());
     let doc_date = doc
@@ -145,6 +247,12 @@ pub fn document<'r>(index: State<Arc<Index>>, id: DocId) -> response::Result<'r>
     let tags: Vec<String> = doc.tags.iter().map(|t| t.to_string()).collect();
     let tags_str = format!("[{}]", tags.join(","));
     map.insert("tags", &tags_str);
+    let provenance_obj = serde_json::to_string(&doc.provenance).unwrap();
+    map.insert("provenance", provenance_obj.as_str());
+    let correspondent = serde_json::to_string(&doc.correspondent).unwrap();
+    map.insert("correspondent", correspondent.as_str());
+    let document_type = serde_json::to_string(&doc.document_type).unwrap();
+    map.insert("document_type", document_type.as_str());
     get_content_page_with_named_template("show_document.html", &map)
 }
 
@@ -168,13 +276,19 @@ pub fn document_remove(
     Ok(())
 }
 
-/// GET the document file
-#[get("/<id>/download")]
+/// GET the document file.
+/// Returns the untouched original, or the searchable OCR version if `archive` is set
+#[get("/<id>/download?<archive>")]
 pub fn document_download(
     index: State<Arc<Index>>,
     id: DocId,
+    archive: Option<bool>,
 ) -> Result<rocket::response::NamedFile, Box<dyn std::error::Error>> {
-    let path = index.get_document_path(id)?;
+    let rendition = match archive.unwrap_or_default() {
+        true => Rendition::Archive,
+        false => Rendition::Original,
+    };
+    let path = index.get_rendition_path(id, rendition)?;
     Ok(rocket::response::NamedFile::open(&path)?)
 }
 
@@ -202,6 +316,9 @@ pub struct PatchDocumentData {
     pub language: Option<String>,
     // Extracted metadata
     pub extracted: Option<PatchExtractedData>,
+    /// Values of custom fields, `null` removes a value
+    #[serde(default)]
+    pub custom_fields: Option<HashMap<CustomFieldId, Option<CustomFieldValue>>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -214,6 +331,12 @@ pub struct PatchExtractedData {
     pub link: Option<Vec<String>>,
     #[serde(default)]
     pub iban: Option<Vec<String>>,
+    #[serde(default)]
+    pub bic: Option<Vec<String>>,
+    #[serde(default)]
+    pub vat_id: Option<Vec<String>>,
+    #[serde(default)]
+    pub tax_number: Option<Vec<String>>,
     #[serde(with = "ts_seconds_option")]
     pub doc_date: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -229,8 +352,17 @@ impl From<DocumentData> for PatchDocumentData {
                 email: Some(doc.extracted.email),
                 link: Some(doc.extracted.link),
                 iban: Some(doc.extracted.iban),
+                bic: Some(doc.extracted.bic),
+                vat_id: Some(doc.extracted.vat_id),
+                tax_number: Some(doc.extracted.tax_number),
                 doc_date: doc.extracted.doc_date,
             }),
+            custom_fields: Some(
+                doc.custom_fields
+                    .into_iter()
+                    .map(|(id, value)| (id, Some(value)))
+                    .collect(),
+            ),
         }
     }
 }
@@ -243,28 +375,64 @@ impl DocumentData {
 
         if let Some(language) = patch.language {
             self.language = Some(language);
+            self.set_manual_field(MetadataField::Language);
         }
 
         if let Some(tags) = patch.tags {
-            self.tags = tags;
+            let removed: Vec<TagId> = self
+                .tags
+                .iter()
+                .filter(|t| !tags.contains(t))
+                .copied()
+                .collect();
+            for tag in removed {
+                self.remove_manual_tag(tag);
+            }
+            for tag in tags {
+                if !self.tags.contains(&tag) {
+                    self.add_manual_tag(tag);
+                }
+            }
         }
 
         if let Some(extracted) = patch.extracted {
-            let my_extracted = &mut self.extracted;
             if let Some(phone) = extracted.phone {
-                my_extracted.phone = phone;
+                self.extracted.phone = phone;
+                self.set_manual_field(MetadataField::Phone);
             }
             if let Some(email) = extracted.email {
-                my_extracted.email = email;
+                self.extracted.email = email;
+                self.set_manual_field(MetadataField::Email);
             }
             if let Some(link) = extracted.link {
-                my_extracted.link = link;
+                self.extracted.link = link;
+                self.set_manual_field(MetadataField::Link);
             }
             if let Some(iban) = extracted.iban {
-                my_extracted.iban = iban;
+                self.extracted.iban = iban;
+                self.set_manual_field(MetadataField::Iban);
+            }
+            if let Some(bic) = extracted.bic {
+                self.extracted.bic = bic;
+                self.set_manual_field(MetadataField::Bic);
+            }
+            if let Some(vat_id) = extracted.vat_id {
+                self.extracted.vat_id = vat_id;
+                self.set_manual_field(MetadataField::VatId);
+            }
+            if let Some(tax_number) = extracted.tax_number {
+                self.extracted.tax_number = tax_number;
+                self.set_manual_field(MetadataField::TaxNumber);
             }
             if let Some(doc_date) = extracted.doc_date {
-                my_extracted.doc_date = Some(doc_date);
+                self.extracted.doc_date = Some(doc_date);
+                self.set_manual_field(MetadataField::DocDate);
+            }
+        }
+
+        if let Some(custom_fields) = patch.custom_fields {
+            for (field, value) in custom_fields {
+                self.set_manual_custom_field(field, value);
             }
         }
     }
@@ -274,12 +442,23 @@ impl DocumentData {
 #[patch("/<id>", format = "json", data = "<patch>")]
 pub fn document_patch(
     index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
     id: DocId,
     patch: Json<PatchDocumentData>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = index.get_document(id)?;
-    doc.patch(patch.into_inner());
+    let patch = patch.into_inner();
+    for (field, value) in patch.custom_fields.iter().flatten() {
+        if let Some(value) = value {
+            index.check_custom_field(*field, value)?;
+        }
+    }
+    let tags_changed = patch.tags.is_some();
+    doc.patch(patch);
     index.update_doc_metadata(doc)?;
+    if tags_changed {
+        queue_training(&index, &send)?;
+    }
     Ok(())
 }
 
@@ -289,27 +468,107 @@ pub fn document_patch(
 #[delete("/<id>/tags/<tagid>")]
 pub fn document_delete_tag(
     index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
     id: DocId,
     tagid: TagId,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = index.get_document(id)?;
-    doc.tags.retain(|t| t != &tagid);
+    doc.remove_manual_tag(tagid);
     index.update_doc_metadata(doc)?;
-    Ok(())
+    queue_training(&index, &send)
 }
 
 #[post("/<id>/tags/<tagid>")]
 pub fn document_add_tag(
     index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
     id: DocId,
     tagid: TagId,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = index.get_document(id)?;
+    doc.add_manual_tag(tagid);
+    index.update_doc_metadata(doc)?;
+    queue_training(&index, &send)
+}
+
+///////////////// Correspondent //////////////////////
 
-    if !doc.tags.contains(&tagid) {
-        doc.tags.push(tagid);
+/// PUT the correspondent of a document, it is kept when reprocessing
+#[put("/<id>/correspondent/<correspondent>")]
+pub fn document_set_correspondent(
+    index: State<Arc<Index>>,
+    id: DocId,
+    correspondent: CorrespondentId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if index.get_correspondent(correspondent).is_none() {
+        return Err(format!("correspondent {} does not exist", correspondent).into());
     }
+    let mut doc = index.get_document(id)?;
+    doc.set_manual_correspondent(Some(correspondent));
+    index.update_doc_metadata(doc)?;
+    Ok(())
+}
 
+/// DELETE the correspondent of a document, no correspondent is assigned when reprocessing
+#[delete("/<id>/correspondent")]
+pub fn document_delete_correspondent(
+    index: State<Arc<Index>>,
+    id: DocId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = index.get_document(id)?;
+    doc.set_manual_correspondent(None);
     index.update_doc_metadata(doc)?;
     Ok(())
 }
+
+///////////////// Document type //////////////////////
+
+/// PUT the type of a document, it is kept when reprocessing
+#[put("/<id>/type/<document_type>")]
+pub fn document_set_type(
+    index: State<Arc<Index>>,
+    id: DocId,
+    document_type: DocumentTypeId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if index.get_document_type(document_type).is_none() {
+        return Err(format!("document type {} does not exist", document_type).into());
+    }
+    let mut doc = index.get_document(id)?;
+    doc.set_manual_document_type(Some(document_type));
+    index.update_doc_metadata(doc)?;
+    Ok(())
+}
+
+/// DELETE the type of a document, no type is assigned when reprocessing
+#[delete("/<id>/type")]
+pub fn document_delete_type(
+    index: State<Arc<Index>>,
+    id: DocId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = index.get_document(id)?;
+    doc.set_manual_document_type(None);
+    index.update_doc_metadata(doc)?;
+    Ok(())
+}
+
+///////////////// Custom fields //////////////////////
+
+/// GET what each extraction rule extracts from the document, as JSON
+#[get("/<id>/extraction")]
+pub fn document_preview_extraction(
+    index: State<Arc<Index>>,
+    id: DocId,
+) -> Result<Json<Vec<ExtractionPreview>>, Box<dyn std::error::Error>> {
+    Ok(Json(index.preview_extraction(id)?))
+}
+
+/// Retrains the classifier in the background after tags were edited by hand
+fn queue_training(
+    index: &Index,
+    send: &Mutex<Sender<JobType>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if index.request_training() {
+        send.lock().unwrap().send(JobType::TrainClassifier)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use crate::index::{Index, JobType};
+use crate::metadata::extraction::{ExtractionRuleConfig, ExtractionRuleId};
+use crossbeam_channel::Sender;
+use rocket::State;
+use rocket_contrib::json::Json;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// GET all extraction rules as JSON, sorted by ID
+#[get("/json")]
+pub fn extraction_rules_json(index: State<Arc<Index>>) -> Json<Vec<ExtractionRuleConfig>> {
+    Json((*index).get_extraction_rules())
+}
+
+/// GET a specific extraction rule as JSON config
+#[get("/<id>/json")]
+pub fn extraction_rule_json(
+    index: State<Arc<Index>>,
+    id: ExtractionRuleId,
+) -> Json<Option<ExtractionRuleConfig>> {
+    Json((*index).get_extraction_rule(id))
+}
+
+/// POST a new extraction rule, or replace an existing one with the same ID.
+/// A job extracts the custom fields of all documents again. Returns the ID of the rule.
+#[post("/", format = "json", data = "<rule>")]
+pub fn create_or_update_extraction_rule(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    rule: Json<ExtractionRuleConfig>,
+) -> Result<Json<ExtractionRuleId>, Box<dyn std::error::Error>> {
+    let id = (*index).add_or_replace_extraction_rule(rule.into_inner())?;
+    send.lock().unwrap().send(JobType::ExtractCustomFields)?;
+    Ok(Json(id))
+}
+
+/// DELETE a specific extraction rule. A job extracts the custom fields of all documents again.
+#[delete("/<id>")]
+pub fn remove_extraction_rule(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: ExtractionRuleId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    (*index).remove_extraction_rule(id)?;
+    send.lock().unwrap().send(JobType::ExtractCustomFields)?;
+    Ok(())
+}
@@ -1,6 +1,10 @@
 mod api;
 mod assets;
+mod correspondents;
+mod custom_fields;
+mod document_types;
 mod documents;
+mod extraction_rules;
 mod pages;
 mod tags;
 
@@ -30,6 +34,7 @@ impl Server {
                     documents::index_get,
                     documents::index_get_json_fail,
                     documents::index_get_json,
+                    documents::export_csv,
                     documents::upload,
                     documents::document,
                     documents::document_json,
@@ -39,6 +44,11 @@ impl Server {
                     documents::document_delete_tag,
                     documents::document_add_tag,
                     documents::document_patch,
+                    documents::document_set_correspondent,
+                    documents::document_delete_correspondent,
+                    documents::document_set_type,
+                    documents::document_delete_type,
+                    documents::document_preview_extraction,
                     // documents::document_edit,
                     //documents::document,
                 ],
@@ -49,12 +59,59 @@ impl Server {
                     tags::tag_json,
                     tags::tags_json,
                     tags::remove_tag,
+                    tags::merge_tag,
+                    tags::rename_tag,
                     tags::tags,
                     tags::edit_tag,
                     tags::create_or_update_tag,
+                    tags::preview_tag,
+                ],
+            )
+            .mount(
+                "/correspondents",
+                routes![
+                    correspondents::correspondents_json,
+                    correspondents::correspondent_json,
+                    correspondents::create_or_update_correspondent,
+                    correspondents::remove_correspondent,
+                ],
+            )
+            .mount(
+                "/document_types",
+                routes![
+                    document_types::document_types_json,
+                    document_types::document_type_json,
+                    document_types::create_or_update_document_type,
+                    document_types::remove_document_type,
+                ],
+            )
+            .mount(
+                "/custom_fields",
+                routes![
+                    custom_fields::custom_fields_json,
+                    custom_fields::custom_field_json,
+                    custom_fields::create_or_update_custom_field,
+                    custom_fields::remove_custom_field,
+                ],
+            )
+            .mount(
+                "/extraction_rules",
+                routes![
+                    extraction_rules::extraction_rules_json,
+                    extraction_rules::extraction_rule_json,
+                    extraction_rules::create_or_update_extraction_rule,
+                    extraction_rules::remove_extraction_rule,
+                ],
+            )
+            .mount(
+                "/api",
+                routes![
+                    api::job_status,
+                    api::job_reports,
+                    api::fsck,
+                    api::fsck_repair
                 ],
             )
-            .mount("/api", routes![api::job_status,])
             .mount(
                 "/thumbnails",
                 StaticFiles::from(cfg.data_dir.join("thumbnails")),
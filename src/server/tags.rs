@@ -1,9 +1,11 @@
 use super::pages::{get_content_page, get_content_page_with_named_template};
-use crate::index::Index;
-use crate::metadata::tag::{TagConfig, TagId};
+use crate::index::{Index, JobType, MatchPreview};
+use crate::metadata::tag::{TagConfig, TagId, TagNode};
+use crossbeam_channel::Sender;
 use rocket::State;
 use rocket_contrib::json::Json;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 use rocket::request::Form;
 use rocket::response;
@@ -12,6 +14,7 @@ use rocket::response::Redirect;
 use std::collections::HashMap;
 
 use crate::metadata::tag::MatcherConfig;
+use chrono::NaiveDate;
 
 /// GET a specific tag as JSON config
 #[get("/<id>/json")]
@@ -19,18 +22,66 @@ pub fn tag_json(index: State<Arc<Index>>, id: TagId) -> Json<Option<TagConfig>>
     Json((*index).get_tag(id))
 }
 
-/// DELETE a specific tag
+/// DELETE a specific tag. A job removes it from all documents and the tag afterwards.
 #[delete("/<id>")]
-pub fn remove_tag(index: State<Arc<Index>>, id: TagId) -> Result<(), crate::index::IndexError> {
-    (*index).remove_tag(id)?;
+pub fn remove_tag(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: TagId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if (*index).get_tag(id).is_none() {
+        return Err(format!("tag {} does not exist", id).into());
+    }
+    send.lock().unwrap().send(JobType::RemoveTag { tag: id })?;
     Ok(())
 }
 
-/// GET all tags as JSON config
+/// POST to merge a tag into another tag. A job moves all documents to tag `into` and removes the tag afterwards.
+#[post("/<id>/merge/<into>")]
+pub fn merge_tag(
+    index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
+    id: TagId,
+    into: TagId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    (*index).check_merge(id, into)?;
+    send.lock()
+        .unwrap()
+        .send(JobType::MergeTags { from: id, into })?;
+    Ok(())
+}
+
+/// PUT the new name of a tag
+#[put("/<id>/name", data = "<name>")]
+pub fn rename_tag(
+    index: State<Arc<Index>>,
+    id: TagId,
+    name: String,
+) -> Result<(), crate::index::IndexError> {
+    (*index).rename_tag(id, name.trim())
+}
+
+/// GET all tags as JSON tree, child tags are contained in `children`
 #[get("/json")]
-pub fn tags_json(index: State<Arc<Index>>) -> Json<Vec<TagConfig>> {
-    let tags = (*index).get_tags();
-    Json(tags)
+pub fn tags_json(index: State<Arc<Index>>) -> Json<Vec<TagNode>> {
+    Json((*index).get_tag_tree())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreviewRequest {
+    pub matcher: MatcherConfig,
+    /// Tag whose current assignment is compared to the matcher
+    pub tag: Option<TagId>,
+}
+
+/// POST an unsaved matcher and GET the documents it would match
+#[post("/preview", format = "json", data = "<request>")]
+pub fn preview_tag(
+    index: State<Arc<Index>>,
+    request: Json<PreviewRequest>,
+) -> Result<Json<Vec<MatchPreview>>, Box<dyn std::error::Error>> {
+    let request = request.into_inner();
+    Ok(Json(index.preview_matcher(&request.matcher, request.tag)?))
 }
 
 /// GET the tags page
@@ -53,6 +104,7 @@ pub fn edit_tag<'r>(index: State<Arc<Index>>, id: TagId) -> response::Result<'r>
                     match_str: "".into(),
                     case_insensitive: false,
                 },
+                parent: None,
             };
             edit_tag_from_config(&t)
         }
@@ -66,9 +118,10 @@ pub fn edit_tag<'r>(index: State<Arc<Index>>, id: TagId) -> response::Result<'r>
 )]
 pub fn create_or_update_tag(
     index: State<Arc<Index>>,
+    send: State<Mutex<Sender<JobType>>>,
     tag_form: Form<TagForm>,
     id: TagId,
-) -> Result<Redirect, crate::index::IndexError> {
+) -> Result<Redirect, Box<dyn std::error::Error>> {
     let form = tag_form.0;
     let matcher = match form.matcher_type {
         1 => Some(MatcherConfig::FullMatcher {
@@ -83,6 +136,30 @@ pub fn create_or_update_tag(
             case_insensitive: form.any_matcher_case_insensitive,
         }),
         4 => Some(MatcherConfig::NoMatcher),
+        5 => Some(serde_json::from_str(&form.composite_matcher_json)?),
+        6 => Some(MatcherConfig::FieldMatcher {
+            field: serde_json::from_value(serde_json::Value::String(form.field_matcher_field))?,
+            condition: serde_json::from_value(serde_json::Value::String(
+                form.field_matcher_condition,
+            ))?,
+            value: form.field_matcher_value,
+            case_insensitive: form.field_matcher_case_insensitive,
+        }),
+        7 => Some(MatcherConfig::FileSizeMatcher {
+            min: form.file_size_matcher_min,
+            max: form.file_size_matcher_max,
+        }),
+        8 => Some(MatcherConfig::DateMatcher {
+            from: parse_form_date(&form.date_matcher_from)?,
+            to: parse_form_date(&form.date_matcher_to)?,
+        }),
+        9 => Some(MatcherConfig::Auto {
+            threshold: form.auto_matcher_threshold,
+        }),
+        10 => Some(MatcherConfig::FuzzyMatcher {
+            match_str: form.fuzzy_matcher_match_str,
+            max_distance: form.fuzzy_matcher_max_distance,
+        }),
         x => {
             error!("Got unknown matcher type `{}`", x);
             None
@@ -92,9 +169,19 @@ pub fn create_or_update_tag(
         id,
         name: form.name,
         color: Some(form.color),
-        matcher: matcher.unwrap(),
+        matcher: matcher.ok_or("unknown matcher type")?,
+        parent: form.parent,
     };
-    (*index).add_or_replace_tag(tag)?;
+    let auto = tag.matcher.uses_classifier();
+    let id = (*index).add_or_replace_tag(tag)?;
+    // Apply the new matcher to all existing documents
+    let guard = send.lock().unwrap();
+    if auto {
+        // Training applies all automatic tags afterwards
+        guard.send(JobType::TrainClassifier)?;
+    } else {
+        guard.send(JobType::Retag { tag: id })?;
+    }
     Ok(Redirect::to(uri!("/tags", tags)))
 }
 
@@ -102,15 +189,46 @@ pub fn create_or_update_tag(
 pub struct TagForm {
     name: String,
     color: String,
+    parent: Option<TagId>,
     matcher_type: u64,
     full_matcher_match_str: String,
     full_matcher_case_insensitive: bool,
     any_matcher_match_str: String,
     any_matcher_case_insensitive: bool,
     regex_match_string: String,
+    composite_matcher_json: String,
+    field_matcher_field: String,
+    field_matcher_condition: String,
+    field_matcher_value: String,
+    field_matcher_case_insensitive: bool,
+    file_size_matcher_min: Option<u64>,
+    file_size_matcher_max: Option<u64>,
+    date_matcher_from: String,
+    date_matcher_to: String,
+    auto_matcher_threshold: f64,
+    fuzzy_matcher_match_str: String,
+    fuzzy_matcher_max_distance: usize,
+}
+
+/// Parses the value of a date input, which is empty if no date is set
+fn parse_form_date(date: &str) -> Result<Option<NaiveDate>, chrono::ParseError> {
+    match date.trim() {
+        "" => Ok(None),
+        d => NaiveDate::parse_from_str(d, "%Y-%m-%d").map(Some),
+    }
 }
 
 fn edit_tag_from_config<'r>(tag: &TagConfig) -> response::Result<'r> {
+    let composite_json;
+    let field;
+    let condition;
+    let size_min;
+    let size_max;
+    let date_from;
+    let date_to;
+    let threshold;
+    let max_distance;
+    let parent = tag.parent.map(|p| p.to_string()).unwrap_or_default();
     let mut map: HashMap<&'static str, &str> = HashMap::new();
     map.insert("name", &tag.name);
     map.insert("color", tag.color.as_deref().unwrap_or("default string"));
@@ -120,6 +238,19 @@ fn edit_tag_from_config<'r>(tag: &TagConfig) -> response::Result<'r> {
     map.insert("any_match_str", "");
     map.insert("any_checked", "");
     map.insert("checked", "");
+    map.insert("composite_json", "");
+    map.insert("field", "original_filename");
+    map.insert("condition", "equals");
+    map.insert("field_value", "");
+    map.insert("field_checked", "");
+    map.insert("size_min", "");
+    map.insert("size_max", "");
+    map.insert("date_from", "");
+    map.insert("date_to", "");
+    map.insert("threshold", "0.9");
+    map.insert("fuzzy_match_str", "");
+    map.insert("max_distance", "1");
+    map.insert("parent", &parent);
     match &tag.matcher {
         MatcherConfig::FullMatcher {
             match_str,
@@ -147,7 +278,59 @@ fn edit_tag_from_config<'r>(tag: &TagConfig) -> response::Result<'r> {
                 map.insert("any_checked", "checked");
             }
         }
-        MatcherConfig::NoMatcher => {}
+        MatcherConfig::FuzzyMatcher {
+            match_str,
+            max_distance: d,
+        } => {
+            map.insert("type", "10");
+            map.insert("fuzzy_match_str", match_str);
+            max_distance = d.to_string();
+            map.insert("max_distance", &max_distance);
+        }
+        MatcherConfig::NoMatcher => {
+            map.insert("type", "4");
+        }
+        MatcherConfig::And { .. } | MatcherConfig::Or { .. } | MatcherConfig::Not { .. } => {
+            map.insert("type", "5");
+            composite_json = serde_json::to_string_pretty(&tag.matcher).unwrap_or_default();
+            map.insert("composite_json", &composite_json);
+        }
+        MatcherConfig::FieldMatcher {
+            field: f,
+            condition: c,
+            value,
+            case_insensitive,
+        } => {
+            map.insert("type", "6");
+            field = serde_json::to_value(f).unwrap_or_default();
+            condition = serde_json::to_value(c).unwrap_or_default();
+            map.insert("field", field.as_str().unwrap_or_default());
+            map.insert("condition", condition.as_str().unwrap_or_default());
+            map.insert("field_value", value);
+
+            if *case_insensitive {
+                map.insert("field_checked", "checked");
+            }
+        }
+        MatcherConfig::FileSizeMatcher { min, max } => {
+            map.insert("type", "7");
+            size_min = min.map(|m| m.to_string()).unwrap_or_default();
+            size_max = max.map(|m| m.to_string()).unwrap_or_default();
+            map.insert("size_min", &size_min);
+            map.insert("size_max", &size_max);
+        }
+        MatcherConfig::DateMatcher { from, to } => {
+            map.insert("type", "8");
+            date_from = from.map(|d| d.to_string()).unwrap_or_default();
+            date_to = to.map(|d| d.to_string()).unwrap_or_default();
+            map.insert("date_from", &date_from);
+            map.insert("date_to", &date_to);
+        }
+        MatcherConfig::Auto { threshold: t } => {
+            map.insert("type", "9");
+            threshold = t.to_string();
+            map.insert("threshold", &threshold);
+        }
     };
     get_content_page_with_named_template("edit_tag.html", &map)
 }
@@ -88,18 +88,15 @@ function deactivateDoc(event) {
   event.target.addClass("shadow-sm");
 }
 
-function nextHandler(pageIndex){
+// Filter and sort order of the current page as query parameters
+function filterParams(){
   let searchParams = new URLSearchParams(window.location.search)
   var paramObj = {};
-  let limit = 10;
-  paramObj.count = limit;
-  paramObj.offset = pageIndex*limit;
-  if (searchParams.has('order')) {
-    paramObj.order = searchParams.get('order')
-  }
-  if (searchParams.has('query')) {
-    paramObj.query = searchParams.get('query')
-  }
+  ['order', 'query', 'correspondent', 'document_type', 'sort_field', 'field', 'compare', 'value', 'total', 'total_compare'].forEach(function (name) {
+    if (searchParams.has(name)) {
+      paramObj[name] = searchParams.get(name)
+    }
+  });
   let tags =[];
   let tmp = sessionStorage.getItem("filterTags");
   if(tmp && tmp != null){
@@ -107,6 +104,18 @@ function nextHandler(pageIndex){
   }
 
   paramObj.tag = tags.join();
+  return paramObj;
+}
+
+function exportDocuments(){
+  window.location = "/documents/export?" + $.param(filterParams());
+}
+
+function nextHandler(pageIndex){
+  var paramObj = filterParams();
+  let limit = 10;
+  paramObj.count = limit;
+  paramObj.offset = pageIndex*limit;
 
   return fetch("/documents/json?"+$.param(paramObj))
   .then(response => response.json())
@@ -127,7 +136,7 @@ function nextHandler(pageIndex){
         var seconds = docData.imported_date;
       }
       var yearSep = null
-      if (!searchParams.has('query')) {
+      if (!searchParams.has('query') && order !== "3" && order !== "4") {
         yearSep = addDocYear(seconds);
       }
       
@@ -260,7 +269,7 @@ function toggleMultiSelect(elem) {
 
     // Fill with fresh tags from server
     $.get("/tags/json").done(function (data) {
-      data.forEach(function (tag) {
+      flattenTags(data).forEach(function (tag) {
         var item = $("<div class=\"btn-group\"><button class=\"btn btn-sm btn-add-tag\" type=\"button\"></button><button class=\"btn btn-sm btn-danger btn-del-tag\" type=\"button\">X</button></div>");
         var add_button = item.find(".btn-add-tag")
         var del_button = item.find(".btn-del-tag");
@@ -394,7 +403,7 @@ function removeDocument(id){
   tagMap = new Map()
   $.get("/tags/json")
     .done(function (data) {
-      data.forEach(function (tag) {
+      flattenTags(data).forEach(function (tag) {
         tagMap.set(tag.id, tag);
       });
 
@@ -410,6 +419,15 @@ function removeDocument(id){
     });
 
 
+    $.get("/custom_fields/json").done(function (fields) {
+      fields.forEach(function (field) {
+        let item = $("<a class=\"dropdown-item\"></a>");
+        item.attr("href", "?order=4&sort_field=" + field.id);
+        item.text(field.name);
+        $("#sortMenu").append(item);
+      });
+    });
+
     $('#uploadButton').on('click', function () {
       var fileList = $('#customFile').prop("files");
       for (var i = 0; i < fileList.length; i++) {
@@ -460,6 +478,11 @@ function updateStatusWindow(){
         alert.removeClass("alert-primary");
         alert.addClass("alert-success");
         alert.find("#alertText").text("Finished jobs. Refresh for new content.");
+        $.get("/api/job/reports").done(function (reports) {
+          if (reports.length > 0) {
+            alert.find("#alertText").text("Finished jobs. " + reports[0].summary + ". Refresh for new content.");
+          }
+        });
       }
     }else{
         var text = "Processing " + (data.Busy.queue+1) + " documents. "
@@ -1,22 +1,132 @@
-
 $('#matcherType').on('change', function() {
-  if(this.value == "1"){
-    $( "#fullMatcher" ).show();
-    $( "#anyMatcher" ).hide();
-    $( "#Regex" ).hide();
-  }else if(this. value == "2"){
-    $( "#fullMatcher" ).hide();
-    $( "#anyMatcher" ).hide();
-    $( "#Regex" ).show();
-  }else if(this. value == "3"){
-    $( "#fullMatcher" ).hide();
-    $( "#anyMatcher" ).show();
-    $( "#Regex" ).hide();
-  }else if(this. value == "4"){
-    $( "#fullMatcher" ).hide();
-    $( "#anyMatcher" ).hide();
-    $( "#Regex" ).hide();
-  }
+  $( "#fullMatcher" ).toggle(this.value == "1");
+  $( "#Regex" ).toggle(this.value == "2");
+  $( "#anyMatcher" ).toggle(this.value == "3");
+  $( "#compositeMatcher" ).toggle(this.value == "5");
+  $( "#fieldMatcher" ).toggle(this.value == "6");
+  $( "#fileSizeMatcher" ).toggle(this.value == "7");
+  $( "#dateMatcher" ).toggle(this.value == "8");
+  $( "#autoMatcher" ).toggle(this.value == "9");
+  $( "#fuzzyMatcher" ).toggle(this.value == "10");
+});
+
+// Select the stored values
+$('#fieldMatcherField').val($('#fieldMatcherField').attr('value'));
+$('#fieldMatcherCondition').val($('#fieldMatcherCondition').attr('value'));
+$('#matcherType').val($('#matcherType').attr('value') || "1");
+$('#matcherType').trigger("change");
+
+// Offer all other tags as parent
+$.get("/tags/json").done(function (data) {
+  let tagId = parseInt(window.location.pathname.split("/").pop());
+  flattenTags(data).forEach(function (tag) {
+    if (tag.id != tagId) {
+      $('#tagParent').append($("<option>").val(tag.id).text(tag.name));
+    }
+  });
+  $('#tagParent').val($('#tagParent').attr('value'));
 });
 
-$('#matcherType').trigger("change");
\ No newline at end of file
+function optionalValue(selector, parse) {
+  let value = $(selector).val();
+  return value === "" ? null : parse(value);
+}
+
+function currentMatcher() {
+  switch ($('#matcherType').val()) {
+    case "1":
+      return {
+        "FullMatcher": {
+          "match_str": $('#fullMatcherMatchStr').val(),
+          "case_insensitive": $('#fullMatcherCaseInsensitive').is(':checked'),
+        }
+      };
+    case "2":
+      return { "RegexMatcher": { "match_str": $('#regexMatchStr').val() } };
+    case "3":
+      return {
+        "AnyMatcher": {
+          "match_str": $('#anyMatcherMatchStr').val(),
+          "case_insensitive": $('#anyMatcherCaseInsensitive').is(':checked'),
+        }
+      };
+    case "5":
+      return JSON.parse($('#compositeMatcherJson').val());
+    case "6":
+      return {
+        "FieldMatcher": {
+          "field": $('#fieldMatcherField').val(),
+          "condition": $('#fieldMatcherCondition').val(),
+          "value": $('#fieldMatcherValue').val(),
+          "case_insensitive": $('#fieldMatcherCaseInsensitive').is(':checked'),
+        }
+      };
+    case "7":
+      return {
+        "FileSizeMatcher": {
+          "min": optionalValue('#fileSizeMatcherMin', parseInt),
+          "max": optionalValue('#fileSizeMatcherMax', parseInt),
+        }
+      };
+    case "8":
+      return {
+        "DateMatcher": {
+          "from": optionalValue('#dateMatcherFrom', d => d),
+          "to": optionalValue('#dateMatcherTo', d => d),
+        }
+      };
+    case "10":
+      return {
+        "FuzzyMatcher": {
+          "match_str": $('#fuzzyMatcherMatchStr').val(),
+          "max_distance": parseInt($('#fuzzyMatcherMaxDistance').val()) || 0,
+        }
+      };
+    case "9":
+      return { "Auto": { "threshold": parseFloat($('#autoMatcherThreshold').val()) } };
+    default:
+      return "NoMatcher";
+  }
+}
+
+function escapeHtml(text) {
+  return $("<div>").text(text).html();
+}
+
+$('#previewButton').on('click', function () {
+  let tagId = parseInt(window.location.pathname.split("/").pop());
+  let matcher;
+  try {
+    matcher = currentMatcher();
+  } catch (e) {
+    $('#previewHeader').text("Invalid matcher: " + e.message);
+    $('#previewTable > tbody').empty();
+    $('#preview').show();
+    return;
+  }
+  let request = { "matcher": matcher, "tag": tagId > 0 ? tagId : null };
+  $.ajax({
+    url: '/tags/preview',
+    type: 'POST',
+    contentType: 'application/json',
+    data: JSON.stringify(request),
+    success: function (previews) {
+      let body = $('#previewTable > tbody');
+      body.empty();
+      let symbols = { "Added": "+ added", "Removed": "- removed", "Unchanged": "unchanged" };
+      previews.forEach(function (preview) {
+        let change = preview.manual ? "set manually" : symbols[preview.change];
+        let snippets = preview.snippets.map(escapeHtml).join("<br>");
+        body.append(`<tr><td>${change}</td><td><a href="/documents/${preview.id}">${escapeHtml(preview.title)}</a></td><td>${snippets}</td></tr>`);
+      });
+      let matches = previews.filter(p => p.matches).length;
+      $('#previewHeader').text(matches + " matching documents");
+      $('#preview').show();
+    },
+    error: function (xhr) {
+      $('#previewHeader').text("Invalid matcher");
+      $('#previewTable > tbody').empty();
+      $('#preview').show();
+    },
+  });
+});
@@ -81,13 +81,19 @@ function initButtons(docId) {
 
 }
 
-function createTagButton(docId, tagMap, tagId, clickFunc) {
+function createTagButton(docId, tagMap, tagId, manual, clickFunc) {
   var tag = tagMap.get(tagId);
   if (!tag) {
     return "";
   }
   var btn = $("<a href=\"/tags/" + tagId + "\" type=\"button\" class=\"btn btn-secondary btn-sm tag-btn mb-2\" style=\"border-color: transparent;\"></a>");
   btn.text(tag.name);
+  if (manual) {
+    btn.attr("title", "Assigned manually");
+    btn.append(" <span data-feather=\"user\"></span>");
+  } else {
+    btn.attr("title", "Assigned automatically");
+  }
   btn.css("background-color", tag.color);
   btn.css("color", isDark(tag.color) ? "var(--light)" : "var(--dark)")
   btn.addClass("tag-" + tag.id);
@@ -144,15 +150,16 @@ function createAddButton(docId, tagMap) {
   return div;
 }
 
-function createTagButtons(docId, tags) {
+function createTagButtons(docId, tags, provenance) {
   $.get("/tags/json")
     .done(function (data) {
       let tagMap = new Map()
-      data.forEach(function (tag) {
+      flattenTags(data).forEach(function (tag) {
         tagMap.set(tag.id, tag);
       });
       tags.forEach(function (tag) {
-        $("#tags").append(createTagButton(docId, tagMap, tag, function () { }));
+        let manual = provenance.manual_tags.includes(tag);
+        $("#tags").append(createTagButton(docId, tagMap, tag, manual, function () { }));
         tagMap.delete(tag);
       });
       $("#tags").append(createAddButton(docId, tagMap));
@@ -173,6 +180,22 @@ $('#datepicker').on('changeDate', function () {
 
 
 function initExtracted(extracted) {
+  // Other dates found in the document, which can be picked as its date
+  if (extracted.date_candidates && extracted.date_candidates.length > 1) {
+    $('#dateCandidates').append($("<h6 class=\"dropdown-header px-0\">Dates in this document</h6>"));
+    extracted.date_candidates.forEach(function (candidate) {
+      let item = $("<button class=\"dropdown-item px-0\" type=\"button\"></button>");
+      item.append($("<strong></strong>").text(displayDate(candidate.date)));
+      item.append($("<small class=\"d-block text-muted text-truncate\"></small>").text(candidate.snippet));
+      item.on("click", function () {
+        $('#docDateInput').val(candidate.date);
+        $('#docDate').text(displayDate(candidate.date));
+        updateDate();
+      });
+      $('#dateCandidates').append(item);
+    });
+  }
+
   if (extracted.link.length > 0) {
     var i = 0;
     extracted.link.forEach(function (link) {
@@ -227,4 +250,143 @@ function initExtracted(extracted) {
     $('#metaHeader').show();
     $('#iban-div').show();
   }
+
+  // Bank and tax identifiers
+  [["bic", "#bic"], ["vat_id", "#vatId"], ["tax_number", "#taxNumber"]].forEach(function ([type, id]) {
+    let values = extracted[type] || [];
+    values.forEach(function (value, i) {
+      let elt = $("<tr><td><i><span class=\"text-muted\" data-feather=\"x\"></span></i></td></tr>");
+      elt.find("td").prepend(document.createTextNode(value));
+      elt.find("i").on("click", removeExtracted.bind(null, values, type, i));
+      $(id + 'Table > tbody:last-child').append(elt);
+    });
+    if (values.length > 0) {
+      $('#metaHeader').show();
+      $(id + '-div').show();
+    }
+  });
+
+  if (extracted.amounts && extracted.amounts.length > 0) {
+    const display = function (money) {
+      return (money.cents / 100).toFixed(2) + " " + money.currency;
+    };
+    const total = extracted.total ? display(extracted.total) : null;
+    extracted.amounts.forEach(function (money) {
+      let cell = $("<td></td>").text(display(money));
+      if (display(money) == total) {
+        cell.addClass("font-weight-bold").attr("title", "Likely total");
+      }
+      $('#amountsTable > tbody:last-child').append($("<tr></tr>").append(cell));
+    });
+    $('#metaHeader').show();
+    $('#amounts-div').show();
+  }
+}
+
+// Shows the metadata embedded in the file, e.g. the author of a PDF
+function initFileMetadata(metadata) {
+  const entries = [
+    ["Title", metadata.title],
+    ["Author", metadata.author],
+    ["Subject", metadata.subject],
+    ["Keywords", metadata.keywords.join(", ")],
+    ["Created", metadata.creation_date ? displayDateTime(metadata.creation_date) : null],
+    ["Pages", metadata.page_count],
+    ["PDF version", metadata.pdf_version],
+  ];
+  entries.forEach(function ([name, value]) {
+    if (value === null || value === undefined || value === "") {
+      return;
+    }
+    $('#fileMetadata').append($("<dt class=\"col-sm-4\"></dt>").text(name));
+    $('#fileMetadata').append($("<dd class=\"col-sm-8\"></dd>").text(value));
+    $('#fileMetadata-div').show();
+  });
+}
+
+// Marks values which were set by a user and are kept when reprocessing
+function initProvenance(provenance) {
+  const fields = {
+    "language": "#lang",
+    "doc_date": "#docDate",
+    "link": "#urlTable th",
+    "email": "#emailsTable th",
+    "phone": "#phoneTable th",
+    "iban": "#ibanTable th",
+    "bic": "#bicTable th",
+    "vat_id": "#vatIdTable th",
+    "tax_number": "#taxNumberTable th",
+  };
+  provenance.manual_fields.forEach(function (field) {
+    let elt = $(fields[field]);
+    elt.attr("title", "Set manually");
+    elt.append(" <small class=\"text-muted\"><span data-feather=\"user\"></span></small>");
+  });
+  feather.replace();
+}
+
+// Shows which rules assigned the automatic tags and the text they matched
+function initEvidence(docId) {
+  Promise.all([
+    fetch(`/documents/${docId}/json`).then(response => response.json()),
+    $.get("/tags/json"),
+  ]).then(([doc, tags]) => {
+    let tagMap = new Map();
+    flattenTags(tags).forEach(function (tag) {
+      tagMap.set(tag.id, tag);
+    });
+    doc.evidence.forEach(function (evidence) {
+      let tag = tagMap.get(evidence.tag);
+      if (!tag || !doc.tags.includes(evidence.tag)) {
+        return;
+      }
+      let row = $("<tr><td></td><td></td><td></td></tr>");
+      row.children().eq(0).text(tag.name);
+      row.children().eq(1).text(evidence.rule);
+      evidence.snippets.forEach(function (snippet) {
+        row.children().eq(2).append($("<div class=\"small\"></div>").text(snippet));
+      });
+      $('#evidenceTable > tbody:last-child').append(row);
+      $('#evidenceHeader').show();
+      $('#evidenceTable').show();
+    });
+  });
+}
+
+// Fills the correspondent selection, changes are kept when reprocessing
+function initCorrespondent(docId, correspondent) {
+  $.get("/correspondents/json").done(function (data) {
+    let select = $("#correspondent");
+    select.append($("<option value=\"\">-</option>"));
+    data.forEach(function (c) {
+      select.append($("<option></option>").attr("value", c.id).text(c.name));
+    });
+    select.val(correspondent === null ? "" : correspondent);
+    select.on("change", function () {
+      let value = select.val();
+      $.ajax({
+        url: "/documents/" + docId + "/correspondent" + (value === "" ? "" : "/" + value),
+        type: value === "" ? 'DELETE' : 'PUT',
+      });
+    });
+  });
+}
+
+// Fills the selection of the document type, changes are kept when reprocessing
+function initDocumentType(docId, documentType) {
+  $.get("/document_types/json").done(function (data) {
+    let select = $("#documentType");
+    select.append($("<option value=\"\">-</option>"));
+    data.forEach(function (t) {
+      select.append($("<option></option>").attr("value", t.id).text(t.name));
+    });
+    select.val(documentType === null ? "" : documentType);
+    select.on("change", function () {
+      let value = select.val();
+      $.ajax({
+        url: "/documents/" + docId + "/type" + (value === "" ? "" : "/" + value),
+        type: value === "" ? 'DELETE' : 'PUT',
+      });
+    });
+  });
 }
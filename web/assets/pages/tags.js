@@ -3,7 +3,7 @@
 
   $.get("/tags/json")
   .done(function( data ) {
-    $.each(data, function () {
+    $.each(flattenTags(data), function () {
       if (this.deactivated) {
         return;
       }